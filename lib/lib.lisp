@@ -1,7 +1,5 @@
 (define eq? =)
 
-(define (abs x) (if (< x 0) (- 0 x) x))
-
 (define (foldl proc init list)
   (if list
       (foldl proc
@@ -56,23 +54,6 @@
   `((lambda ,(map car defs) ,@body)
     ,@(map cadr defs)))
 
-(define +
-  (let ((old+ +))
-    (lambda xs (foldl old+ 0 xs))))
-
-(define -
-  (let ((old- -))
-    (lambda xs (foldl old- (car xs) (cdr xs)))))
-
-(define *
-  (let ((old* *))
-    (lambda xs (foldl old* (car xs) (cdr xs)))))
-
-(define /
-  (let ((old/ /))
-    (lambda xs (foldl old/ (car xs) (cdr xs)))))
-
-
 (define (last x)
   (if (pair? x)
       (if (pair? (cdr x))
@@ -0,0 +1,81 @@
+//! Evaluation can be limited in how much memory it allocates, so that code which conses forever, like an untrusted
+//! script, stops with an [`ErrorKind::AllocationLimitExceeded`] error instead of using up the memory of the process.
+//!
+//! The count is approximate: it is the size of the atoms returned by builtins which nothing else refers to yet,
+//! like a list made by `cons` or a string made by `string-append`, added up from when the limit was set. A single
+//! builtin call can still allocate more than the limit before evaluation stops. Like the fuel, the limit is kept
+//! per thread.
+
+use std::cell::Cell;
+
+use crate::atom::{Atom, Rc};
+use crate::error::{ErrorKind, LispError, Result};
+use crate::lisp_error;
+
+thread_local! {
+    static LIMIT: Cell<Option<usize>> = const { Cell::new(None) };
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Run `f` allowed to allocate `limit` bytes, or without limit if it is `None`.
+///
+/// The limit and count of the current thread are restored afterwards, so that this can be nested.
+pub fn with<T>(limit: Option<usize>, f: impl FnOnce() -> T) -> T {
+    let previous_limit = LIMIT.with(|cell| cell.replace(limit));
+    let previous_allocated = ALLOCATED.with(|cell| cell.replace(0));
+    let result = f();
+    LIMIT.with(|cell| cell.set(previous_limit));
+    ALLOCATED.with(|cell| cell.set(previous_allocated));
+    result
+}
+
+/// Count the memory of `atom`, which a builtin returned, returning an [`ErrorKind::AllocationLimitExceeded`]
+/// error if it goes over the limit.
+///
+/// # Errors
+/// If evaluation is limited and allocated more than its limit, return an error.
+pub fn record(atom: &Rc<Atom>) -> Result<()> {
+    let Some(limit) = LIMIT.with(Cell::get) else {
+        return Ok(());
+    };
+    let allocated = ALLOCATED.with(|cell| {
+        let allocated = cell.get().saturating_add(fresh_size(atom));
+        cell.set(allocated);
+        allocated
+    });
+    if allocated > limit {
+        Err(lisp_error!(
+            AllocationLimitExceeded,
+            "Allocation limit of {limit} bytes exceeded"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// The approximate size in bytes of the atoms in `atom` which are only referred to once, so were just made.
+fn fresh_size(atom: &Rc<Atom>) -> usize {
+    // an atom in its reference-counted allocation, with the strong and weak counts
+    let base = std::mem::size_of::<Atom>() + 2 * std::mem::size_of::<usize>();
+    let mut size = 0;
+    let mut stack = vec![atom];
+    while let Some(atom) = stack.pop() {
+        if Rc::strong_count(atom) > 1 {
+            continue;
+        }
+        size += base;
+        match atom.as_ref() {
+            Atom::String(s) | Atom::Symbol(s) | Atom::Keyword(s) => size += s.len(),
+            Atom::Pair(car, cdr) => stack.extend([car, cdr]),
+            Atom::Map(map) => stack.extend(map.keys().chain(map.values())),
+            _ => {}
+        }
+    }
+    size
+}
+
+/// Whether an error comes from an evaluation which allocated more than its limit.
+#[must_use]
+pub fn is_exceeded(error: &LispError) -> bool {
+    error.kind() == ErrorKind::AllocationLimitExceeded
+}
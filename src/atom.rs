@@ -1,12 +1,192 @@
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
 
-use color_eyre::eyre::eyre;
-use color_eyre::Result;
+#[cfg(feature = "diagnostics")]
+use ariadne::Fmt;
 
 use crate::env::Env;
+use crate::error::Result;
+use crate::lisp_error;
 
+/// Conversion between atoms and data formats.
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub mod convert;
 /// Evalutation happens here.
 pub mod eval;
+/// Conversion between atoms and Rust types.
+mod rust_conversions;
+/// Serialization of atoms with serde.
+#[cfg(feature = "serde")]
+mod serialization;
+
+/// Reference-counted pointer used for atoms and environments.
+///
+/// With the `arc` feature, this is [`std::sync::Arc`], so that atoms and environments can be sent to and shared
+/// between threads.
+#[cfg(not(feature = "arc"))]
+pub type Rc<T> = std::rc::Rc<T>;
+/// Reference-counted pointer used for atoms and environments.
+///
+/// With the `arc` feature, this is [`std::sync::Arc`], so that atoms and environments can be sent to and shared
+/// between threads.
+#[cfg(feature = "arc")]
+pub type Rc<T> = std::sync::Arc<T>;
+
+/// Weak version of [`Rc`].
+#[cfg(not(feature = "arc"))]
+pub type Weak<T> = std::rc::Weak<T>;
+/// Weak version of [`Rc`].
+#[cfg(feature = "arc")]
+pub type Weak<T> = std::sync::Weak<T>;
+
+/// Rust function implementing a builtin, which is called with the list of its evaluated arguments.
+///
+/// Any closure with the right signature is one, so that it can capture state, like a counter or a handle to a
+/// database of the application embedding lwhlisp. With the `arc` feature, it must also be [`Send`] and [`Sync`].
+#[cfg(not(feature = "arc"))]
+pub trait NativeFn: Fn(Rc<Atom>) -> Result<Rc<Atom>> {}
+#[cfg(not(feature = "arc"))]
+impl<F: Fn(Rc<Atom>) -> Result<Rc<Atom>>> NativeFn for F {}
+/// Rust function implementing a builtin, which is called with the list of its evaluated arguments.
+///
+/// Any closure with the right signature is one, so that it can capture state, like a counter or a handle to a
+/// database of the application embedding lwhlisp. With the `arc` feature, it must also be [`Send`] and [`Sync`].
+#[cfg(feature = "arc")]
+pub trait NativeFn: Fn(Rc<Atom>) -> Result<Rc<Atom>> + Send + Sync {}
+#[cfg(feature = "arc")]
+impl<F: Fn(Rc<Atom>) -> Result<Rc<Atom>> + Send + Sync> NativeFn for F {}
+
+/// Keys and values of an [`Atom::Map`], persistent so that an updated map shares most of it with the original.
+#[cfg(not(feature = "arc"))]
+pub type Map = im_rc::HashMap<Rc<Atom>, Rc<Atom>>;
+/// Keys and values of an [`Atom::Map`], persistent so that an updated map shares most of it with the original.
+#[cfg(feature = "arc")]
+pub type Map = im::HashMap<Rc<Atom>, Rc<Atom>>;
+
+/// Interior mutability for state shared between atoms, like the results cached by [`Atom::Memoized`].
+///
+/// With the `arc` feature, this is a [`std::sync::Mutex`] instead of a [`std::cell::RefCell`].
+#[cfg(not(feature = "arc"))]
+type Mutable<T> = std::cell::RefCell<T>;
+/// Interior mutability for state shared between atoms, like the results cached by [`Atom::Memoized`].
+///
+/// With the `arc` feature, this is a [`std::sync::Mutex`] instead of a [`std::cell::RefCell`].
+#[cfg(feature = "arc")]
+type Mutable<T> = std::sync::Mutex<T>;
+
+/// Results of a memoized function, by the arguments they were computed from.
+#[derive(Default)]
+pub struct MemoCache(Mutable<HashMap<MemoKey, Rc<Atom>>>);
+
+/// Arguments of a memoized function, whose numbers are compared by their bits, so that NaN finds its cached result
+/// even though it is not equal to itself.
+#[derive(Clone)]
+pub(crate) struct MemoKey(pub(crate) Rc<Atom>);
+
+impl PartialEq for MemoKey {
+    fn eq(&self, other: &Self) -> bool {
+        fn same(mut left: &Atom, mut right: &Atom) -> bool {
+            // follow the cdrs in a loop, like hashing
+            loop {
+                match (left, right) {
+                    (Atom::Number(l), Atom::Number(r)) => return l.to_bits() == r.to_bits(),
+                    (Atom::Pair(l_car, l_cdr), Atom::Pair(r_car, r_cdr)) => {
+                        if !same(l_car, r_car) {
+                            return false;
+                        }
+                        (left, right) = (l_cdr, r_cdr);
+                    }
+                    (l, r) => return l == r,
+                }
+            }
+        }
+        same(&self.0, &other.0)
+    }
+}
+
+impl Eq for MemoKey {}
+
+impl Hash for MemoKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // equal numbers have equal bits, except 0 and -0, which the hash of atoms already does not tell apart
+        self.0.hash(state);
+    }
+}
+
+impl MemoCache {
+    /// Get the cached result for the given arguments.
+    #[must_use]
+    pub fn get(&self, args: &Rc<Atom>) -> Option<Rc<Atom>> {
+        self.lock().get(&MemoKey(args.clone())).cloned()
+    }
+
+    /// Cache the result for the given arguments.
+    ///
+    /// Returns whether this is the first result in the cache.
+    pub fn insert(&self, args: Rc<Atom>, result: Rc<Atom>) -> bool {
+        let mut cache = self.lock();
+        let first = cache.is_empty();
+        cache.insert(MemoKey(args), result);
+        first
+    }
+
+    /// Forget all cached results.
+    pub fn clear(&self) {
+        // the results are only dropped once the lock is released, as dropping them can drop other caches
+        let entries = self.lock().drain().collect::<Vec<_>>();
+        drop(entries);
+    }
+
+    /// Get exclusive access to the cached results.
+    pub(crate) fn lock(&self) -> impl std::ops::DerefMut<Target = HashMap<MemoKey, Rc<Atom>>> + '_ {
+        #[cfg(not(feature = "arc"))]
+        let cache = self.0.borrow_mut();
+        // a panic while holding the lock can not leave the cache in an inconsistent state
+        #[cfg(feature = "arc")]
+        let cache = self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        cache
+    }
+}
+
+/// Integers in this range are allocated once per thread, and shared by all atoms representing them.
+const CACHED_INTEGERS: std::ops::RangeInclusive<i64> = -128..=1023;
+
+thread_local! {
+    static CACHED_ATOMS: CachedAtoms = CachedAtoms::new();
+}
+
+/// Atoms which are shared instead of being allocated every time they are needed.
+struct CachedAtoms {
+    nil: Rc<Atom>,
+    t: Rc<Atom>,
+    integers: Vec<Rc<Atom>>,
+}
+
+impl CachedAtoms {
+    fn new() -> Self {
+        Self {
+            nil: Rc::new(Atom::symbol("nil")),
+            t: Rc::new(Atom::symbol("t")),
+            #[allow(clippy::cast_precision_loss)]
+            integers: CACHED_INTEGERS
+                .map(|i| Rc::new(Atom::Number(i as f64)))
+                .collect(),
+        }
+    }
+
+    /// Get the shared atom for an integer, if it is small enough to be cached.
+    fn integer(num: i64) -> Option<Rc<Atom>> {
+        if !CACHED_INTEGERS.contains(&num) {
+            return None;
+        }
+        let index = usize::try_from(num - CACHED_INTEGERS.start()).ok()?;
+        CACHED_ATOMS.with(|cached| cached.integers.get(index).cloned())
+    }
+}
 
 /// A single value in lwhlisp.
 #[derive(Clone)]
@@ -14,53 +194,122 @@ pub enum Atom {
     /// Number
     Number(f64),
     /// String
-    String(String),
+    String(Rc<str>),
     /// Symbol
-    Symbol(String),
+    Symbol(Rc<str>),
+    /// Keyword, like `:foo`, which evaluates to itself.
+    ///
+    /// The name is stored without the leading colon.
+    Keyword(Rc<str>),
     /// Pair.
     ///
     /// This is also used to construct lists, using nested pairs.
     /// For example (pseudocode), `Pair(1, Pair(2, Pair(3, nil)))` would be interpreted as `(1 2 3)`.
     Pair(Rc<Atom>, Rc<Atom>),
+    /// Map from keys to values, read from a literal like `{:a 1 :b 2}`, which evaluates to itself.
+    Map(Map),
     /// Native Rust function.
     ///
     /// This is used to implement some base function that require direct access to the underlying data.
-    NativeFunc(fn(Rc<Atom>) -> Result<Rc<Atom>>),
+    NativeFunc(Rc<dyn NativeFn>),
     /// Closure
     Closure(Env, Rc<Atom>, Rc<Atom>),
     /// Macro
     Macro(Env, Rc<Atom>, Rc<Atom>),
+    /// Function whose results are cached by arguments, created by the `memoize` builtin.
+    Memoized(Rc<Atom>, Rc<MemoCache>),
+}
+
+impl Drop for Atom {
+    fn drop(&mut self) {
+        // Dropping a long list would recurse once per element and overflow the stack,
+        // so take apart the chain of uniquely owned cdrs in a loop instead.
+        let Atom::Pair(_, cdr) = self else {
+            return;
+        };
+        let Some(cdr) = Rc::get_mut(cdr) else {
+            return;
+        };
+        let mut next = std::mem::replace(cdr, Atom::Number(0.0));
+        while let Atom::Pair(_, cdr) = &mut next {
+            match Rc::get_mut(cdr) {
+                Some(cdr) => next = std::mem::replace(cdr, Atom::Number(0.0)),
+                None => break,
+            }
+        }
+    }
 }
 
 impl PartialEq for Atom {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Self::Number(l0), Self::Number(r0)) => l0 == r0,
-            (Self::Symbol(l0), Self::Symbol(r0)) | (Atom::String(l0), Atom::String(r0)) => l0 == r0,
+            (Self::Symbol(l0), Self::Symbol(r0))
+            | (Self::Keyword(l0), Self::Keyword(r0))
+            | (Atom::String(l0), Atom::String(r0)) => l0 == r0,
             (Self::Pair(l0, l1), Self::Pair(r0, r1)) => l0 == r0 && l1 == r1,
+            (Self::Map(l0), Self::Map(r0)) => l0 == r0,
             (Self::Closure(l0, l1, l2), Self::Closure(r0, r1, r2)) => {
                 l0 == r0 && l1 == r1 && l2 == r2
             }
             (Self::Macro(l0, l1, l2), Self::Macro(r0, r1, r2)) => l0 == r0 && l1 == r1 && l2 == r2,
+            (Self::Memoized(_, l1), Self::Memoized(_, r1)) => Rc::ptr_eq(l1, r1),
             _ => false,
         }
     }
 }
 
+impl Eq for Atom {}
+
+impl Hash for Atom {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // follow the cdrs in a loop, so that hashing a long list does not overflow the stack
+        let mut atom = self;
+        loop {
+            std::mem::discriminant(atom).hash(state);
+            match atom {
+                // adding zero turns -0 into 0, which compare equal
+                Atom::Number(x) => (x + 0.0).to_bits().hash(state),
+                Atom::String(s) | Atom::Symbol(s) | Atom::Keyword(s) => s.hash(state),
+                Atom::Pair(car, cdr) => {
+                    car.hash(state);
+                    atom = cdr;
+                    continue;
+                }
+                // the entries are in no particular order, so their hashes are combined in a way which does not
+                // depend on it
+                Atom::Map(map) => map
+                    .iter()
+                    .map(|entry| {
+                        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                        entry.hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .fold(0, u64::wrapping_add)
+                    .hash(state),
+                // functions are only hashed by their kind
+                Atom::NativeFunc(_) | Atom::Closure(_, _, _) | Atom::Macro(_, _, _) => {}
+                Atom::Memoized(_, cache) => Rc::as_ptr(cache).hash(state),
+            }
+            return;
+        }
+    }
+}
+
 impl Atom {
     fn fmt_pair_debug(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Atom::Pair(car, cdr) => {
-                write!(f, "{:?}", car)?;
+                write!(f, "{car:?}")?;
                 let mut atom = cdr;
                 while !atom.is_nil() {
                     match atom.as_ref() {
                         Atom::Pair(car, cdr) => {
-                            write!(f, " {:?}", car)?;
+                            write!(f, " {car:?}")?;
                             atom = cdr;
                         }
                         a => {
-                            write!(f, " . {:?}", a)?;
+                            write!(f, " . {a:?}")?;
                             break;
                         }
                     }
@@ -75,8 +324,9 @@ impl Atom {
 impl std::fmt::Debug for Atom {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Atom::Number(i) => write!(f, "{}", i),
-            Atom::Symbol(s) => write!(f, "{}", s),
+            Atom::Number(i) => write!(f, "{i}"),
+            Atom::Symbol(s) => write!(f, "{s}"),
+            Atom::Keyword(s) => write!(f, ":{s}"),
             Atom::Pair(_, _) => {
                 write!(f, "(")?;
                 self.fmt_pair_debug(f)?;
@@ -85,45 +335,191 @@ impl std::fmt::Debug for Atom {
             }
             Atom::NativeFunc(_) => write!(f, "#<BUILTIN>"),
             Atom::Closure(_env, args, expr) => {
-                write!(f, "(lambda {:?} ", args)?;
+                write!(f, "(lambda {args:?} ")?;
                 expr.fmt_pair_debug(f)?;
                 write!(f, ")")
             }
             Atom::Macro(_env, args, expr) => {
-                write!(f, "(defmacro {:?} ", args)?;
+                write!(f, "(defmacro {args:?} ")?;
                 expr.fmt_pair_debug(f)?;
                 write!(f, ")")
             }
-            Atom::String(s) => write!(f, "\"{}\"", s.escape_debug()),
+            Atom::Memoized(function, _) => write!(f, "(memoize {function:?})"),
+            Atom::Map(map) => {
+                // entries are sorted by how their keys are printed, so that equal maps are printed the same way
+                let mut entries = map
+                    .iter()
+                    .map(|(key, value)| (format!("{key:?}"), value))
+                    .collect::<Vec<_>>();
+                entries.sort_by(|(l, _), (r, _)| l.cmp(r));
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{key} {value:?}")?;
+                }
+                write!(f, "}}")
+            }
+            Atom::String(s) => write_string_literal(f, s),
         }
     }
 }
 
+/// Write `s` between double quotes, escaping characters so that parsing the result gives back `s`.
+fn write_string_literal(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    use std::fmt::Write as _;
+
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\0' => f.write_str("\\0")?,
+            // control characters are all below U+00A0
+            c if c.is_control() => write!(f, "\\x{:02x}", u32::from(c))?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
 impl std::fmt::Display for Atom {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.pretty_print(0))
+        write!(
+            f,
+            "{}",
+            self.pretty_print(0, 0, 0, Layout::default_ref(), false)
+        )
     }
 }
 
+/// Width of the lines lists are printed in by default.
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Number of spaces the elements of a list printed on several lines are indented by default.
+pub const DEFAULT_INDENT: usize = 3;
+
+/// Forms which keep their first arguments on the line of their head by default, with how many.
+pub const DEFAULT_BODY_FORMS: &[(&str, usize)] = &[
+    ("define", 1),
+    ("defmacro", 1),
+    ("if", 1),
+    ("lambda", 1),
+    ("let", 1),
+];
+
+/// How lists are laid out by [`Atom::pretty_print_with`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Maximum width of the lines
+    pub width: usize,
+    /// Number of spaces the elements of a list printed on several lines are indented by
+    pub indent: usize,
+    /// Forms which keep their first arguments on the line of their head when they are printed on several lines,
+    /// like `(define (f x)`, with how many. Their other arguments are their body, indented on their own lines
+    pub body_forms: HashMap<String, usize>,
+    /// Whether the other lists starting with a symbol keep their first argument on the line of their head, and
+    /// align the others with it, instead of indenting all of them on their own lines
+    pub align_arguments: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+            indent: DEFAULT_INDENT,
+            body_forms: DEFAULT_BODY_FORMS
+                .iter()
+                .map(|&(name, kept)| (name.to_string(), kept))
+                .collect(),
+            align_arguments: false,
+        }
+    }
+}
+
+impl Layout {
+    /// The default layout, which is only built once.
+    fn default_ref() -> &'static Self {
+        static DEFAULT: OnceLock<Layout> = OnceLock::new();
+        DEFAULT.get_or_init(Layout::default)
+    }
+}
+
+/// Terminal colors of the kinds of atoms, used by [`Atom::colored`] and by the REPL to highlight what is typed, so
+/// that an atom looks the same in both.
+#[cfg(feature = "diagnostics")]
+pub mod colors {
+    use ariadne::Color;
+
+    /// Color of strings.
+    pub const STRING: Color = Color::Green;
+    /// Color of numbers.
+    pub const NUMBER: Color = Color::Cyan;
+    /// Color of symbols, or in the REPL, of the symbols which are bound.
+    pub const SYMBOL: Color = Color::Yellow;
+    /// Color of keywords.
+    pub const KEYWORD: Color = Color::Magenta;
+}
+
 impl Atom {
-    fn pretty_print(&self, indent_level: usize) -> String {
+    /// Pretty-print the atom like [`Display`](std::fmt::Display) does, with the terminal [`colors`] of strings,
+    /// numbers, symbols and keywords.
+    #[cfg(feature = "diagnostics")]
+    #[must_use]
+    pub fn colored(&self) -> String {
+        self.pretty_print(0, 0, 0, Layout::default_ref(), true)
+    }
+
+    /// Pretty-print the atom like [`Display`](std::fmt::Display) does, with another layout than the default one.
+    ///
+    /// A list is printed on a single line if it fits, else with each element on its own line. Atoms which are
+    /// wider than the lines on their own, like long strings, still make longer lines.
+    ///
+    /// A list of one of the symbols `'`, `` ` ``, `,` or `,@`, which can not be read, and of another atom, is
+    /// printed as that shorthand followed by the atom, so that `'x` can be printed the way it was written rather
+    /// than as `(quote x)`.
+    #[must_use]
+    pub fn pretty_print_with(&self, layout: &Layout) -> String {
+        self.pretty_print(0, 0, 0, layout, false)
+    }
+
+    /// Pretty-print the atom, starting at `column` of a line and followed by `trailing` closing parentheses, with
+    /// the lines of its elements indented by `indent_level` levels.
+    fn pretty_print(
+        &self,
+        column: usize,
+        indent_level: usize,
+        trailing: usize,
+        layout: &Layout,
+        colored: bool,
+    ) -> String {
         use std::fmt::Write as _;
 
+        if let Some((shorthand, quoted)) = self.shorthand() {
+            let column = column + shorthand.len();
+            let quoted = quoted.pretty_print(column, indent_level, trailing, layout, colored);
+            return format!("{shorthand}{quoted}");
+        }
+        let available = layout.width.saturating_sub(column + trailing);
         match self {
-            Atom::Pair(car, cdr) if self.get_list_lenght_including_inner() <= 12 => {
+            Atom::Pair(car, cdr) if self.flat_width(available).is_some() => {
                 let mut s = String::new();
                 s.push('(');
 
-                write!(s, "{}", car).unwrap();
+                write!(s, "{}", car.pretty_print(0, 0, 0, layout, colored)).unwrap();
                 let mut atom = cdr;
                 while !atom.is_nil() {
                     match atom.as_ref() {
                         Atom::Pair(car, cdr) => {
-                            write!(s, " {}", car).unwrap();
+                            write!(s, " {}", car.pretty_print(0, 0, 0, layout, colored)).unwrap();
                             atom = cdr;
                         }
                         a => {
-                            write!(s, " . {}", a).unwrap();
+                            write!(s, " . {}", a.pretty_print(0, 0, 0, layout, colored)).unwrap();
                             break;
                         }
                     }
@@ -133,56 +529,156 @@ impl Atom {
                 s
             }
             Atom::Pair(car, cdr) => {
-                let mut s = String::new();
-                s.push('(');
+                Self::pretty_print_lines(car, cdr, column, indent_level, trailing, layout, colored)
+            }
+            Atom::Macro(_env, args, expr) => Atom::Pair(
+                Rc::new(Atom::symbol("defmacro")),
+                Rc::new(Atom::Pair(args.clone(), expr.clone())),
+            )
+            .pretty_print(column, indent_level, trailing, layout, colored),
+            #[cfg(feature = "diagnostics")]
+            a if colored => {
+                let color = match a {
+                    Atom::String(_) => colors::STRING,
+                    Atom::Number(_) => colors::NUMBER,
+                    Atom::Symbol(_) => colors::SYMBOL,
+                    Atom::Keyword(_) => colors::KEYWORD,
+                    _ => return format!("{a:?}"),
+                };
+                format!("{a:?}").fg(color).to_string()
+            }
+            a => {
+                format!("{a:?}")
+            }
+        }
+    }
 
-                write!(s, "{}", car.pretty_print(indent_level + 1)).unwrap();
-                let mut atom = cdr;
-                let mut print_on_first_line = false;
-                let mut first_arg = true;
-                if let Atom::Symbol(sym) = car.as_ref() {
-                    if matches!(sym.as_str(), "if" | "define" | "defmacro" | "lambda") {
-                        print_on_first_line = true;
+    /// Pretty-print the list with `car` and `cdr` over several lines, like [`Atom::pretty_print`].
+    ///
+    /// The arguments of a body form which are kept on the line of its head, or the first one of other lists if
+    /// arguments are aligned, are followed by the other arguments on their own lines.
+    fn pretty_print_lines(
+        car: &Rc<Atom>,
+        cdr: &Rc<Atom>,
+        column: usize,
+        indent_level: usize,
+        trailing: usize,
+        layout: &Layout,
+        colored: bool,
+    ) -> String {
+        use std::fmt::Write as _;
+
+        let mut s = String::new();
+        s.push('(');
+
+        let indent_level = indent_level + 1;
+        // the element printed last is followed by the closing parenthesis of this list too
+        let trailing_after = |cdr: &Atom| if cdr.is_nil() { trailing + 1 } else { 0 };
+        let head = car.pretty_print(
+            column + 1,
+            indent_level,
+            trailing_after(cdr),
+            layout,
+            colored,
+        );
+        write!(s, "{head}").unwrap();
+        let (kept, aligned) = match car.as_ref() {
+            Atom::Symbol(sym) => match layout.body_forms.get(&**sym) {
+                Some(&kept) => (kept, false),
+                None => (usize::from(layout.align_arguments), layout.align_arguments),
+            },
+            _ => (0, false),
+        };
+        // the arguments kept on the line of the head follow it, which is a symbol on a single line
+        let first_arg_column = column + 1 + car.flat_width(usize::MAX).unwrap_or_default() + 1;
+        let mut arg_column = first_arg_column;
+        let line_column = if aligned {
+            first_arg_column
+        } else {
+            indent_level * layout.indent
+        };
+        let mut atom = cdr;
+        let mut args = 0;
+        while !atom.is_nil() {
+            match atom.as_ref() {
+                Atom::Pair(car, cdr) => {
+                    let trailing = trailing_after(cdr);
+                    if args < kept {
+                        let arg =
+                            car.pretty_print(arg_column, indent_level, trailing, layout, colored);
+                        write!(s, " {arg}").unwrap();
+                        arg_column += car.flat_width(usize::MAX).unwrap_or_default() + 1;
+                    } else {
+                        writeln!(s).unwrap();
+                        s.push_str(&" ".repeat(line_column));
+                        let arg =
+                            car.pretty_print(line_column, indent_level, trailing, layout, colored);
+                        write!(s, "{arg}").unwrap();
                     }
+                    atom = cdr;
                 }
-                while !atom.is_nil() {
+                a => {
+                    write!(s, " . {}", a.pretty_print(0, 0, 0, layout, colored)).unwrap();
+                    break;
+                }
+            }
+            args += 1;
+        }
+
+        s.push(')');
+        s
+    }
+
+    /// The number of characters of the atom printed on a single line, or `None` if there are more than `limit`.
+    ///
+    /// This stops as soon as the limit is exceeded, so that pretty-printing a large list does not print each
+    /// sublist again at every level.
+    fn flat_width(&self, limit: usize) -> Option<usize> {
+        if let Some((shorthand, quoted)) = self.shorthand() {
+            let width =
+                shorthand.len() + quoted.flat_width(limit.saturating_sub(shorthand.len()))?;
+            return (width <= limit).then_some(width);
+        }
+        let width = match self {
+            Atom::Pair(car, cdr) => {
+                // the parentheses and the car
+                let mut width = 2 + car.flat_width(limit)?;
+                let mut atom = cdr;
+                while !atom.is_nil() && width <= limit {
                     match atom.as_ref() {
                         Atom::Pair(car, cdr) => {
-                            if print_on_first_line && first_arg {
-                                write!(s, " {}", car.pretty_print(indent_level + 1)).unwrap();
-                            } else {
-                                writeln!(s).unwrap();
-                                for _ in 0..=indent_level {
-                                    write!(s, "   ").unwrap();
-                                }
-                                write!(s, "{}", car.pretty_print(indent_level + 1)).unwrap();
-                            }
+                            width += 1 + car.flat_width(limit - width)?;
                             atom = cdr;
                         }
                         a => {
-                            write!(s, " . {}", a).unwrap();
+                            width += 3 + a.flat_width(limit - width)?;
                             break;
                         }
                     }
-                    first_arg = false;
                 }
-
-                s.push(')');
-                s
-            }
-            Atom::Macro(_env, args, expr) => {
-                let mut s = String::new();
-                let atom = Atom::Pair(
-                    Rc::new(Atom::symbol("defmacro")),
-                    Rc::new(Atom::Pair(args.clone(), expr.clone())),
-                );
-                write!(s, "{}", atom.pretty_print(indent_level)).unwrap();
-                s
-            }
-            a => {
-                format!("{:?}", a)
+                width
             }
-        }
+            Atom::Macro(_env, args, expr) => Atom::Pair(
+                Rc::new(Atom::symbol("defmacro")),
+                Rc::new(Atom::Pair(args.clone(), expr.clone())),
+            )
+            .flat_width(limit)?,
+            a => format!("{a:?}").chars().count(),
+        };
+        (width <= limit).then_some(width)
+    }
+
+    /// The shorthand and the quoted atom of a list like `(' x)`, which is pretty-printed as `'x`.
+    fn shorthand(&self) -> Option<(&str, &Rc<Atom>)> {
+        let Atom::Pair(car, cdr) = self else {
+            return None;
+        };
+        let (Atom::Symbol(shorthand), Atom::Pair(quoted, rest)) = (car.as_ref(), cdr.as_ref())
+        else {
+            return None;
+        };
+        (matches!(&**shorthand, "'" | "`" | "," | ",@") && rest.is_nil())
+            .then_some((&**shorthand, quoted))
     }
 }
 
@@ -217,7 +713,11 @@ impl Atom {
         } else {
             match self {
                 Atom::Pair(_, cdr) => Ok(cdr.clone()),
-                _ => Err(eyre!("Tried to get cdr of {:?}, which is invalid", self)),
+                _ => Err(lisp_error!(
+                    Value,
+                    "Tried to get cdr of {:?}, which is invalid",
+                    self
+                )),
             }
         }
     }
@@ -226,7 +726,7 @@ impl Atom {
     #[must_use]
     pub fn is_nil(&self) -> bool {
         match self {
-            Atom::Symbol(sym) => sym.as_str() == "nil",
+            Atom::Symbol(sym) => &**sym == "nil",
             _ => false,
         }
     }
@@ -253,16 +753,16 @@ impl Atom {
         matches!(expr.as_ref(), Atom::Pair(_, _))
     }
 
-    /// Creates a nil atom
+    /// Get the nil atom
     #[must_use]
-    pub fn nil() -> Atom {
-        Atom::symbol("nil")
+    pub fn nil() -> Rc<Atom> {
+        CACHED_ATOMS.with(|cached| cached.nil.clone())
     }
 
-    /// Creates a t atom
+    /// Get the t atom
     #[must_use]
-    pub fn t() -> Atom {
-        Atom::symbol("t")
+    pub fn t() -> Rc<Atom> {
+        CACHED_ATOMS.with(|cached| cached.t.clone())
     }
 
     /// Constructs a pair from two atoms
@@ -274,27 +774,43 @@ impl Atom {
     /// Constructs a symbol from a string
     #[must_use]
     pub fn symbol(sym: &str) -> Atom {
-        Atom::Symbol(String::from(sym))
+        Atom::Symbol(sym.into())
+    }
+
+    /// Constructs a keyword from its name, without the leading colon
+    #[must_use]
+    pub fn keyword(name: &str) -> Atom {
+        Atom::Keyword(name.into())
     }
 
     /// Constructs a number from a number
+    ///
+    /// Small integers are shared instead of being allocated again.
     #[must_use]
-    pub const fn number(num: f64) -> Atom {
-        Atom::Number(num)
+    pub fn number(num: f64) -> Rc<Atom> {
+        // -0.0 has no fractional part, but is displayed differently from 0
+        let negative_zero = num == 0.0 && num.is_sign_negative();
+        if num.fract() == 0.0 && !negative_zero {
+            #[allow(clippy::cast_possible_truncation)]
+            if let Some(cached) = CachedAtoms::integer(num as i64) {
+                return cached;
+            }
+        }
+        Rc::new(Atom::Number(num))
     }
 
     /// Constructs a number from an integer
     ///
     /// Warning: may cause precision loss if more than 52 bits are needed to represent the given integer
     #[must_use]
-    pub const fn integer(num: i64) -> Atom {
+    pub fn integer(num: i64) -> Rc<Atom> {
         #[allow(clippy::cast_precision_loss)]
-        Atom::Number(num as f64)
+        CachedAtoms::integer(num).unwrap_or_else(|| Rc::new(Atom::Number(num as f64)))
     }
 
     #[must_use]
     pub fn string(s: &str) -> Atom {
-        Atom::String(String::from(s))
+        Atom::String(s.into())
     }
 
     /// Get the value if the atom is a number.
@@ -304,7 +820,43 @@ impl Atom {
     pub fn get_number(&self) -> Result<f64> {
         match self {
             Atom::Number(x) => Ok(*x),
-            a => Err(eyre!("Expected a number, got {}", a)),
+            a => Err(lisp_error!(Type, "Expected a number, got {}", a)),
+        }
+    }
+
+    /// Get the value if the atom is a number without a fractional part.
+    ///
+    /// # Errors
+    /// If the given atom is not a number, if it is not an integer, or if it does not fit in an `i64`, return an
+    /// error.
+    pub fn get_integer(&self) -> Result<i64> {
+        let x = self.get_number()?;
+        if x.fract() != 0.0 {
+            return Err(lisp_error!(Type, "Expected an integer, got {}", x));
+        }
+        rust_conversions::as_integer(x)
+            .ok_or_else(|| lisp_error!(Value, "Expected an integer fitting in 64 bits, got {}", x))
+    }
+
+    /// Get the contents if the atom is a string.
+    ///
+    /// # Errors
+    /// If the given atom is not a string, return an error.
+    pub fn get_string(&self) -> Result<Rc<str>> {
+        match self {
+            Atom::String(s) => Ok(s.clone()),
+            a => Err(lisp_error!(Type, "Expected a string, got {}", a)),
+        }
+    }
+
+    /// Get the keys and values if the atom is a map, else return an error.
+    ///
+    /// # Errors
+    /// If the given atom is not a map, return an error.
+    pub fn get_map(&self) -> Result<Map> {
+        match self {
+            Atom::Map(map) => Ok(map.clone()),
+            a => Err(lisp_error!(Type, "Expected a map, got {}", a)),
         }
     }
 
@@ -312,10 +864,45 @@ impl Atom {
     ///
     /// # Errors
     /// If the given atom is not a symbol, return an error.
-    pub fn get_symbol_name(&self) -> Result<String> {
+    pub fn get_symbol_name(&self) -> Result<Rc<str>> {
         match self {
             Atom::Symbol(name) => Ok(name.clone()),
-            a => Err(eyre!("Expected a symbol, got {}", a)),
+            a => Err(lisp_error!(Type, "Expected a symbol, got {}", a)),
+        }
+    }
+
+    /// The name of the type of the atom, like `number` or `list`.
+    #[must_use]
+    pub fn type_name(self: &Rc<Self>) -> &'static str {
+        match self.as_ref() {
+            Atom::Number(_) => "number",
+            Atom::String(_) => "string",
+            Atom::Symbol(_) => "symbol",
+            Atom::Keyword(_) => "keyword",
+            Atom::Pair(_, _) if Atom::is_proper_list(self.clone()) => "list",
+            Atom::Pair(_, _) => "pair",
+            Atom::Map(_) => "map",
+            Atom::NativeFunc(_) => "builtin",
+            Atom::Closure(_, _, _) => "function",
+            Atom::Macro(_, _, _) => "macro",
+            Atom::Memoized(_, _) => "memoized function",
+        }
+    }
+
+    /// Get the documentation of a function or macro: a string at the start of its body, followed by at least one
+    /// other expression.
+    #[must_use]
+    pub fn docstring(&self) -> Option<Rc<str>> {
+        match self {
+            Atom::Closure(_, _, body) | Atom::Macro(_, _, body) => match body.as_ref() {
+                Atom::Pair(first, rest) if !rest.is_nil() => match first.as_ref() {
+                    Atom::String(doc) => Some(doc.clone()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Atom::Memoized(function, _) => function.docstring(),
+            _ => None,
         }
     }
 
@@ -332,17 +919,20 @@ impl Atom {
                         Atom::Symbol(_) => break,
                         Atom::Pair(car, cdr) => {
                             if !matches!(car.as_ref(), Atom::Symbol(_)) {
-                                return Err(eyre!("Expected all argument names to be symbols, but got {}, which is not a symbol", car))
+                                return Err(lisp_error!(Type, "Expected all argument names to be symbols, but got {}, which is not a symbol", car))
                             }
                             p = cdr.clone();
                         },
-                        a => return Err(eyre!("Expected all argument names to be symbols, but got {}, which is not a symbol", a))
+                        a => return Err(lisp_error!(Type, "Expected all argument names to be symbols, but got {}, which is not a symbol", a))
                     }
             }
 
             Ok((env, args, body))
         } else {
-            Err(eyre!("Expected body to be a proper list, got {body}"))
+            Err(lisp_error!(
+                Type,
+                "Expected body to be a proper list, got {body}"
+            ))
         }
     }
 
@@ -361,7 +951,7 @@ impl Atom {
     /// Returns an error if the given atom is not a closure.
     pub fn closure_add_env_binding(
         atom: &Rc<Atom>,
-        name: String,
+        name: Rc<str>,
         value: Rc<Atom>,
     ) -> Result<Rc<Atom>> {
         match atom.as_ref() {
@@ -371,7 +961,7 @@ impl Atom {
                 Ok(Rc::new(Atom::Closure(env, a.clone(), b.clone())))
             }
             a => {
-                Err(eyre!(format!("Tried to change the environment of a closure, but the provided atom was not a closure. Found {}", a)))
+                Err(lisp_error!(Type, "Tried to change the environment of a closure, but the provided atom was not a closure. Found {}", a))
             }
         }
     }
@@ -384,7 +974,7 @@ impl Atom {
 
     /// Create nil or t from a bool
     #[must_use]
-    pub fn bool(b: bool) -> Self {
+    pub fn bool(b: bool) -> Rc<Self> {
         if b {
             Atom::t()
         } else {
@@ -406,20 +996,47 @@ impl Atom {
         Ok(list.car())
     }
 
+    /// Collect the elements of a proper list into a vector.
+    ///
+    /// # Errors
+    /// Returns an error if the given atom is not a proper list.
+    pub fn list_to_vec(list: &Rc<Self>) -> Result<Vec<Rc<Self>>> {
+        let mut items = Vec::new();
+        let mut atom = list.clone();
+        while !atom.is_nil() {
+            match atom.as_ref() {
+                Atom::Pair(car, cdr) => {
+                    items.push(car.clone());
+                    atom = cdr.clone();
+                }
+                _ => return Err(lisp_error!(Type, "Expected a proper list, got {}", list)),
+            }
+        }
+        Ok(items)
+    }
+
+    /// Construct a proper list from the given atoms.
+    #[must_use]
+    pub fn list_from_vec(items: Vec<Rc<Self>>) -> Rc<Self> {
+        items
+            .into_iter()
+            .rev()
+            .fold(Atom::nil(), |cdr, car| Rc::new(Atom::Pair(car, cdr)))
+    }
+
     /// WARNING: This is probably broken, and should only be used when it doesn't matter much.
     /// Currently it is used in the pretty printer, where it is used to count the lenght of a list.
     #[must_use]
     pub fn into_vec(atom: Rc<Self>) -> Vec<Rc<Self>> {
-        match atom.as_ref() {
-            Atom::Pair(car, cdr) => {
-                let mut v = vec![car.clone()];
-                v.append(&mut Self::into_vec(cdr.clone()));
-                v
-            }
-            _ => {
-                vec![atom]
-            }
+        let mut v = Vec::new();
+        let mut atom = atom;
+        while let Atom::Pair(car, cdr) = atom.as_ref() {
+            v.push(car.clone());
+            let cdr = cdr.clone();
+            atom = cdr;
         }
+        v.push(atom);
+        v
     }
 
     /// Get length of list including sublists, or length of string if atom is a string.
@@ -438,12 +1055,17 @@ impl Atom {
     /// Get length of list including sublists.
     #[must_use]
     pub fn get_list_lenght_including_inner_without_symbol(&self) -> usize {
-        match self {
-            Atom::Pair(car, cdr) => {
-                car.get_list_lenght_including_inner_without_symbol()
-                    + cdr.get_list_lenght_including_inner_without_symbol()
+        let mut length = 0;
+        let mut stack = vec![self];
+        while let Some(atom) = stack.pop() {
+            match atom {
+                Atom::Pair(car, cdr) => {
+                    stack.push(cdr);
+                    stack.push(car);
+                }
+                _ => length += 1,
             }
-            _ => 1,
         }
+        length
     }
 }
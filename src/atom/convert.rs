@@ -0,0 +1,130 @@
+//! All formats share the same mapping:
+//! - tables and mappings become association lists of `(key . value)` pairs, with string keys
+//! - arrays and sequences become lists
+//! - strings and numbers become strings and numbers
+//! - true becomes t, and false and null become nil
+//!
+//! When converting back, a non-empty list where every element is a pair with a string as car
+//! is treated as a table, any other list as an array, and nil as false (or null where the format
+//! has it).
+
+use crate::error::{Context, Result};
+use crate::lisp_error;
+
+use super::rust_conversions::{alist_entries, alist_from_entries, as_integer};
+use super::{Atom, Rc};
+
+/// Convert a TOML value to an atom.
+#[cfg(feature = "toml")]
+#[must_use]
+pub fn from_toml(value: &toml::Value) -> Rc<Atom> {
+    match value {
+        toml::Value::String(s) => Rc::new(Atom::String(s.as_str().into())),
+        #[allow(clippy::cast_precision_loss)]
+        toml::Value::Integer(x) => Atom::number(*x as f64),
+        toml::Value::Float(x) => Atom::number(*x),
+        toml::Value::Boolean(b) => Atom::bool(*b),
+        toml::Value::Datetime(time) => Rc::new(Atom::String(time.to_string().into())),
+        toml::Value::Array(items) => Atom::list_from_vec(items.iter().map(from_toml).collect()),
+        toml::Value::Table(table) => alist_from_entries(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), from_toml(value))),
+        ),
+    }
+}
+
+/// Convert an atom to a TOML value.
+///
+/// # Errors
+/// If the atom contains something that can not be represented in TOML, return an error.
+#[cfg(feature = "toml")]
+pub fn to_toml(atom: &Rc<Atom>) -> Result<toml::Value> {
+    if let Some(entries) = alist_entries(atom) {
+        let mut table = toml::map::Map::new();
+        for (key, value) in entries {
+            let value = to_toml(&value).context(format!("As value of key {key:?}"))?;
+            table.insert(key, value);
+        }
+        return Ok(toml::Value::Table(table));
+    }
+    match atom.as_ref() {
+        Atom::Number(x) => Ok(as_integer(*x).map_or(toml::Value::Float(*x), toml::Value::Integer)),
+        Atom::Symbol(_) if atom.is_nil() => Ok(toml::Value::Boolean(false)),
+        Atom::Symbol(s) if &**s == "t" => Ok(toml::Value::Boolean(true)),
+        Atom::String(s) | Atom::Symbol(s) => Ok(toml::Value::String(s.to_string())),
+        Atom::Pair(_, _) => Ok(toml::Value::Array(
+            Atom::list_to_vec(atom)?
+                .iter()
+                .map(to_toml)
+                .collect::<Result<_>>()?,
+        )),
+        a => Err(lisp_error!(Value, "Can not represent {} in TOML", a)),
+    }
+}
+
+/// Convert a YAML value to an atom.
+///
+/// # Errors
+/// If a mapping has keys which are not strings or numbers, return an error.
+#[cfg(feature = "yaml")]
+pub fn from_yaml(value: &serde_yaml::Value) -> Result<Rc<Atom>> {
+    match value {
+        serde_yaml::Value::Null => Ok(Atom::nil()),
+        serde_yaml::Value::Bool(b) => Ok(Atom::bool(*b)),
+        serde_yaml::Value::Number(x) => x
+            .as_f64()
+            .map(Atom::number)
+            .ok_or_else(|| lisp_error!(Value, "Can not represent the number {} as an atom", x)),
+        serde_yaml::Value::String(s) => Ok(Rc::new(Atom::String(s.as_str().into()))),
+        serde_yaml::Value::Sequence(items) => Ok(Atom::list_from_vec(
+            items.iter().map(from_yaml).collect::<Result<_>>()?,
+        )),
+        serde_yaml::Value::Mapping(mapping) => {
+            let entries = mapping
+                .iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        serde_yaml::Value::String(key) => key.clone(),
+                        serde_yaml::Value::Number(key) => key.to_string(),
+                        key => return Err(lisp_error!(Value, "Unsupported mapping key {:?}", key)),
+                    };
+                    Ok((key, from_yaml(value)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(alist_from_entries(entries.into_iter()))
+        }
+        serde_yaml::Value::Tagged(tagged) => from_yaml(&tagged.value),
+    }
+}
+
+/// Convert an atom to a YAML value.
+///
+/// # Errors
+/// If the atom contains something that can not be represented in YAML, return an error.
+#[cfg(feature = "yaml")]
+pub fn to_yaml(atom: &Rc<Atom>) -> Result<serde_yaml::Value> {
+    if let Some(entries) = alist_entries(atom) {
+        let mut mapping = serde_yaml::Mapping::new();
+        for (key, value) in entries {
+            let value = to_yaml(&value).context(format!("As value of key {key:?}"))?;
+            mapping.insert(serde_yaml::Value::String(key), value);
+        }
+        return Ok(serde_yaml::Value::Mapping(mapping));
+    }
+    match atom.as_ref() {
+        Atom::Number(x) => {
+            Ok(as_integer(*x).map_or_else(|| serde_yaml::Value::from(*x), serde_yaml::Value::from))
+        }
+        Atom::Symbol(_) if atom.is_nil() => Ok(serde_yaml::Value::Null),
+        Atom::Symbol(s) if &**s == "t" => Ok(serde_yaml::Value::Bool(true)),
+        Atom::String(s) | Atom::Symbol(s) => Ok(serde_yaml::Value::String(s.to_string())),
+        Atom::Pair(_, _) => Ok(serde_yaml::Value::Sequence(
+            Atom::list_to_vec(atom)?
+                .iter()
+                .map(to_yaml)
+                .collect::<Result<_>>()?,
+        )),
+        a => Err(lisp_error!(Value, "Can not represent {} in YAML", a)),
+    }
+}
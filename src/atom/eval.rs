@@ -1,39 +1,56 @@
-use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use color_eyre::{
-    eyre::{eyre, Context},
-    Result,
-};
 use tracing::{debug, instrument};
 
-use super::Atom;
+use super::{Atom, Rc};
+use crate::allocation;
 use crate::env::Env;
+use crate::error::{Context, Result};
+use crate::fuel;
+use crate::gc;
+use crate::interrupt;
+use crate::lisp_error;
+use crate::module;
+use crate::observer;
+use crate::output;
+use crate::profile;
 
 impl Atom {
     /// Evaluate a single atom.
+    ///
+    /// # Errors
+    /// Returns an error if the evaluation fails, like when a symbol is unbound or a function gets the wrong
+    /// arguments, or if it is stopped, like by `exit` or an interrupt.
+    // taking the atom lets callers evaluate one they just made without keeping it around
+    #[allow(clippy::needless_pass_by_value)]
     #[instrument(skip(env))]
     pub fn eval(expr: Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
         match expr.as_ref() {
-            Atom::Number(_) | Atom::NativeFunc(_) | Atom::Closure(_, _, _) | Atom::String(_) => {
+            Atom::Number(_)
+            | Atom::NativeFunc(_)
+            | Atom::Closure(_, _, _)
+            | Atom::Memoized(_, _)
+            | Atom::Map(_)
+            | Atom::String(_)
+            | Atom::Keyword(_) => {
                 debug!("Primitive evaluates to itself");
                 Ok(expr.clone())
             }
             Atom::Symbol(symbol) => env.get(symbol),
-            Atom::Macro(_, _, _) => Err(eyre!("Attempt to evaluate macro {}", expr)),
+            Atom::Macro(_, _, _) => Err(lisp_error!(Syntax, "Attempt to evaluate macro {}", expr)),
             Atom::Pair(car, cdr) => list_evaluation(car, cdr, &expr, env),
         }
     }
 }
 
 fn eval_elements_in_list(x: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
-    Ok(Rc::new(Atom::Pair(
-        Atom::eval(x.car(), env)?,
-        if x.cdr().is_nil() {
-            x.cdr()
-        } else {
-            eval_elements_in_list(&x.cdr(), env)?
-        },
-    )))
+    let mut evaluated = Vec::new();
+    let mut atom = x.clone();
+    while !atom.is_nil() {
+        evaluated.push(Atom::eval(atom.car(), env)?);
+        atom = atom.cdr();
+    }
+    Ok(Atom::list_from_vec(evaluated))
 }
 
 fn list_evaluation(
@@ -41,34 +58,71 @@ fn list_evaluation(
     cdr: &Rc<Atom>,
     expr: &Rc<Atom>,
     env: &mut Env,
-) -> Result<Rc<Atom>, color_eyre::Report> {
+) -> Result<Rc<Atom>> {
+    observer::enter(expr);
+    let result = application(car, cdr, expr, env);
+    observer::exit(expr, &result);
+    result
+}
+
+fn application(car: &Rc<Atom>, cdr: &Rc<Atom>, expr: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
+    interrupt::check()?;
+    fuel::consume()?;
     if !Atom::is_proper_list(expr.clone()) {
-        return Err(eyre!("Attempted to evaluate improper list\n{}", expr));
+        return Err(lisp_error!(
+            Syntax,
+            "Attempted to evaluate improper list\n{}",
+            expr
+        ));
     }
-    let op = Atom::eval(car.clone(), env).context(format!(
-        "While evaluating first element of list for function application {:?}",
-        car,
-    ))?;
+    let op = Atom::eval(car.clone(), env).with_context(|| {
+        format!("While evaluating first element of list for function application {car:?}")
+    })?;
     let args = cdr;
 
     match &op.as_ref() {
-        Atom::Symbol(symbol) => try_evaluate_special_form(symbol, args, env).context(format!(
-            "While trying to evaluate special form {:?}",
-            symbol
-        )),
+        Atom::Symbol(symbol) => try_evaluate_special_form(symbol, args, env)
+            .with_context(|| format!("While trying to evaluate special form {symbol:?}")),
         Atom::NativeFunc(f) => {
             let evaled_args = eval_elements_in_list(&args.clone(), env)?;
-            f(evaled_args).context(format!("While evaluating builtin function {:?}", expr))
+            let _profile = profile::enter(car);
+            let result = f(evaled_args)
+                .with_context(|| format!("While evaluating builtin function {expr:?}"))?;
+            allocation::record(&result)?;
+            Ok(result)
         }
         Atom::Closure(function_env, original_arg_names, body) => {
+            let _profile = profile::enter(car);
             eval_closure(function_env, env, original_arg_names, args, body)
-                .context(format!("While evaluating closure\n{}", expr))
+                .with_context(|| format!("While evaluating closure\n{expr}"))
+        }
+        Atom::Memoized(function, cache) => {
+            let evaled_args = eval_elements_in_list(args, env)?;
+            let cached = cache.get(&evaled_args);
+            if let Some(result) = cached {
+                return Ok(result);
+            }
+            let quoted_args = quote_elements_in_list(&evaled_args);
+            // a memoized closure is called directly, so that it is profiled under the name of the memoized function
+            let result =
+                if let Atom::Closure(function_env, original_arg_names, body) = function.as_ref() {
+                    let _profile = profile::enter(car);
+                    eval_closure(function_env, env, original_arg_names, &quoted_args, body)
+                } else {
+                    Atom::eval(Rc::new(Atom::Pair(function.clone(), quoted_args)), env)
+                }
+                .with_context(|| format!("While evaluating memoized function\n{expr}"))?;
+            if cache.insert(evaled_args, result.clone()) {
+                gc::register(env, cache);
+            }
+            Ok(result)
         }
         Atom::Macro(function_env, original_arg_names, body) => {
             eval_macro(function_env, env, original_arg_names, args, body)
-                .context(format!("While evaluating macro\n{}", expr))
+                .with_context(|| format!("While evaluating macro\n{expr}"))
         }
-        a => Err(eyre!(
+        a => Err(lisp_error!(
+            Type,
             "Expected a function as first element of evaluated list, got\n{}",
             a
         )),
@@ -77,18 +131,18 @@ fn list_evaluation(
 
 fn eval_macro(
     function_env: &Env,
-    env: &mut Env,
+    env: &Env,
     original_arg_names: &Rc<Atom>,
     args: &Rc<Atom>,
     body: &Rc<Atom>,
-) -> Result<Rc<Atom>, color_eyre::Report> {
-    let mut func_env = Env::new(Some(Box::new(function_env.clone())));
-    func_env.add_furthest_parent(env.clone());
+) -> Result<Rc<Atom>> {
+    let mut func_env = Env::new_call_frame(function_env, env);
     let mut arg_names = Rc::new(original_arg_names.as_ref().clone());
     let mut args_working = Rc::new(args.as_ref().clone());
     while !arg_names.is_nil() {
         if args_working.is_nil() {
-            return Err(eyre!(
+            return Err(lisp_error!(
+                Arity,
                 "Too few arguments, expected {}, but got {}",
                 arg_names,
                 args
@@ -98,8 +152,8 @@ fn eval_macro(
         if let Atom::Symbol(sym) = arg_names.as_ref() {
             // final argument for variadic functions
 
-            func_env.set(sym.to_string(), args_working.clone());
-            args_working = Rc::new(Atom::nil());
+            func_env.set(sym.clone(), args_working.clone());
+            args_working = Atom::nil();
             break;
         } else {
             let arg = args_working.car();
@@ -111,19 +165,20 @@ fn eval_macro(
     if args_working.is_nil() {
         let mut body_working = Rc::new(body.as_ref().clone());
 
-        let mut result = Rc::new(Atom::nil());
+        let mut result = Atom::nil();
 
         while !body_working.is_nil() {
             let to_eval = body_working.car();
             result = Atom::eval(to_eval.clone(), &mut func_env)
-                .context(format!("While evaluating closure\n{}", to_eval))?;
+                .with_context(|| format!("While evaluating closure\n{to_eval}"))?;
             result = Atom::eval(result, &mut func_env)?;
             body_working = body_working.cdr();
         }
 
         Ok(result)
     } else {
-        Err(eyre!(
+        Err(lisp_error!(
+            Arity,
             "Too many arguments, expected {} but got {}",
             original_arg_names,
             args
@@ -137,14 +192,14 @@ fn eval_closure(
     original_arg_names: &Rc<Atom>,
     args: &Rc<Atom>,
     body: &Rc<Atom>,
-) -> Result<Rc<Atom>, color_eyre::Report> {
-    let mut func_env = Env::new(Some(Box::new(function_env.clone())));
-    func_env.add_furthest_parent(env.clone());
+) -> Result<Rc<Atom>> {
+    let mut func_env = Env::new_call_frame(function_env, env);
     let mut arg_names = Rc::new(original_arg_names.as_ref().clone());
     let mut args_working = Rc::new(args.as_ref().clone());
     while !arg_names.is_nil() {
         if args_working.is_nil() {
-            return Err(eyre!(
+            return Err(lisp_error!(
+                Arity,
                 "Too few arguments, expected {}, but got {}",
                 arg_names,
                 args
@@ -153,21 +208,10 @@ fn eval_closure(
 
         if let Atom::Symbol(sym) = arg_names.as_ref() {
             // final argument for variadic functions
-            // eval each arg
-            fn eval_args(x: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
-                Ok(Rc::new(Atom::Pair(
-                    Atom::eval(x.car(), env)?,
-                    if x.cdr().is_nil() {
-                        x.cdr()
-                    } else {
-                        eval_args(&x.cdr(), env)?
-                    },
-                )))
-            }
-            let evaled_args = eval_args(&args_working, env)?;
+            let evaled_args = eval_elements_in_list(&args_working, env)?;
 
-            func_env.set(sym.to_string(), evaled_args);
-            args_working = Rc::new(Atom::nil());
+            func_env.set(sym.clone(), evaled_args);
+            args_working = Atom::nil();
             break;
         } else {
             let arg = args_working.car();
@@ -180,18 +224,19 @@ fn eval_closure(
     if args_working.is_nil() {
         let mut body_working = Rc::new(body.as_ref().clone());
 
-        let mut result = Rc::new(Atom::nil());
+        let mut result = Atom::nil();
 
         while !body_working.is_nil() {
             let to_eval = body_working.car();
             result = Atom::eval(to_eval.clone(), &mut func_env)
-                .context(format!("While evaluating closure\n{}", to_eval))?;
+                .with_context(|| format!("While evaluating closure\n{to_eval}"))?;
             body_working = body_working.cdr();
         }
 
         Ok(result)
     } else {
-        Err(eyre!(
+        Err(lisp_error!(
+            Arity,
             "Too many arguments, expected {} but got {}",
             original_arg_names,
             args
@@ -199,46 +244,64 @@ fn eval_closure(
     }
 }
 
-fn try_evaluate_special_form(
-    symbol: &str,
-    args: &Rc<Atom>,
-    env: &mut Env,
-) -> Result<Rc<Atom>, color_eyre::Report> {
+fn try_evaluate_special_form(symbol: &str, args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
     match symbol {
-        "quote" => eval_special_form_quote(args).context(format!(
-            "While trying to evaluate special form quote with args\n{}",
-            args
-        )),
-        "define" => eval_special_form_define(args, env).context(format!(
-            "While trying to evaluate special form define with args\n{}",
-            args
-        )),
-        "defmacro" => eval_special_form_defmacro(args, env).context(format!(
-            "While trying to evaluate special form defmacro with args\n{}",
-            args
-        )),
-        "lambda" => eval_special_form_lambda(args, env).context(format!(
-            "While trying to evaluate special form lambda with args\n{}",
-            args
-        )),
-        "if" => eval_special_form_if(args, env).context(format!(
-            "While trying to evaluate special form if with args\n{}",
-            args
-        )),
-        "apply" => eval_special_form_apply(args, env).context(format!(
-            "While trying to evaluate special form apply with args\n{}",
-            args
-        )),
-        name => Err(eyre!(
+        "quote" => eval_special_form_quote(args).with_context(|| {
+            format!("While trying to evaluate special form quote with args\n{args}")
+        }),
+        "define" => eval_special_form_define(args, env).with_context(|| {
+            format!("While trying to evaluate special form define with args\n{args}")
+        }),
+        "defmacro" => eval_special_form_defmacro(args, env).with_context(|| {
+            format!("While trying to evaluate special form defmacro with args\n{args}")
+        }),
+        "lambda" => eval_special_form_lambda(args, env).with_context(|| {
+            format!("While trying to evaluate special form lambda with args\n{args}")
+        }),
+        "if" => eval_special_form_if(args, env)
+            .with_context(|| format!("While trying to evaluate special form if with args\n{args}")),
+        "apply" => eval_special_form_apply(args, env).with_context(|| {
+            format!("While trying to evaluate special form apply with args\n{args}")
+        }),
+        "bench" => eval_special_form_bench(args, env).with_context(|| {
+            format!("While trying to evaluate special form bench with args\n{args}")
+        }),
+        "gc" => eval_special_form_gc(args, env)
+            .with_context(|| format!("While trying to evaluate special form gc with args\n{args}")),
+        "apropos" => eval_special_form_apropos(args, env).with_context(|| {
+            format!("While trying to evaluate special form apropos with args\n{args}")
+        }),
+        "doc" => eval_special_form_doc(args, env).with_context(|| {
+            format!("While trying to evaluate special form doc with args\n{args}")
+        }),
+        "env" => eval_special_form_env(args, env).with_context(|| {
+            format!("While trying to evaluate special form env with args\n{args}")
+        }),
+        "require" => eval_special_form_require(args, env).with_context(|| {
+            format!("While trying to evaluate special form require with args\n{args}")
+        }),
+        "provide" => eval_special_form_provide(args, env).with_context(|| {
+            format!("While trying to evaluate special form provide with args\n{args}")
+        }),
+        "in-namespace" => eval_special_form_in_namespace(args, env).with_context(|| {
+            format!("While trying to evaluate special form in-namespace with args\n{args}")
+        }),
+        #[cfg(feature = "arc")]
+        "pmap" => eval_special_form_pmap(args, env).with_context(|| {
+            format!("While trying to evaluate special form pmap with args\n{args}")
+        }),
+        name => Err(lisp_error!(
+            Type,
             "Expected function, builtin function or special form, but got {}, which is a symbol",
             name
         )),
     }
 }
 
-fn eval_special_form_apply(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>, color_eyre::Report> {
+fn eval_special_form_apply(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
     if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-        Err(eyre!(
+        Err(lisp_error!(
+            Arity,
             "Special form apply expected exactly two arguments, got {}",
             args
         ))
@@ -246,35 +309,281 @@ fn eval_special_form_apply(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>, c
         let func = Atom::eval(args.car(), env)?;
         let args = Atom::eval(args.cdr().car(), env)?;
         if Atom::is_proper_list(args.clone()) {
-            let to_eval = Rc::new(Atom::Pair(func, quote_elements_in_list(&args)?));
+            let to_eval = Rc::new(Atom::Pair(func, quote_elements_in_list(&args)));
             Atom::eval(to_eval, env)
         } else {
-            Err(eyre!("Expected second argument to apply to be a proper list, but got {}, which is invalid", args))
+            Err(lisp_error!(Type, "Expected second argument to apply to be a proper list, but got {}, which is invalid", args))
         }
     }
 }
 
-fn quote_elements_in_list(x: &Rc<Atom>) -> Result<Rc<Atom>> {
-    Ok(Rc::new(Atom::Pair(
-        Rc::new(Atom::Pair(
-            Rc::new(Atom::symbol("quote")),
-            Rc::new(Atom::Pair(x.car(), Rc::new(Atom::symbol("nil")))),
+/// Evaluate `(gc)`, clearing the memoization caches which are unreachable from `env`.
+///
+/// Returns the number of caches that were cleared.
+fn eval_special_form_gc(args: &Rc<Atom>, env: &Env) -> Result<Rc<Atom>> {
+    if args.is_nil() {
+        Ok(Atom::integer(i64::try_from(gc::collect(env))?))
+    } else {
+        Err(lisp_error!(
+            Arity,
+            "Special form gc expected no arguments, got {}",
+            args
+        ))
+    }
+}
+
+/// Evaluate `(apropos part)`, giving the sorted list of bound symbols whose names contain the string `part`.
+fn eval_special_form_apropos(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
+    let items = Atom::list_to_vec(args)?;
+    let [part] = items.as_slice() else {
+        return Err(lisp_error!(
+            Arity,
+            "Special form apropos expected exactly one argument, got {}",
+            args
+        ));
+    };
+    let part = Atom::eval(part.clone(), env)?;
+    match part.as_ref() {
+        Atom::String(part) => Ok(Atom::list_from_vec(
+            env.apropos(part)
+                .into_iter()
+                .map(|name| Rc::new(Atom::Symbol(name)))
+                .collect(),
         )),
-        if x.cdr().is_nil() {
-            x.cdr()
-        } else {
-            quote_elements_in_list(&x.cdr())?
-        },
-    )))
+        _ => Err(lisp_error!(
+            Type,
+            "Expected the argument of apropos to be a string, got {}",
+            part
+        )),
+    }
+}
+
+/// Evaluate the special form `(env)`, giving the bindings names are looked up in where it is evaluated, like the
+/// parameters of the function it is called in and the definitions around it, as an association list sorted by name.
+/// A special form, so that it sees the environment it is evaluated in rather than that of a function.
+fn eval_special_form_env(args: &Rc<Atom>, env: &Env) -> Result<Rc<Atom>> {
+    if !args.is_nil() {
+        return Err(lisp_error!(
+            Arity,
+            "Special form env expected no arguments, got {}",
+            args
+        ));
+    }
+    Ok(Atom::list_from_vec(
+        env.visible_bindings()
+            .into_iter()
+            .map(|(name, value)| Rc::new(Atom::Pair(Rc::new(Atom::Symbol(name)), value)))
+            .collect(),
+    ))
+}
+
+/// Evaluate `(require 'name)`, binding the names the module `name` exports.
+fn eval_special_form_require(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
+    let items = Atom::list_to_vec(args)?;
+    let [name] = items.as_slice() else {
+        return Err(lisp_error!(
+            Arity,
+            "Special form require expected exactly one argument, got {}",
+            args
+        ));
+    };
+    let name = Atom::eval(name.clone(), env)?.get_symbol_name()?;
+    module::require(&name, env)
 }
 
-fn eval_special_form_if(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>, color_eyre::Report> {
+/// Evaluate `(provide 'name ...)`, adding the names to those the module being required exports.
+fn eval_special_form_provide(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
+    let names = eval_elements_in_list(args, env)?;
+    module::provide(&names, env)
+}
+
+/// Evaluate `(in-namespace name)`, making the definitions which follow in the namespace `name`, which is not
+/// evaluated, or outside of any namespace if it is `nil`.
+fn eval_special_form_in_namespace(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
+    let items = Atom::list_to_vec(args)?;
+    let [name] = items.as_slice() else {
+        return Err(lisp_error!(
+            Arity,
+            "Special form in-namespace expected exactly one argument, got {}",
+            args
+        ));
+    };
+    let namespace = name.get_symbol_name()?;
+    if namespace.contains(':') {
+        return Err(lisp_error!(
+            Value,
+            "Expected the name of a namespace, which can not contain :, got {}",
+            name
+        ));
+    }
+    env.set_namespace((!name.is_nil()).then_some(namespace));
+    Ok(name.clone())
+}
+
+/// Evaluate `(doc name)`, printing the documentation of what is bound to `name`, which is not evaluated.
+fn eval_special_form_doc(args: &Rc<Atom>, env: &Env) -> Result<Rc<Atom>> {
+    let items = Atom::list_to_vec(args)?;
+    let [name] = items.as_slice() else {
+        return Err(lisp_error!(
+            Arity,
+            "Special form doc expected exactly one argument, got {}",
+            args
+        ));
+    };
+    output::print(&format!(
+        "{}\n",
+        env.documentation(&name.get_symbol_name()?)?
+    ))?;
+    Ok(Atom::nil())
+}
+
+/// Number of timed runs when `bench` is not given `:iterations`.
+const BENCH_DEFAULT_ITERATIONS: usize = 100;
+
+/// Evaluate `(bench expr [:iterations n])`.
+///
+/// Runs `expr` `n` times after a few untimed warmup runs, prints the minimum, mean and maximum time of a run,
+/// and returns them as a list of milliseconds.
+fn eval_special_form_bench(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
+    let items = Atom::list_to_vec(args)?;
+    let (expr, iterations) = match items.as_slice() {
+        [expr] => (expr, BENCH_DEFAULT_ITERATIONS),
+        [expr, keyword, n] if matches!(keyword.as_ref(), Atom::Keyword(k) if &**k == "iterations") =>
+        {
+            let n = Atom::eval(n.clone(), env)?.get_number()?;
+            if n < 1.0 || n.fract() != 0.0 || n > f64::from(u32::MAX) {
+                return Err(lisp_error!(
+                    Value,
+                    "Expected the number of iterations to be a positive integer, got {}",
+                    n
+                ));
+            }
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            (expr, n as usize)
+        }
+        _ => {
+            return Err(lisp_error!(
+                Syntax,
+                "BENCH has the form (bench expr [:iterations n]), but got {}, which is invalid",
+                args
+            ))
+        }
+    };
+
+    let warmup = (iterations / 10).max(1);
+    for _ in 0..warmup {
+        Atom::eval(expr.clone(), env)?;
+    }
+
+    // the timings are summarized as they come, as there can be billions of them
+    let (mut min, mut max, mut total) = (Duration::MAX, Duration::ZERO, Duration::ZERO);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        Atom::eval(expr.clone(), env)?;
+        let elapsed = start.elapsed();
+        min = min.min(elapsed);
+        max = max.max(elapsed);
+        total = total.saturating_add(elapsed);
+    }
+
+    let as_millis = |d: Duration| d.as_secs_f64() * 1000.0;
+    let (min, max) = (as_millis(min), as_millis(max));
+    #[allow(clippy::cast_precision_loss)]
+    let mean = as_millis(total) / iterations as f64;
+    output::print(&format!("bench {expr}: {iterations} iterations, min {min:.3} ms, mean {mean:.3} ms, max {max:.3} ms\n"))?;
+
+    Ok(Atom::list_from_vec(vec![
+        Atom::number(min),
+        Atom::number(mean),
+        Atom::number(max),
+    ]))
+}
+
+/// Evaluate `(pmap function list)`.
+///
+/// The list is split in one chunk per available thread, and each element is passed to the function in its own
+/// child environment of `env`, so that calls on different threads do not see each other's definitions.
+#[cfg(feature = "arc")]
+fn eval_special_form_pmap(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
+    let items = Atom::list_to_vec(args)?;
+    let [function, list] = items.as_slice() else {
+        return Err(lisp_error!(
+            Syntax,
+            "PMAP has the form (pmap function list), but got {}, which is invalid",
+            args
+        ));
+    };
+    let function = Atom::eval(function.clone(), env)?;
+    let list = Atom::eval(list.clone(), env)?;
+    let items = Atom::list_to_vec(&list).context("While evaluating the list to map over")?;
+    if items.is_empty() {
+        return Ok(Atom::nil());
+    }
+
+    let threads = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+    let parent = Rc::new(env.clone());
+    // the workers stop when the evaluation running pmap is interrupted
+    let watched = interrupt::current().unwrap_or_default();
+    let chunks = std::thread::scope(|scope| {
+        // all workers have to be spawned before waiting for any of them
+        #[allow(clippy::needless_collect)]
+        let workers = items
+            .chunks(items.len().div_ceil(threads))
+            .map(|chunk| {
+                let (function, parent, watched) = (&function, &parent, &watched);
+                scope.spawn(move || {
+                    interrupt::watch(watched, || {
+                        chunk
+                            .iter()
+                            .map(|item| {
+                                let call = Atom::list_from_vec(vec![
+                                    function.clone(),
+                                    Atom::list_from_vec(vec![
+                                        Rc::new(Atom::symbol("quote")),
+                                        item.clone(),
+                                    ]),
+                                ]);
+                                Atom::eval(call, &mut Env::new(Some(parent.clone())))
+                            })
+                            .collect::<Result<Vec<_>>>()
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|_| Err(lisp_error!(Other, "A pmap worker thread panicked")))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    Ok(Atom::list_from_vec(chunks.into_iter().flatten().collect()))
+}
+
+fn quote_elements_in_list(x: &Rc<Atom>) -> Rc<Atom> {
+    let mut quoted = Vec::new();
+    let mut atom = x.clone();
+    while !atom.is_nil() {
+        quoted.push(Rc::new(Atom::Pair(
+            Rc::new(Atom::symbol("quote")),
+            Rc::new(Atom::Pair(atom.car(), Atom::nil())),
+        )));
+        atom = atom.cdr();
+    }
+    Atom::list_from_vec(quoted)
+}
+
+fn eval_special_form_if(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
     if args.is_nil()
         || args.cdr().is_nil()
         || args.cdr().cdr().is_nil()
         || !args.cdr().cdr().cdr().is_nil()
     {
-        Err(eyre!(
+        Err(lisp_error!(
+            Arity,
             "Special form if takes exactly 3 arguments, but got {}, which is invalid",
             args
         ))
@@ -288,12 +597,10 @@ fn eval_special_form_if(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>, colo
     }
 }
 
-fn eval_special_form_lambda(
-    args: &Rc<Atom>,
-    env: &mut Env,
-) -> Result<Rc<Atom>, color_eyre::Report> {
+fn eval_special_form_lambda(args: &Rc<Atom>, env: &Env) -> Result<Rc<Atom>> {
     if args.is_nil() || args.cdr().is_nil() {
-        Err(eyre!(
+        Err(lisp_error!(
+            Syntax,
             "LAMBDA has the form (lambda (arg ...) (body) ...), but got {}, which is invalid",
             args
         ))
@@ -302,12 +609,9 @@ fn eval_special_form_lambda(
     }
 }
 
-fn eval_special_form_defmacro(
-    args: &Rc<Atom>,
-    env: &mut Env,
-) -> Result<Rc<Atom>, color_eyre::Report> {
+fn eval_special_form_defmacro(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
     if args.is_nil() || args.cdr().is_nil() || !matches!(args.as_ref(), Atom::Pair(_, _)) {
-        Err(eyre!("DEFMACRO has the form (DEFMACRO (name arg ...) body ...), but got {}, which is invalid", args))
+        Err(lisp_error!(Syntax, "DEFMACRO has the form (DEFMACRO (name arg ...) body ...), but got {}, which is invalid", args))
     } else {
         let name = args.car().car();
         match name.as_ref() {
@@ -315,21 +619,19 @@ fn eval_special_form_defmacro(
                 let (macro_env, args, body) =
                     Atom::validate_closure_form(env.clone(), args.car().cdr(), args.cdr())?;
                 let makro = Rc::new(Atom::Macro(macro_env, args, body));
-                env.set(sym.to_string(), makro);
+                env.define(sym, makro);
                 Ok(name)
             }
-            a => Err(eyre!("Expected name to be a symbol, got {}", a)),
+            a => Err(lisp_error!(Type, "Expected name to be a symbol, got {}", a)),
         }
     }
 }
 
-fn eval_special_form_define(
-    args: &Rc<Atom>,
-    env: &mut Env,
-) -> Result<Rc<Atom>, color_eyre::Report> {
+fn eval_special_form_define(args: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
     // exactly two arguments
     if args.is_nil() || args.cdr().is_nil() {
-        Err(eyre!(
+        Err(lisp_error!(
+            Syntax,
             "DEFINE has either the form (DEFINE name value) or (DEFINE (name arg ...) body ...), but got {}, which is invalid",
             &args
         ))
@@ -340,26 +642,25 @@ fn eval_special_form_define(
                 let result = Atom::closure(env.clone(), cdr.clone(), args.cdr())?;
                 match car.as_ref() {
                     Atom::Symbol(symbol) => {
-                        let symbol = symbol.to_string();
-
                         // set closure name in environment.
                         let result = Atom::closure_add_env_binding(&result.clone(), symbol.clone(), result)?;
 
-                        env.set(symbol, result);
+                        env.define(symbol, result);
                         Ok(car.clone())
                     }
                     _ => {
-                        Err(eyre!("Found define form (DEFINE (name arg ...) body ...), but name was not a symbol"))
+                        Err(lisp_error!(Syntax, "Found define form (DEFINE (name arg ...) body ...), but name was not a symbol"))
                     }
                 }
             }
             Atom::Symbol(symbol) => {
                 let value = Atom::eval(args.cdr().car(), env)
                     .context("While evaluating VALUE argument for DEFINE")?;
-                env.set(symbol.to_string(), value);
+                env.define(symbol, value);
                 Ok(sym)
             }
-            _ => Err(eyre!(
+            _ => Err(lisp_error!(
+                Type,
                 "Expected a symbol as first argument to define, got {}",
                 sym
             )),
@@ -367,10 +668,14 @@ fn eval_special_form_define(
     }
 }
 
-fn eval_special_form_quote(args: &Rc<Atom>) -> Result<Rc<Atom>, color_eyre::Report> {
+fn eval_special_form_quote(args: &Rc<Atom>) -> Result<Rc<Atom>> {
     // exactly one argument
     if args.is_nil() || !args.cdr().is_nil() {
-        Err(eyre!("QUOTE takes exactly one argument, got {}", &args))
+        Err(lisp_error!(
+            Arity,
+            "QUOTE takes exactly one argument, got {}",
+            &args
+        ))
     } else {
         Ok(args.car())
     }
@@ -0,0 +1,219 @@
+//! Rust values convert to atoms with [`From`], and back with [`TryFrom<&Atom>`]:
+//! - `f64` and `i64` become numbers, and a number converts to an `i64` only if it is an integer
+//! - `&str` and `String` become strings
+//! - `true` becomes t and `false` nil, and any atom but nil converts back to `true`
+//! - `Vec`s become lists
+//! - `None` becomes nil and `Some` its value, and nil converts back to `None`
+//! - `HashMap`s with string keys become association lists of `(key . value)` pairs, with string keys
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+use super::{Atom, Rc};
+use crate::error::{Context, LispError};
+use crate::lisp_error;
+
+/// Build an association list from `(key, value)` pairs.
+pub(super) fn alist_from_entries(entries: impl Iterator<Item = (String, Rc<Atom>)>) -> Rc<Atom> {
+    Atom::list_from_vec(
+        entries
+            .map(|(key, value)| Rc::new(Atom::Pair(Rc::new(Atom::String(key.into())), value)))
+            .collect(),
+    )
+}
+
+/// Split an association list into `(key, value)` pairs.
+///
+/// Returns `None` if the atom is not a non-empty list of pairs with string keys.
+pub(super) fn alist_entries(atom: &Rc<Atom>) -> Option<Vec<(String, Rc<Atom>)>> {
+    if atom.is_nil() {
+        return None;
+    }
+    Atom::list_to_vec(atom)
+        .ok()?
+        .into_iter()
+        .map(|entry| match entry.as_ref() {
+            Atom::Pair(key, value) => match key.as_ref() {
+                Atom::String(key) => Some((key.to_string(), value.clone())),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Get a number as an integer, if it has no fractional part and fits in an `i64`.
+pub(super) fn as_integer(x: f64) -> Option<i64> {
+    #[allow(clippy::cast_precision_loss)]
+    let in_range = (i64::MIN as f64..i64::MAX as f64).contains(&x);
+    if x.fract() == 0.0 && in_range {
+        #[allow(clippy::cast_possible_truncation)]
+        Some(x as i64)
+    } else {
+        None
+    }
+}
+
+impl From<f64> for Atom {
+    fn from(x: f64) -> Self {
+        Atom::Number(x)
+    }
+}
+
+impl From<i64> for Atom {
+    fn from(x: i64) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        Atom::Number(x as f64)
+    }
+}
+
+impl From<bool> for Atom {
+    fn from(b: bool) -> Self {
+        Rc::unwrap_or_clone(Atom::bool(b))
+    }
+}
+
+impl From<&str> for Atom {
+    fn from(s: &str) -> Self {
+        Atom::String(s.into())
+    }
+}
+
+impl From<String> for Atom {
+    fn from(s: String) -> Self {
+        Atom::String(s.into())
+    }
+}
+
+impl<T: Into<Atom>> From<Vec<T>> for Atom {
+    fn from(items: Vec<T>) -> Self {
+        let items = items.into_iter().map(|item| Rc::new(item.into())).collect();
+        Rc::unwrap_or_clone(Atom::list_from_vec(items))
+    }
+}
+
+impl<T: Into<Atom>> From<Option<T>> for Atom {
+    fn from(value: Option<T>) -> Self {
+        value.map_or_else(|| Rc::unwrap_or_clone(Atom::nil()), Into::into)
+    }
+}
+
+impl<T: Into<Atom>, S> From<HashMap<String, T, S>> for Atom {
+    fn from(map: HashMap<String, T, S>) -> Self {
+        let entries = map
+            .into_iter()
+            .map(|(key, value)| (key, Rc::new(value.into())));
+        Rc::unwrap_or_clone(alist_from_entries(entries))
+    }
+}
+
+impl TryFrom<&Atom> for f64 {
+    type Error = LispError;
+
+    fn try_from(atom: &Atom) -> Result<Self, LispError> {
+        atom.get_number()
+    }
+}
+
+impl TryFrom<&Atom> for i64 {
+    type Error = LispError;
+
+    fn try_from(atom: &Atom) -> Result<Self, LispError> {
+        let x = atom.get_number()?;
+        as_integer(x).ok_or_else(|| lisp_error!(Type, "Expected an integer, got {}", x))
+    }
+}
+
+impl TryFrom<&Atom> for bool {
+    type Error = LispError;
+
+    fn try_from(atom: &Atom) -> Result<Self, LispError> {
+        Ok(atom.as_bool())
+    }
+}
+
+impl<'a> TryFrom<&'a Atom> for &'a str {
+    type Error = LispError;
+
+    fn try_from(atom: &'a Atom) -> Result<Self, LispError> {
+        match atom {
+            Atom::String(s) => Ok(s),
+            a => Err(lisp_error!(Type, "Expected a string, got {}", a)),
+        }
+    }
+}
+
+impl TryFrom<&Atom> for String {
+    type Error = LispError;
+
+    fn try_from(atom: &Atom) -> Result<Self, LispError> {
+        Ok(atom.get_string()?.to_string())
+    }
+}
+
+impl<T> TryFrom<&Atom> for Vec<T>
+where
+    T: for<'a> TryFrom<&'a Atom, Error = LispError>,
+{
+    type Error = LispError;
+
+    fn try_from(atom: &Atom) -> Result<Self, LispError> {
+        let mut items = Vec::new();
+        let mut rest = atom;
+        while let Atom::Pair(car, cdr) = rest {
+            let item =
+                T::try_from(car.as_ref()).with_context(|| format!("In element {}", items.len()))?;
+            items.push(item);
+            rest = cdr;
+        }
+        if rest.is_nil() {
+            Ok(items)
+        } else {
+            Err(lisp_error!(Type, "Expected a proper list, got {}", atom))
+        }
+    }
+}
+
+impl<T> TryFrom<&Atom> for Option<T>
+where
+    T: for<'a> TryFrom<&'a Atom, Error = LispError>,
+{
+    type Error = LispError;
+
+    fn try_from(atom: &Atom) -> Result<Self, LispError> {
+        if atom.is_nil() {
+            Ok(None)
+        } else {
+            T::try_from(atom).map(Some)
+        }
+    }
+}
+
+impl<T, S> TryFrom<&Atom> for HashMap<String, T, S>
+where
+    T: for<'a> TryFrom<&'a Atom, Error = LispError>,
+    S: BuildHasher + Default,
+{
+    type Error = LispError;
+
+    fn try_from(atom: &Atom) -> Result<Self, LispError> {
+        if atom.is_nil() {
+            return Ok(Self::default());
+        }
+        let entries = alist_entries(&Rc::new(atom.clone())).ok_or_else(|| {
+            lisp_error!(
+                Type,
+                "Expected an association list of (key . value) pairs with string keys, got {}",
+                atom
+            )
+        })?;
+        entries
+            .into_iter()
+            .map(|(key, value)| {
+                let value = T::try_from(value.as_ref())
+                    .with_context(|| format!("In the value of {key:?}"))?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,168 @@
+//! Atoms serialize to the serde data model as:
+//! - numbers as integers when they have no fractional part, and as floats otherwise
+//! - strings as strings
+//! - nil as unit (`null` in JSON) and t as `true`, and `false` deserializes to nil
+//! - association lists, non-empty lists where every element is a pair with a string as car, as maps
+//! - other proper lists as sequences
+//! - symbols and keywords as the `symbol` and `keyword` variants, `{"symbol": "name"}` in JSON
+//! - the pairs of improper lists as the `pair` variant, with a sequence of their car and cdr
+//!
+//! Functions and macros can not be serialized. Deserializing needs a self-describing format, like JSON or YAML,
+//! where a map with a single `symbol`, `keyword` or `pair` key is read as such a variant if it is a valid one.
+
+use std::fmt;
+
+use serde::de::{
+    self, Deserialize, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use super::rust_conversions::{alist_entries, as_integer};
+use super::{Atom, Rc};
+
+impl Serialize for Atom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Atom::Number(x) => match as_integer(*x) {
+                Some(x) => serializer.serialize_i64(x),
+                None => serializer.serialize_f64(*x),
+            },
+            Atom::String(s) => serializer.serialize_str(s),
+            Atom::Symbol(name) if &**name == "nil" => serializer.serialize_unit(),
+            Atom::Symbol(name) if &**name == "t" => serializer.serialize_bool(true),
+            Atom::Symbol(name) => {
+                serializer.serialize_newtype_variant("Atom", 0, "symbol", &**name)
+            }
+            Atom::Keyword(name) => {
+                serializer.serialize_newtype_variant("Atom", 1, "keyword", &**name)
+            }
+            Atom::Pair(car, cdr) => {
+                let list = Rc::new(self.clone());
+                if let Some(entries) = alist_entries(&list) {
+                    let mut map = serializer.serialize_map(Some(entries.len()))?;
+                    for (key, value) in &entries {
+                        map.serialize_entry(key, value.as_ref())?;
+                    }
+                    map.end()
+                } else if let Ok(items) = Atom::list_to_vec(&list) {
+                    let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                    for item in &items {
+                        seq.serialize_element(item.as_ref())?;
+                    }
+                    seq.end()
+                } else {
+                    serializer.serialize_newtype_variant(
+                        "Atom",
+                        2,
+                        "pair",
+                        &[car.as_ref(), cdr.as_ref()],
+                    )
+                }
+            }
+            Atom::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key.as_ref(), value.as_ref())?;
+                }
+                map.end()
+            }
+            a => Err(ser::Error::custom(format!("Can not serialize {a}"))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Atom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(AtomVisitor)
+    }
+}
+
+/// Builds atoms from whatever the deserializer finds.
+struct AtomVisitor;
+
+impl AtomVisitor {
+    /// Build the symbol, keyword or pair given by a variant.
+    fn variant<E: de::Error>(name: &str, value: &Atom) -> Result<Atom, E> {
+        match (name, value) {
+            ("symbol", Atom::String(name)) => Ok(Atom::Symbol(name.clone())),
+            ("keyword", Atom::String(name)) => Ok(Atom::Keyword(name.clone())),
+            ("pair", Atom::Pair(car, rest)) if matches!(rest.as_ref(), Atom::Pair(_, end) if end.is_nil()) => {
+                Ok(Atom::Pair(car.clone(), rest.car()))
+            }
+            (name, value) => Err(E::custom(format!("Can not make a {name} from {value}"))),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for AtomVisitor {
+    type Value = Atom;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a lisp atom")
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Atom, E> {
+        Ok(Atom::from(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, x: i64) -> Result<Atom, E> {
+        Ok(Atom::from(x))
+    }
+
+    fn visit_u64<E: de::Error>(self, x: u64) -> Result<Atom, E> {
+        #[allow(clippy::cast_precision_loss)]
+        Ok(Atom::Number(x as f64))
+    }
+
+    fn visit_f64<E: de::Error>(self, x: f64) -> Result<Atom, E> {
+        Ok(Atom::Number(x))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Atom, E> {
+        Ok(Atom::from(s))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Atom, E> {
+        Ok(Atom::from(false))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Atom, E> {
+        Ok(Atom::from(false))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Atom, D::Error> {
+        Atom::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Atom, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element::<Atom>()? {
+            items.push(Rc::new(item));
+        }
+        Ok(Rc::unwrap_or_clone(Atom::list_from_vec(items)))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Atom, A::Error> {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((key, value)) = map.next_entry::<Atom, Atom>()? {
+            entries.push((key, value));
+        }
+        // a map which does not make a valid variant, like {"symbol": 1}, is kept as an association list
+        if let [(Atom::String(name), value)] = entries.as_slice() {
+            if let Ok(atom) = Self::variant::<A::Error>(name, value) {
+                return Ok(atom);
+            }
+        }
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| Rc::new(Atom::Pair(Rc::new(key), Rc::new(value))))
+            .collect();
+        Ok(Rc::unwrap_or_clone(Atom::list_from_vec(entries)))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Atom, A::Error> {
+        let (name, variant) = data.variant::<String>()?;
+        let value = variant.newtype_variant::<Atom>()?;
+        Self::variant(&name, &value)
+    }
+}
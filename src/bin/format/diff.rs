@@ -0,0 +1,231 @@
+use std::fmt::Write as _;
+
+/// Number of unchanged lines shown around changed ones.
+const CONTEXT: usize = 3;
+
+/// What happens to a line going from the old text to the new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Change {
+    Kept,
+    Removed,
+    Added,
+}
+
+/// A line of the diff, with the index of the next line of the old and new texts where it is.
+#[derive(Clone, Copy, Debug)]
+struct Line<'a> {
+    change: Change,
+    text: &'a str,
+    old: usize,
+    new: usize,
+}
+
+/// A unified diff going from `old` to `new`, like `diff -u` prints, or an empty string if they are the same.
+#[must_use]
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let lines = diff_lines(old, new);
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.change != Change::Kept)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let Some(&first) = changed.first() else {
+        return String::new();
+    };
+
+    let mut diff = format!("--- {old_name}\n+++ {new_name}\n");
+    let (mut start, mut end) = (first, first);
+    for &i in &changed[1..] {
+        // hunks whose context would touch are merged
+        if i - end > 2 * CONTEXT {
+            write_hunk(&mut diff, &lines, start, end);
+            start = i;
+        }
+        end = i;
+    }
+    write_hunk(&mut diff, &lines, start, end);
+    diff
+}
+
+/// Write the hunk with the changed lines from `first` to `last`, and the context around them.
+fn write_hunk(diff: &mut String, lines: &[Line], first: usize, last: usize) {
+    let lines = &lines[first.saturating_sub(CONTEXT)..lines.len().min(last + CONTEXT + 1)];
+    let old_count = lines
+        .iter()
+        .filter(|line| line.change != Change::Added)
+        .count();
+    let new_count = lines
+        .iter()
+        .filter(|line| line.change != Change::Removed)
+        .count();
+    // like diff, an empty range starts at the line before it
+    let start = |index: usize, count| if count == 0 { index } else { index + 1 };
+    writeln!(
+        diff,
+        "@@ -{},{old_count} +{},{new_count} @@",
+        start(lines[0].old, old_count),
+        start(lines[0].new, new_count)
+    )
+    .unwrap();
+    for line in lines {
+        let marker = match line.change {
+            Change::Kept => ' ',
+            Change::Removed => '-',
+            Change::Added => '+',
+        };
+        write!(diff, "{marker}{}", line.text).unwrap();
+        if !line.text.ends_with('\n') {
+            diff.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+/// The lines of `old` and `new`, with as many of them kept as possible.
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old = old.split_inclusive('\n').collect::<Vec<_>>();
+    let new = new.split_inclusive('\n').collect::<Vec<_>>();
+
+    // formatting changes few lines, so the lines they start and end with are kept without searching
+    let prefix = old.iter().zip(&new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let changes = std::iter::repeat_n(Change::Kept, prefix)
+        .chain(shortest_edit(
+            &old[prefix..old.len() - suffix],
+            &new[prefix..new.len() - suffix],
+        ))
+        .chain(std::iter::repeat_n(Change::Kept, suffix));
+
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    for change in changes {
+        let text = if change == Change::Added {
+            new[j]
+        } else {
+            old[i]
+        };
+        lines.push(Line {
+            change,
+            text,
+            old: i,
+            new: j,
+        });
+        if change != Change::Added {
+            i += 1;
+        }
+        if change != Change::Removed {
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// The changes going from `old` to `new` with as few lines removed and added as possible, found with Myers'
+/// algorithm, which takes time and space proportional to the square of the number of changes instead of the product
+/// of the numbers of lines.
+fn shortest_edit(old: &[&str], new: &[&str]) -> Vec<Change> {
+    let (old_len, new_len) = (old.len(), new.len());
+    // furthest[d][i] is how far into `old` the furthest path with d changes gets on diagonal i - d, whose lines of
+    // `new` are as far as those of `old` minus the diagonal. Only every other diagonal can be reached with d changes
+    let mut furthest: Vec<Vec<usize>> = Vec::new();
+    // whether the furthest path on a diagonal comes from adding a line, going down from the diagonal above, instead
+    // of removing one
+    let down = |previous: &[usize], d: usize, i: usize| {
+        i == 0 || (i != 2 * d && previous[i - 2] < previous[i])
+    };
+    'search: for d in 0..=old_len + new_len {
+        let mut row = vec![0; 2 * d + 1];
+        for i in (0..=2 * d).step_by(2) {
+            let mut x = match furthest.last() {
+                None => 0,
+                Some(previous) if down(previous, d, i) => previous[i],
+                Some(previous) => previous[i - 2] + 1,
+            };
+            let mut y = x + d - i;
+            while x < old_len && new.get(y) == Some(&old[x]) {
+                x += 1;
+                y += 1;
+            }
+            row[i] = x;
+            if x >= old_len && y >= new_len {
+                furthest.push(row);
+                break 'search;
+            }
+        }
+        furthest.push(row);
+    }
+
+    // follow the path back from the end
+    let mut changes = Vec::with_capacity(old_len + new_len);
+    let (mut x, mut y) = (old_len, new_len);
+    for d in (1..furthest.len()).rev() {
+        let previous = &furthest[d - 1];
+        let i = x + d - y;
+        let (change, from) = if down(previous, d, i) {
+            (Change::Added, previous[i])
+        } else {
+            (Change::Removed, previous[i - 2])
+        };
+        // the start of the diagonal the path slid along after the change
+        let (after_x, after_y) = match change {
+            Change::Added => (from, from + d - i),
+            _ => (from + 1, from + d + 1 - i),
+        };
+        changes.extend(std::iter::repeat_n(Change::Kept, x - after_x));
+        changes.push(change);
+        (x, y) = match change {
+            Change::Added => (after_x, after_y - 1),
+            _ => (after_x - 1, after_y),
+        };
+    }
+    changes.extend(std::iter::repeat_n(Change::Kept, x));
+    changes.reverse();
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unified;
+
+    #[test]
+    fn unified_diff() {
+        assert_eq!(unified("a\nb\n", "a\nb\n", "old", "new"), "");
+
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12";
+        let new = "0\n1\n2\n3\n4\n5\n6\n7\n8\nnine\n10\n11\n12\n";
+        assert_eq!(
+            unified(old, new, "old", "new"),
+            "--- old\n+++ new\n\
+             @@ -1,3 +1,4 @@\n+0\n 1\n 2\n 3\n\
+             @@ -6,7 +7,7 @@\n 6\n 7\n 8\n-9\n+nine\n 10\n 11\n-12\n\\ No newline at end of file\n+12\n"
+        );
+
+        assert_eq!(
+            unified("a\nb\nc\n", "c\nb\na\nd\n", "old", "new"),
+            "--- old\n+++ new\n@@ -1,3 +1,4 @@\n-a\n-b\n c\n+b\n+a\n+d\n"
+        );
+        assert_eq!(
+            unified("", "a\n", "old", "new"),
+            "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+a\n"
+        );
+    }
+
+    #[test]
+    fn long_unified_diff() {
+        // a quadratic table of the lines would not fit in memory
+        let old = "line\n".repeat(100_000);
+        let new = format!("first\n{old}last\n");
+        assert_eq!(
+            unified(&old, &new, "old", "new")
+                .lines()
+                .filter(|line| line.starts_with('+'))
+                .count(),
+            3
+        );
+    }
+}
@@ -0,0 +1,458 @@
+#![warn(clippy::pedantic)]
+#![warn(clippy::nursery)]
+// we use car and cdr a lot
+#![allow(clippy::similar_names)]
+// I find this clearer sometimes
+#![allow(clippy::redundant_else)]
+// I find this clearer sometimes
+#![allow(clippy::use_self)]
+
+/// Unified diffs between texts
+mod diff;
+
+use std::ffi::OsStr;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use chumsky::Parser as _;
+use clap::Parser as _;
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use lwhlisp::{
+    atom::{Atom, Layout, Rc},
+    config::Config,
+    parsing::{parser_with_comments, Commented, SourceText, SpanTree},
+    print_parse_errs, read_file_to_string,
+};
+
+/// lwhlisp -- Lisp interpreter in Rust
+/// Pretty-print files
+#[derive(clap::Parser, Debug)]
+#[clap(author, version, about, propagate_version = true)]
+struct Args {
+    /// Files to pretty-print, directories to pretty-print the `.lisp` files in, including those in
+    /// subdirectories, or `-` to read standard input and write the formatted version to standard output
+    #[clap(value_parser, required = true)]
+    files: Vec<String>,
+    /// Replace the files with their formatted version
+    #[clap(long)]
+    replace: bool,
+    /// Only check whether the files are formatted, printing those which are not and exiting with status 1 if
+    /// there are any
+    #[clap(long, conflicts_with = "replace")]
+    check: bool,
+    /// Print a unified diff of the changes formatting would make, instead of the formatted files
+    #[clap(long, conflicts_with = "replace")]
+    diff: bool,
+    /// Maximum width of the lines, lists which do not fit on a line are split over several. Defaults to the width
+    /// of the configuration files, or else 80
+    #[clap(long, value_parser)]
+    width: Option<usize>,
+    /// Only format the top-level s-expressions on the lines from START to END, counting from 1, and leave the rest
+    /// of the files as they are
+    #[clap(long, value_parser = parse_line_range, value_name = "START:END")]
+    lines: Option<RangeInclusive<usize>>,
+}
+
+/// Parse a range of lines given as `start:end`.
+fn parse_line_range(range: &str) -> Result<RangeInclusive<usize>, String> {
+    let (start, end) = range
+        .split_once(':')
+        .ok_or_else(|| format!("{range:?} should be START:END"))?;
+    let line = |line: &str| match line.parse::<usize>() {
+        Ok(line) if line > 0 => Ok(line),
+        _ => Err(format!("{line:?} is not a line number")),
+    };
+    let (start, end) = (line(start)?, line(end)?);
+    if start > end {
+        return Err(format!("line {start} is after line {end}"));
+    }
+    Ok(start..=end)
+}
+
+fn main() -> Result<()> {
+    // the location is where the formatter turned the error into a report, which says nothing about the input
+    color_eyre::config::HookBuilder::default()
+        .display_location_section(false)
+        .install()?;
+    let args = Args::parse();
+    if args.replace && args.files.iter().any(|file| file == "-") {
+        return Err(eyre!("Can not --replace standard input"));
+    }
+
+    let config = Config::load()?;
+    let mut layout = Layout::default();
+    layout.width = args.width.or(config.width).unwrap_or(layout.width);
+    layout.indent = config.indent.unwrap_or(layout.indent);
+    layout.body_forms.extend(config.body_forms);
+    layout.align_arguments = config.align_arguments.unwrap_or(layout.align_arguments);
+
+    let files = find_files(&args.files)?;
+    let mut changed = 0;
+    let mut failed = 0;
+    for file in &files {
+        if files.len() > 1 && !args.replace && !args.check && !args.diff {
+            println!("==> {file} <==");
+        }
+        match format_file(file, &args, &layout) {
+            Ok(true) => changed += 1,
+            Ok(false) => {}
+            Err(e) if files.len() == 1 => return Err(e),
+            Err(e) => {
+                failed += 1;
+                eprintln!("!! {e:#}");
+            }
+        }
+    }
+
+    if files.len() > 1 && args.check {
+        println!("{changed} of {} files would be reformatted", files.len());
+    } else if files.len() > 1 && args.replace {
+        println!("Reformatted {changed} of {} files", files.len());
+    }
+    if failed > 0 {
+        return Err(eyre!("Could not format {failed} of {} files", files.len()));
+    }
+    if args.check && changed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The files to format: the given files, and the `.lisp` files in the given directories and their
+/// subdirectories.
+fn find_files(paths: &[String]) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if Path::new(path).is_dir() {
+            find_lisp_files(Path::new(path), &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Add the `.lisp` files in `dir` and its subdirectories to `files`, sorted by path.
+///
+/// Symbolic links to directories are not followed, so that a link to a parent directory does not make this recurse
+/// forever.
+fn find_lisp_files(dir: &Path, files: &mut Vec<String>) -> Result<()> {
+    let mut paths = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| {
+                    let entry = entry?;
+                    Ok((entry.path(), entry.file_type()?.is_dir()))
+                })
+                .collect::<std::io::Result<Vec<_>>>()
+        })
+        .with_context(|| format!("While reading directory {}", dir.display()))?;
+    paths.sort();
+    for (path, is_dir) in paths {
+        if is_dir {
+            find_lisp_files(&path, files)?;
+        } else if path.extension() == Some(OsStr::new("lisp")) {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Format `file` with `layout`, printing the formatted version, replacing the file with it, or checking it, as
+/// `args` say.
+///
+/// Returns whether the formatted version is different from the file.
+fn format_file(file: &str, args: &Args, layout: &Layout) -> Result<bool> {
+    let name = if file == "-" { "<stdin>" } else { file };
+    let original = read_file_to_string(file)?;
+    let src = original.trim();
+    let (commented, errs) = parser_with_comments().parse_recovery_verbose(src);
+    let parsed_cleanly = errs.is_empty();
+    print_parse_errs(errs, src);
+    if !parsed_cleanly {
+        // the forms with errors are left out of the parsed atoms, so nothing is printed for the file, which would
+        // otherwise look like the whole of it without them
+        return Err(eyre!("Could not parse {name}"));
+    }
+
+    let formatted = commented
+        .map(|commented| match &args.lines {
+            Some(lines) => format_lines(&original, commented, layout, lines),
+            None => format(src, commented, layout),
+        })
+        .unwrap_or_default();
+    let changed = formatted != original;
+    if args.diff {
+        print!("{}", diff::unified(&original, &formatted, name, name));
+    } else if args.check {
+        if changed {
+            println!("{name} would be reformatted");
+        }
+    } else if args.replace {
+        if changed {
+            let out_file_path = format!("{file}.tmp_format");
+            std::fs::write(&out_file_path, formatted)
+                .context("While writing to temporary output file")?;
+            std::fs::rename(out_file_path, file)
+                .context("While moving formatted file to original")?;
+        }
+    } else {
+        print!("{formatted}");
+    }
+    Ok(changed)
+}
+
+/// Pretty-print parsed source with `layout`, keeping its comments.
+fn format(src: &str, commented: Commented, layout: &Layout) -> String {
+    use std::fmt::Write as _;
+
+    let src = SourceText::new(src);
+    let mut formatted = String::new();
+    for (comments, atom, spans) in commented.atoms {
+        for comment in comments {
+            writeln!(formatted, "{}", src.get(&comment)).unwrap();
+        }
+        // the pretty-printer can not place comments inside of a form, so such a form is kept as it is
+        if spans.has_comments() {
+            writeln!(formatted, "{}\n", src.get(&spans.span)).unwrap();
+        } else {
+            let atom = literal_text(&atom, &spans, &src);
+            writeln!(formatted, "{}\n", atom.pretty_print_with(layout)).unwrap();
+        }
+    }
+    for comment in commented.trailing_comments {
+        writeln!(formatted, "{}", src.get(&comment)).unwrap();
+    }
+    formatted
+}
+
+/// Pretty-print the top-level forms of `original` which are at least partly on `lines`, leaving the rest of it as
+/// it is. `commented` is parsed from `original` without its leading and trailing whitespace.
+fn format_lines(
+    original: &str,
+    commented: Commented,
+    layout: &Layout,
+    lines: &RangeInclusive<usize>,
+) -> String {
+    // spans count characters of the trimmed source
+    let offset = original.chars().count() - original.trim_start().chars().count();
+    let newlines = original
+        .chars()
+        .enumerate()
+        .filter(|(_, c)| *c == '\n')
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let line = |index: usize| newlines.partition_point(|&newline| newline < index) + 1;
+    let bytes = original
+        .char_indices()
+        .map(|(byte, _)| byte)
+        .chain([original.len()])
+        .collect::<Vec<_>>();
+
+    let src = SourceText::new(original.trim());
+    let mut formatted = String::new();
+    let mut copied = 0;
+    for (_, atom, spans) in commented.atoms {
+        let (start, end) = (spans.span.start + offset, spans.span.end + offset);
+        // the pretty-printer can not place comments inside of a form, so such a form is kept as it is
+        if spans.has_comments() || line(start) > *lines.end() || line(end - 1) < *lines.start() {
+            continue;
+        }
+        formatted.push_str(&original[bytes[copied]..bytes[start]]);
+        let atom = literal_text(&atom, &spans, &src);
+        formatted.push_str(&atom.pretty_print_with(layout));
+        copied = end;
+    }
+    formatted.push_str(&original[bytes[copied]..]);
+    formatted
+}
+
+/// Replace the numbers, strings and maps of `atom` by symbols named after their text in `src`, and the `quote` of
+/// `'x` and the like by their shorthand, so that they are printed the way they were written, like `1.0`, `"\x41"`
+/// or `'x`, instead of the way they were read.
+fn literal_text(atom: &Atom, spans: &SpanTree, src: &SourceText) -> Rc<Atom> {
+    match atom {
+        Atom::Number(_) | Atom::String(_) | Atom::Map(_) => {
+            Rc::new(Atom::Symbol(src.get(&spans.span).into()))
+        }
+        // the name of the list read from `'x` is only its `'`, which the pretty-printer prints as it is
+        Atom::Symbol(_) if matches!(src.get(&spans.span), "'" | "`" | "," | ",@") => {
+            Rc::new(Atom::Symbol(src.get(&spans.span).into()))
+        }
+        Atom::Pair(_, _) => {
+            // the elements of a list given by a reader macro have no spans, so they are kept as they are
+            let mut element_spans = spans.elements.iter();
+            let mut literal = |atom: &Rc<Atom>| {
+                element_spans
+                    .next()
+                    .map_or_else(|| atom.clone(), |spans| literal_text(atom, spans, src))
+            };
+            let mut elements = Vec::new();
+            let mut rest = atom;
+            while let Atom::Pair(car, cdr) = rest {
+                elements.push(literal(car));
+                rest = cdr;
+            }
+            let tail = if rest.is_nil() {
+                Atom::nil()
+            } else {
+                literal(&Rc::new(rest.clone()))
+            };
+            elements
+                .into_iter()
+                .rev()
+                .fold(tail, |cdr, car| Rc::new(Atom::Pair(car, cdr)))
+        }
+        atom => Rc::new(atom.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(arguments: &[&str]) -> Args {
+        Args::parse_from(std::iter::once("lwhlisp-format").chain(arguments.iter().copied()))
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lwhlisp-format-test-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn format_src(src: &str, lines: Option<RangeInclusive<usize>>) -> String {
+        let commented = parser_with_comments().parse(src.trim()).unwrap();
+        match lines {
+            Some(lines) => format_lines(src, commented, &Layout::default(), &lines),
+            None => format(src.trim(), commented, &Layout::default()),
+        }
+    }
+
+    #[test]
+    fn line_ranges() {
+        let src = "(a   b)\n\n(c\n   d)\n(e   f)\n";
+        // a form partly on the lines is formatted whole
+        assert_eq!(format_src(src, Some(4..=4)), "(a   b)\n\n(c d)\n(e   f)\n");
+        assert_eq!(format_src(src, Some(1..=3)), "(a b)\n\n(c d)\n(e   f)\n");
+        // lines after the end of the file select nothing more
+        assert_eq!(
+            format_src(src, Some(5..=100)),
+            "(a   b)\n\n(c\n   d)\n(e f)\n"
+        );
+        assert_eq!(format_src(src, Some(10..=20)), src);
+        // a blank line has no form on it
+        assert_eq!(format_src(src, Some(2..=2)), src);
+
+        assert_eq!(parse_line_range("2:5"), Ok(2..=5));
+        assert_eq!(parse_line_range("3:3"), Ok(3..=3));
+        assert!(parse_line_range("5:2").is_err());
+        assert!(parse_line_range("0:2").is_err());
+        assert!(parse_line_range("2").is_err());
+    }
+
+    #[test]
+    fn quote_shorthands() {
+        let src = "(f   'x   `(a ,b ,@c)   (quote y))";
+        assert_eq!(format_src(src, None), "(f 'x `(a ,b ,@c) (quote y))\n\n");
+        // a quoted list which does not fit on a line is split after its shorthand
+        let layout = Layout {
+            width: 20,
+            ..Layout::default()
+        };
+        let src = "(define xs '(first-element second-element))";
+        let commented = parser_with_comments().parse(src).unwrap();
+        assert_eq!(
+            format(src, commented, &layout),
+            "(define xs\n   '(first-element\n      second-element))\n\n"
+        );
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        // spans count characters, which are several bytes before and inside of the forms here
+        let src = "#| é |#\n(ü   \"→ ö\"   1.50)\n";
+        assert_eq!(format_src(src, None), "#| é |#\n(ü \"→ ö\" 1.50)\n\n");
+        let src = "(é   \"ü\")\n(ö   \"→\"   2.0)\n";
+        assert_eq!(format_src(src, Some(2..=2)), "(é   \"ü\")\n(ö \"→\" 2.0)\n");
+    }
+
+    #[test]
+    fn directories() {
+        let dir = temp_dir("directories");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        for file in ["b.lisp", "a.lisp", "notes.txt", "sub/c.lisp"] {
+            std::fs::write(dir.join(file), "(a b)\n").unwrap();
+        }
+        let path = |file: &str| dir.join(file).display().to_string();
+        // the files given are kept as they are, even if they are not .lisp files
+        let files = find_files(&[path(""), "-".to_owned(), path("notes.txt")]).unwrap();
+        assert_eq!(
+            files,
+            [
+                path("a.lisp"),
+                path("b.lisp"),
+                path("sub/c.lisp"),
+                "-".to_owned(),
+                path("notes.txt")
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_and_replace() {
+        let dir = temp_dir("check");
+        let file = dir.join("f.lisp").display().to_string();
+        std::fs::write(&file, "(a   b)").unwrap();
+        let layout = Layout::default();
+        assert!(format_file(&file, &args(&["--check", &file]), &layout).unwrap());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "(a   b)");
+        assert!(format_file(&file, &args(&["--replace", &file]), &layout).unwrap());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "(a b)\n\n");
+        // once formatted, there is nothing left to change
+        assert!(!format_file(&file, &args(&["--check", &file]), &layout).unwrap());
+        std::fs::write(&file, "(a b").unwrap();
+        assert!(format_file(&file, &args(&["--check", &file]), &layout).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            Args::try_parse_from(["lwhlisp-format", "--check", "--replace", "f.lisp"]).is_err()
+        );
+    }
+
+    // stdin is only read once, and only what the test writes to it should be, so the test runs again in a child
+    // process reading it
+    #[test]
+    fn standard_input() {
+        use std::io::Write as _;
+
+        const CHILD: &str = "LWHLISP_FORMAT_TEST_STDIN_CHILD";
+        if std::env::var_os(CHILD).is_some() {
+            assert!(format_file("-", &args(&["-"]), &Layout::default()).unwrap());
+            return;
+        }
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "tests::standard_input",
+                "--test-threads=1",
+                "--nocapture",
+            ])
+            .env(CHILD, "1")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"(a   b)").unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        // the formatted version is written to stdout
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("(a b)\n"));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::info;
+
+use crate::atom::{Atom, Map, Rc};
+
+/// Marks the start of a cache file, followed by the format version.
+const MAGIC: &[u8; 4] = b"LWHC";
+/// Bump this whenever the encoding changes, so old cache files are ignored.
+const VERSION: u8 = 3;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_SYMBOL: u8 = 2;
+const TAG_LIST: u8 = 3;
+const TAG_MAP: u8 = 4;
+const TAG_KEYWORD: u8 = 5;
+
+/// Hash source code, to detect when a cache file is out of date.
+#[must_use]
+pub fn hash_source(src: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    src.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Path of the cache file for a source file.
+#[must_use]
+pub fn cache_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{path}.cache"))
+}
+
+/// Encode parsed atoms to bytes, tagged with the hash of the source they were parsed from.
+///
+/// # Errors
+/// If one of the atoms can not be produced by the parser (like a builtin function), return an error.
+pub fn encode(atoms: &[Atom], hash: u64) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&hash.to_le_bytes());
+    write_length(&mut bytes, atoms.len());
+    for atom in atoms {
+        encode_atom(&mut bytes, atom)?;
+    }
+    Ok(bytes)
+}
+
+/// Decode atoms from bytes produced by [`encode`].
+///
+/// Returns `None` if the bytes are not a valid cache, or were produced from source with a different hash.
+#[must_use]
+pub fn decode(bytes: &[u8], hash: u64) -> Option<Vec<Atom>> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC || reader.take(1)? != [VERSION] {
+        return None;
+    }
+    if reader.read_u64()? != hash {
+        return None;
+    }
+    let count = reader.read_length()?;
+    let atoms = (0..count)
+        .map(|_| decode_atom(&mut reader))
+        .collect::<Option<Vec<_>>>()?;
+    reader.bytes.is_empty().then_some(atoms)
+}
+
+/// Read the cached atoms for the given source from a cache file.
+///
+/// Returns `None` if there is no cache file, or if it is out of date.
+#[must_use]
+pub fn read(cache_path: &Path, src: &str) -> Option<Vec<Atom>> {
+    let bytes = std::fs::read(cache_path).ok()?;
+    let atoms = decode(&bytes, hash_source(src));
+    if atoms.is_none() {
+        info!("Cache file {} is out of date", cache_path.display());
+    }
+    atoms
+}
+
+/// Write the atoms parsed from the given source to a cache file.
+///
+/// # Errors
+/// If the atoms can not be encoded, or if the file can not be written, return an error.
+pub fn write(cache_path: &Path, src: &str, atoms: &[Atom]) -> Result<()> {
+    let bytes = encode(atoms, hash_source(src))?;
+    std::fs::write(cache_path, bytes)
+        .context(format!("While writing cache file {}", cache_path.display()))
+}
+
+fn write_length(bytes: &mut Vec<u8>, length: usize) {
+    bytes.extend_from_slice(&(length as u64).to_le_bytes());
+}
+
+fn write_str(bytes: &mut Vec<u8>, s: &str) {
+    write_length(bytes, s.len());
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+/// Lists are stored as their elements followed by their tail, so that long lists do not recurse per element.
+fn encode_atom(bytes: &mut Vec<u8>, atom: &Atom) -> Result<()> {
+    match atom {
+        Atom::Number(x) => {
+            bytes.push(TAG_NUMBER);
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        Atom::String(s) => {
+            bytes.push(TAG_STRING);
+            write_str(bytes, s);
+        }
+        Atom::Symbol(s) => {
+            bytes.push(TAG_SYMBOL);
+            write_str(bytes, s);
+        }
+        Atom::Keyword(s) => {
+            bytes.push(TAG_KEYWORD);
+            write_str(bytes, s);
+        }
+        Atom::Pair(_, _) => {
+            let mut items = Vec::new();
+            let mut tail = atom;
+            while let Atom::Pair(car, cdr) = tail {
+                items.push(car);
+                tail = cdr;
+            }
+            bytes.push(TAG_LIST);
+            write_length(bytes, items.len());
+            for item in items {
+                encode_atom(bytes, item)?;
+            }
+            encode_atom(bytes, tail)?;
+        }
+        Atom::Map(map) => {
+            bytes.push(TAG_MAP);
+            write_length(bytes, map.len());
+            for (key, value) in map {
+                encode_atom(bytes, key)?;
+                encode_atom(bytes, value)?;
+            }
+        }
+        a => return Err(eyre!("Can not cache {}", a)),
+    }
+    Ok(())
+}
+
+fn decode_atom(reader: &mut Reader) -> Option<Atom> {
+    match reader.take(1)?[0] {
+        TAG_NUMBER => Some(Atom::Number(f64::from_bits(reader.read_u64()?))),
+        TAG_STRING => Some(Atom::String(reader.read_str()?.into())),
+        TAG_SYMBOL => Some(Atom::Symbol(reader.read_str()?.into())),
+        TAG_KEYWORD => Some(Atom::Keyword(reader.read_str()?.into())),
+        TAG_LIST => {
+            let count = reader.read_length()?;
+            let items = (0..count)
+                .map(|_| decode_atom(reader))
+                .collect::<Option<Vec<_>>>()?;
+            let tail = decode_atom(reader)?;
+            Some(
+                items
+                    .into_iter()
+                    .rev()
+                    .fold(tail, |cdr, car| Atom::cons(car, cdr)),
+            )
+        }
+        TAG_MAP => {
+            let count = reader.read_length()?;
+            let map = (0..count)
+                .map(|_| Some((Rc::new(decode_atom(reader)?), Rc::new(decode_atom(reader)?))))
+                .collect::<Option<Map>>()?;
+            Some(Atom::Map(map))
+        }
+        _ => None,
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    const fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn read_u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn read_length(&mut self) -> Option<usize> {
+        usize::try_from(self.read_u64()?).ok()
+    }
+
+    fn read_str(&mut self) -> Option<String> {
+        let length = self.read_length()?;
+        String::from_utf8(self.take(length)?.to_vec()).ok()
+    }
+}
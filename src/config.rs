@@ -0,0 +1,265 @@
+//! Files lwhlisp reads its settings and library from, outside of the command line arguments.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::warn;
+
+/// Environment variable with the paths to search for the default library, separated like `PATH`.
+///
+/// Each path is either a library file, or a directory containing a `lib.lisp` file.
+pub const LIBRARY_PATH_VARIABLE: &str = "LWHLISP_LIBRARY_PATH";
+
+/// Name of the default library file in a directory.
+const LIBRARY_FILE_NAME: &str = "lib.lisp";
+
+/// Name of the configuration file of a project, searched for in the current directory and its parents.
+pub const PROJECT_CONFIG_FILE_NAME: &str = ".lwhlisp.toml";
+
+/// Name of the configuration file of the user, in the [configuration directory](config_dir).
+pub const USER_CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Directory of the user's lwhlisp configuration, like `~/.config/lwhlisp`.
+#[must_use]
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("lwhlisp"))
+}
+
+/// Find the file of the default library, searching the paths in [`LIBRARY_PATH_VARIABLE`], then those of the
+/// configuration files, then the configuration directory.
+///
+/// Returns `None` if there is none, in which case the library embedded in the binary should be used.
+#[must_use]
+pub fn default_library_path(config: &Config) -> Option<PathBuf> {
+    find_library(
+        std::env::var_os(LIBRARY_PATH_VARIABLE).as_deref(),
+        &config.library_path,
+        config_dir().as_deref(),
+    )
+}
+
+/// Find the file of the default library in `library_path`, a list of paths like `PATH`, then in `configured`,
+/// then in `config_dir`.
+#[must_use]
+pub fn find_library(
+    library_path: Option<&OsStr>,
+    configured: &[PathBuf],
+    config_dir: Option<&Path>,
+) -> Option<PathBuf> {
+    let searched = library_path
+        .into_iter()
+        .flat_map(std::env::split_paths)
+        .filter(|path| !path.as_os_str().is_empty())
+        .chain(configured.iter().cloned())
+        .chain(config_dir.map(Path::to_path_buf));
+    for path in searched {
+        if path.is_file() {
+            return Some(path);
+        }
+        let file = path.join(LIBRARY_FILE_NAME);
+        if file.is_file() {
+            return Some(file);
+        }
+    }
+    None
+}
+
+/// Settings read from configuration files, which are `None` or empty when they are not set.
+///
+/// A configuration file is a TOML document like this one, where every setting is optional:
+///
+/// ```toml
+/// [format]
+/// width = 100
+/// indent = 2
+/// align-arguments = true
+///
+/// # forms keeping their first arguments on the line of their head, with how many
+/// [format.body-forms]
+/// cond = 0
+/// with-open-file = 2
+///
+/// [library]
+/// # relative paths are relative to the directory of the configuration file
+/// path = ["lisp/lib.lisp"]
+///
+/// [repl]
+/// prompt = "{n}> "
+/// continuation-prompt = "... "
+/// color = false
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Maximum width of the lines of formatted files
+    pub width: Option<usize>,
+    /// Number of spaces the elements of lists split over several lines are indented by in formatted files
+    pub indent: Option<usize>,
+    /// Forms keeping their first arguments on the line of their head in formatted files, with how many, in
+    /// addition to the default ones
+    pub body_forms: HashMap<String, usize>,
+    /// Whether to align the arguments of other lists split over several lines in formatted files
+    pub align_arguments: Option<bool>,
+    /// Paths to search for the default library, after those in [`LIBRARY_PATH_VARIABLE`]
+    pub library_path: Vec<PathBuf>,
+    /// Prompt of the REPL
+    pub prompt: Option<String>,
+    /// Prompt of the REPL while an s-expression is unfinished
+    pub continuation_prompt: Option<String>,
+    /// Whether the REPL uses colors
+    pub color: Option<bool>,
+}
+
+impl Config {
+    /// Read the configuration file of the project the current directory is in, and that of the user. The settings
+    /// of the project take precedence.
+    ///
+    /// The library paths of the project are ignored: the project file is found in whichever directory lwhlisp runs
+    /// in, so it may not be trusted to choose code to evaluate.
+    ///
+    /// # Errors
+    /// If a configuration file can not be read, or has invalid settings.
+    pub fn load() -> Result<Self> {
+        Self::load_from(
+            std::env::current_dir().ok().as_deref(),
+            config_dir().as_deref(),
+        )
+    }
+
+    /// Read the configuration file of the project `dir` is in, and that of the user in `config_dir`, like
+    /// [`Config::load`].
+    ///
+    /// # Errors
+    /// If a configuration file can not be read, or has invalid settings.
+    pub fn load_from(dir: Option<&Path>, config_dir: Option<&Path>) -> Result<Self> {
+        let project = dir.and_then(find_project_config);
+        let user = config_dir
+            .map(|dir| dir.join(USER_CONFIG_FILE_NAME))
+            .filter(|file| file.is_file());
+        let mut config = Self::default();
+        if let Some(file) = project {
+            config = Self::read(&file)?;
+            if !config.library_path.is_empty() {
+                warn!(
+                    "Ignoring library.path in {}, only {USER_CONFIG_FILE_NAME} in the configuration directory can set it",
+                    file.display()
+                );
+                config.library_path.clear();
+            }
+        }
+        if let Some(file) = user {
+            config = config.or(Self::read(&file)?);
+        }
+        Ok(config)
+    }
+
+    /// Read a configuration file.
+    ///
+    /// # Errors
+    /// If the file can not be read, or has invalid settings.
+    pub fn read(file: &Path) -> Result<Self> {
+        let src = std::fs::read_to_string(file)
+            .with_context(|| format!("While reading configuration file {}", file.display()))?;
+        let dir = file.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&src, dir).with_context(|| format!("In configuration file {}", file.display()))
+    }
+
+    /// Parse a configuration file in `dir`, which relative paths are relative to.
+    ///
+    /// # Errors
+    /// If the file is not valid TOML, or has invalid settings.
+    pub fn parse(src: &str, dir: &Path) -> Result<Self> {
+        let table = src.parse::<toml::Table>()?;
+        let size = |value: &toml::Value| value.as_integer().and_then(|x| usize::try_from(x).ok());
+        let string = |value: &toml::Value| value.as_str().map(ToString::to_string);
+        let paths = |value: &toml::Value| {
+            value
+                .as_array()?
+                .iter()
+                .map(|path| path.as_str().map(|path| dir.join(path)))
+                .collect::<Option<Vec<_>>>()
+        };
+        let body_forms = |value: &toml::Value| {
+            value
+                .as_table()?
+                .iter()
+                .map(|(name, kept)| Some((name.clone(), size(kept)?)))
+                .collect::<Option<HashMap<_, _>>>()
+        };
+        let a_size = "a positive integer";
+        let a_string = "a string";
+        Ok(Self {
+            width: setting(&table, "format", "width", size, a_size)?,
+            indent: setting(&table, "format", "indent", size, a_size)?,
+            body_forms: setting(
+                &table,
+                "format",
+                "body-forms",
+                body_forms,
+                "a table of positive integers",
+            )?
+            .unwrap_or_default(),
+            align_arguments: setting(
+                &table,
+                "format",
+                "align-arguments",
+                toml::Value::as_bool,
+                "a boolean",
+            )?,
+            library_path: setting(&table, "library", "path", paths, "a list of strings")?
+                .unwrap_or_default(),
+            prompt: setting(&table, "repl", "prompt", string, a_string)?,
+            continuation_prompt: setting(&table, "repl", "continuation-prompt", string, a_string)?,
+            color: setting(&table, "repl", "color", toml::Value::as_bool, "a boolean")?,
+        })
+    }
+
+    /// The settings of `self`, or else those of `other`. The library paths of `other` are searched after those of
+    /// `self`, and its body forms are added to those of `self`.
+    #[must_use]
+    pub fn or(self, other: Self) -> Self {
+        let mut library_path = self.library_path;
+        library_path.extend(other.library_path);
+        let mut body_forms = other.body_forms;
+        body_forms.extend(self.body_forms);
+        Self {
+            width: self.width.or(other.width),
+            indent: self.indent.or(other.indent),
+            body_forms,
+            align_arguments: self.align_arguments.or(other.align_arguments),
+            library_path,
+            prompt: self.prompt.or(other.prompt),
+            continuation_prompt: self.continuation_prompt.or(other.continuation_prompt),
+            color: self.color.or(other.color),
+        }
+    }
+}
+
+/// Find the configuration file of the project `dir` is in, in it or one of its parents.
+fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE_NAME))
+        .find(|file| file.is_file())
+}
+
+/// Get the setting `key` of `section` with `convert`, which returns `None` if it is not `expected`.
+fn setting<T>(
+    table: &toml::Table,
+    section: &str,
+    key: &str,
+    convert: impl Fn(&toml::Value) -> Option<T>,
+    expected: &str,
+) -> Result<Option<T>> {
+    let Some(value) = table.get(section).and_then(|section| section.get(key)) else {
+        return Ok(None);
+    };
+    convert(value)
+        .map(Some)
+        .ok_or_else(|| eyre!("{section}.{key} should be {expected}, not {value}"))
+}
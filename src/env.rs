@@ -1,371 +1,2084 @@
-use std::rc::Rc;
+use std::io::{Read, Write};
+use std::path::Path;
 
-use crate::atom::Atom;
-use color_eyre::eyre::{eyre, Context};
-use color_eyre::Result;
+use crate::atom::{Atom, NativeFn, Rc};
+use crate::error::{Context, ErrorKind, LispError, Result};
+use crate::gc;
+use crate::lisp_error;
+use crate::module;
+#[cfg(feature = "encoding")]
+use base64::Engine as _;
+use chumsky::Parser as _;
+#[cfg(feature = "arc")]
+use im::HashMap;
+#[cfg(not(feature = "arc"))]
 use im_rc::HashMap;
+#[cfg(feature = "random")]
+use rand::seq::SliceRandom;
+#[cfg(feature = "random")]
+use rand::Rng;
 use tracing::trace;
 use tracing::{info, instrument};
 
+/// Grouping of builtins by what they can do.
+mod capability;
+/// Builtins from Rust functions with typed arguments.
+mod typed;
+pub use capability::Capability;
+pub use typed::{IntoAtomResult, Shareable, TypedFn};
+
 /// This holds bindings from symbols to atoms.
 #[derive(Clone, PartialEq, Debug)]
 pub struct Env {
-    bindings: HashMap<Rc<String>, Rc<Atom>>,
-    parent: Option<Box<Env>>,
+    bindings: HashMap<Rc<str>, Rc<Atom>>,
+    /// Documentation of the builtins bound in this environment.
+    docs: HashMap<Rc<str>, Rc<str>>,
+    parent: Option<Rc<Env>>,
+    /// The environment a closure was called from, searched after the parent.
+    caller: Option<Rc<Env>>,
+    /// The namespace definitions are made in, set with `(in-namespace name)`, or else the one of the environment
+    /// this one was made in.
+    namespace: Option<Rc<str>>,
+    /// The caches of memoized functions which [`gc::collect`] considers, shared with the parent.
+    caches: gc::Caches,
+    /// The modules which were required, and where they are looked for, shared with the parent.
+    modules: module::Modules,
 }
 
 impl Default for Env {
     #[instrument]
     fn default() -> Self {
         info!("Creating new default Env");
-        let mut env = Self {
-            bindings: HashMap::new(),
-            parent: None,
-        };
+        Env::new(Some(Rc::new(Self::builtins())))
+    }
+}
 
-        env.set(String::from("nil"), Rc::new(Atom::nil()));
-        env.set(String::from("t"), Rc::new(Atom::t()));
+impl Env {
+    /// The environment binding the builtins and special forms, which the default environment refers to.
+    fn builtins() -> Self {
+        let mut env = Self::new(None);
+        env.add_special_forms();
+        env.add_io_builtins();
+        env.add_list_builtins();
+        env.add_map_builtins();
+        env.add_arithmetic_builtins();
+        env.add_comparison_builtins();
+        env.add_math_builtins();
+        env.add_integer_builtins();
+        #[cfg(feature = "random")]
+        env.add_random_builtins();
+        env.add_string_builtins();
+        env.add_string_building_builtins();
+        env.add_string_search_builtins();
+        env.add_conversion_builtins();
+        env.add_file_builtins();
+        // wasm32-unknown-unknown has no clock and no threads to put to sleep, so reading it and sleeping panic there
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        env.add_time_builtins();
+        #[cfg(feature = "datetime")]
+        env.add_datetime_builtins();
+        env.add_process_builtins();
+        env.add_program_builtins();
+        #[cfg(feature = "encoding")]
+        env.add_encoding_builtins();
+        #[cfg(any(feature = "toml", feature = "yaml"))]
+        env.add_data_format_builtins();
+        env.add_function_builtins();
+        env
+    }
 
-        env.set(String::from("define"), Rc::new(Atom::symbol("define")));
-        env.set(String::from("defmacro"), Rc::new(Atom::symbol("defmacro")));
-        env.set(String::from("lambda"), Rc::new(Atom::symbol("lambda")));
-        env.set(String::from("if"), Rc::new(Atom::symbol("if")));
-        env.set(String::from("quote"), Rc::new(Atom::symbol("quote")));
-        env.set(String::from("apply"), Rc::new(Atom::symbol("apply")));
+    /// Bind the special forms, which are evaluated instead of called, to their own name, and `nil` and `t`.
+    fn add_special_forms(&mut self) {
+        self.set(String::from("nil"), Atom::nil());
+        self.set(String::from("t"), Atom::t());
 
-        env.add_builtin("into-pretty-string", |args| {
-            if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin into-pretty-string expected exactly one argument, got {}",
-                    args
-                ))
-            } else {
-                let arg = args.car();
-                let s = format!("{}", arg);
-                Ok(Rc::new(Atom::String(s)))
-            }
-        });
+        self.set(String::from("define"), Rc::new(Atom::symbol("define")));
+        self.set(String::from("defmacro"), Rc::new(Atom::symbol("defmacro")));
+        self.set(String::from("lambda"), Rc::new(Atom::symbol("lambda")));
+        self.set(String::from("if"), Rc::new(Atom::symbol("if")));
+        self.set(String::from("quote"), Rc::new(Atom::symbol("quote")));
+        self.set(String::from("apply"), Rc::new(Atom::symbol("apply")));
+        // wasm32-unknown-unknown has no clock, so reading it panics there
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        self.set(String::from("bench"), Rc::new(Atom::symbol("bench")));
+        self.set(String::from("gc"), Rc::new(Atom::symbol("gc")));
+        self.set(String::from("apropos"), Rc::new(Atom::symbol("apropos")));
+        self.set(String::from("doc"), Rc::new(Atom::symbol("doc")));
+        self.set(String::from("env"), Rc::new(Atom::symbol("env")));
+        self.set(String::from("require"), Rc::new(Atom::symbol("require")));
+        self.set(String::from("provide"), Rc::new(Atom::symbol("provide")));
+        self.set(
+            String::from("in-namespace"),
+            Rc::new(Atom::symbol("in-namespace")),
+        );
+        #[cfg(feature = "arc")]
+        self.set(String::from("pmap"), Rc::new(Atom::symbol("pmap")));
+    }
 
-        env.add_builtin("into-string", |args| {
-            if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin into-string expected exactly one argument, got {}",
-                    args
-                ))
-            } else {
-                let arg = args.car();
-                let a = arg.as_ref();
-                let s = format!("{:?}", a);
-                Ok(Rc::new(Atom::String(s)))
-            }
-        });
+    /// Add the builtins printing to the output and reading stdin.
+    fn add_io_builtins(&mut self) {
+        self.add_builtin(
+            "print",
+            "(print x)\nPrint x to the output, which is stdout by default, strings without quotes, and return what was printed.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin print expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    let arg = args.car();
+                    let s = format_for_print(&arg);
+                    crate::output::print(&s)?;
+                    Ok(Rc::new(Atom::String(s)))
+                }
+            },
+        );
 
-        env.add_builtin("print", |args| {
+        self.add_builtin("println", "(println x)\nPrint x to the output followed by a newline, strings without quotes, and return what was printed.", |args| {
             if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin print expected exactly one argument, got {}",
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin println expected exactly one argument, got {}",
                     args
                 ))
             } else {
                 let arg = args.car();
                 let s = format_for_print(&arg);
-                print!("{}", &s);
+                crate::output::print(&format!("{s}\n"))?;
                 Ok(Rc::new(Atom::String(s)))
             }
         });
 
-        env.add_builtin("println", |args| {
-            if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin println expected exactly one argument, got {}",
+        self.add_builtin("read-line", "(read-line)\nRead a line from stdin, without its line ending, or nil at the end of the input.", |args| {
+            if args.is_nil() {
+                let mut line = String::new();
+                let read = std::io::stdin()
+                    .read_line(&mut line)
+                    .context("While reading a line from stdin")?;
+                if read == 0 {
+                    Ok(Atom::nil())
+                } else {
+                    let line = line.strip_suffix('\n').unwrap_or(&line);
+                    let line = line.strip_suffix('\r').unwrap_or(line);
+                    Ok(Rc::new(Atom::string(line)))
+                }
+            } else {
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin read-line expected no arguments, got {}",
                     args
                 ))
-            } else {
-                let arg = args.car();
-                let s = format_for_print(&arg);
-                println!("{}", &s);
-                Ok(Rc::new(Atom::String(s)))
             }
         });
 
-        env.add_builtin("pair?", |args| {
+        self.add_builtin(
+            "read-char",
+            "(read-char)\nRead a character from stdin as a string, or nil at the end of the input.",
+            |args| {
+                if args.is_nil() {
+                    let c = read_char(&mut std::io::stdin().lock())
+                        .context("While reading a character from stdin")?;
+                    Ok(c.map_or_else(Atom::nil, |c| Rc::new(Atom::String(c.to_string().into()))))
+                } else {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin read-char expected no arguments, got {}",
+                        args
+                    ))
+                }
+            },
+        );
+    }
+
+    /// Add the builtins making and taking apart pairs, and telling symbols apart.
+    fn add_list_builtins(&mut self) {
+        self.add_builtin("pair?", "(pair? x)\nWhether x is a pair.", |args| {
             if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
+                Err(lisp_error!(
+                    Arity,
                     "Builtin pair? expected exactly one argument, got {}",
                     args
                 ))
             } else if Atom::is_list(&args.car()) {
-                Ok(Rc::new(Atom::t()))
+                Ok(Atom::t())
             } else {
-                Ok(Rc::new(Atom::nil()))
+                Ok(Atom::nil())
             }
         });
 
-        env.add_builtin("symbol?", |args| {
+        self.add_builtin("symbol?", "(symbol? x)\nWhether x is a symbol.", |args| {
             if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
+                Err(lisp_error!(
+                    Arity,
                     "Builtin symbol? expected exactly one argument, got {}",
                     args
                 ))
             } else if matches!(args.car().as_ref(), Atom::Symbol(_)) {
-                Ok(Rc::new(Atom::t()))
+                Ok(Atom::t())
             } else {
-                Ok(Rc::new(Atom::nil()))
+                Ok(Atom::nil())
             }
         });
 
-        env.add_builtin("string?", |args| {
-            if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin string? expected exactly one argument, got {}",
+        self.add_builtin(
+            "keyword?",
+            "(keyword? x)\nWhether x is a keyword, like :name.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin keyword? expected exactly one argument, got {}",
+                        args
+                    ))
+                } else if matches!(args.car().as_ref(), Atom::Keyword(_)) {
+                    Ok(Atom::t())
+                } else {
+                    Ok(Atom::nil())
+                }
+            },
+        );
+
+        self.add_builtin(
+            "car",
+            "(car pair)\nThe first element of pair, or pair itself if it is not a pair.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin car expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    Ok(args.car().car())
+                }
+            },
+        );
+
+        self.add_builtin(
+            "cdr",
+            "(cdr pair)\nThe second element of pair, or pair itself if it is not a pair.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin cdr expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    Ok(args.car().cdr())
+                }
+            },
+        );
+
+        self.add_builtin("cons", "(cons car cdr)\nA pair of car and cdr.", |args| {
+            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin cons expected exactly two arguments, got {}",
                     args
                 ))
-            } else if matches!(args.car().as_ref(), Atom::String(_)) {
-                Ok(Rc::new(Atom::t()))
             } else {
-                Ok(Rc::new(Atom::nil()))
+                let car = args.car();
+                let cdr = args.cdr().car();
+                Ok(Rc::new(Atom::Pair(car, cdr)))
             }
         });
+    }
 
-        env.add_builtin("string-length", |args| {
-            if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin string-length expected exactly one argument, got {}",
-                    args
-                ))
-            } else {
-                match args.car().as_ref() {
-                    Atom::String(s) => Ok(Rc::new(Atom::integer(s.chars().count() as i64))),
-                    a => Err(eyre!(
-                        "Builtin string-length expected its argument to be a string, but got {}",
-                        a
+    /// Add the builtins reading and updating maps.
+    fn add_map_builtins(&mut self) {
+        self.add_builtin(
+            "map?",
+            "(map? x)\nWhether x is a map, like {:a 1}.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin map? expected exactly one argument, got {}",
+                        args
+                    ))
+                } else if matches!(args.car().as_ref(), Atom::Map(_)) {
+                    Ok(Atom::t())
+                } else {
+                    Ok(Atom::nil())
+                }
+            },
+        );
+
+        self.add_builtin(
+            "map-get",
+            "(map-get map key [default])\nThe value of key in map, or default if it has none. default defaults to nil.",
+            |args| {
+                let items = Atom::list_to_vec(&args)?;
+                let (map, key, default) = match items.as_slice() {
+                    [map, key] => (map, key, Atom::nil()),
+                    [map, key, default] => (map, key, default.clone()),
+                    _ => {
+                        return Err(lisp_error!(
+                            Arity,
+                            "Builtin map-get expected two or three arguments, got {}",
+                            args
+                        ))
+                    }
+                };
+                let map = map.get_map().context("As first argument")?;
+                Ok(map.get(key).cloned().unwrap_or(default))
+            },
+        );
+
+        self.add_builtin(
+            "map-set",
+            "(map-set map key value)\nA map like map, but with key bound to value.",
+            |args| {
+                let items = Atom::list_to_vec(&args)?;
+                let [map, key, value] = items.as_slice() else {
+                    return Err(lisp_error!(
+                        Arity,
+                        "Builtin map-set expected exactly three arguments, got {}",
+                        args
+                    ));
+                };
+                let map = map.get_map().context("As first argument")?;
+                Ok(Rc::new(Atom::Map(map.update(key.clone(), value.clone()))))
+            },
+        );
+    }
+
+    /// Add the arithmetic builtins, and those rounding numbers.
+    fn add_arithmetic_builtins(&mut self) {
+        self.add_builtin(
+            "+",
+            "(+ x ...)\nThe sum of the numbers, or 0 if there are none.",
+            |args| {
+                let numbers = get_number_arguments(&args)?;
+                // `sum` starts from -0, which would make `(+)` print as -0
+                Ok(Atom::number(numbers.iter().fold(0.0, |acc, x| acc + x)))
+            },
+        );
+
+        self.add_builtin(
+            "-",
+            "(- x y ...)\nx minus the other numbers, or the negation of x if it is the only one.",
+            |args| {
+                let numbers = get_number_arguments(&args)?;
+                match numbers.split_first() {
+                    None => Err(lisp_error!(
+                        Arity,
+                        "Builtin - expected at least one argument, got nil"
                     )),
+                    Some((first, [])) => Ok(Atom::number(-first)),
+                    Some((first, rest)) => {
+                        Ok(Atom::number(rest.iter().fold(*first, |acc, x| acc - x)))
+                    }
                 }
+            },
+        );
+
+        self.add_builtin(
+            "*",
+            "(* x ...)\nThe product of the numbers, or 1 if there are none.",
+            |args| {
+                let numbers = get_number_arguments(&args)?;
+                Ok(Atom::number(numbers.iter().product()))
+            },
+        );
+
+        self.add_builtin("/", "(/ x y ...)\nx divided by the other numbers, or the inverse of x if it is the only one.", |args| {
+            let numbers = get_number_arguments(&args)?;
+            match numbers.split_first() {
+                None => Err(lisp_error!(Arity, "Builtin / expected at least one argument, got nil")),
+                Some((first, [])) => Ok(Atom::number(1.0 / first)),
+                Some((first, rest)) => Ok(Atom::number(rest.iter().fold(*first, |acc, x| acc / x))),
             }
         });
 
-        env.add_builtin("car", |args| {
-            if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin car expected exactly one argument, got {}",
+        self.add_builtin(
+            "%",
+            "(% x y)\nThe remainder of dividing x by y, with the sign of x.",
+            |args| {
+                if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin % expected exactly two arguments, got {}",
+                        args
+                    ))
+                } else {
+                    let arg1 = args.car().get_number().context("As first argument")?;
+                    let arg2 = args
+                        .cdr()
+                        .car()
+                        .get_number()
+                        .context("As second argument")?;
+                    Ok(Atom::number(arg1 % arg2))
+                }
+            },
+        );
+
+        self.add_builtin("abs", "(abs x)\nThe absolute value of x.", |args| {
+            let x = get_single_number_argument("abs", &args)?;
+            Ok(Atom::number(x.abs()))
+        });
+
+        self.add_builtin(
+            "floor",
+            "(floor x)\nThe largest integer less than or equal to x.",
+            |args| {
+                let x = get_single_number_argument("floor", &args)?;
+                Ok(Atom::number(x.floor()))
+            },
+        );
+
+        self.add_builtin(
+            "ceiling",
+            "(ceiling x)\nThe smallest integer greater than or equal to x.",
+            |args| {
+                let x = get_single_number_argument("ceiling", &args)?;
+                Ok(Atom::number(x.ceil()))
+            },
+        );
+
+        self.add_builtin(
+            "round",
+            "(round x)\nThe integer nearest to x, rounding half-way cases away from zero.",
+            |args| {
+                let x = get_single_number_argument("round", &args)?;
+                Ok(Atom::number(x.round()))
+            },
+        );
+
+        self.add_builtin("truncate", "(truncate x)\nThe integer part of x.", |args| {
+            let x = get_single_number_argument("truncate", &args)?;
+            Ok(Atom::number(x.trunc()))
+        });
+    }
+
+    /// Add the builtins comparing numbers.
+    fn add_comparison_builtins(&mut self) {
+        self.add_builtin(
+            "=",
+            "(= a b)\nWhether a and b are structurally equal.",
+            |args| {
+                if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin = expected exactly two arguments, got {}",
+                        args
+                    ))
+                } else {
+                    let arg1 = args.car();
+                    let arg2 = args.cdr().car();
+                    Ok(Atom::bool(arg1 == arg2))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "<",
+            "(< x y ...)\nWhether the numbers are strictly increasing.",
+            |args| {
+                let numbers = get_number_arguments(&args)?;
+                if numbers.is_empty() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin < expected at least one argument, got nil"
+                    ))
+                } else {
+                    Ok(Atom::bool(numbers.windows(2).all(|w| w[0] < w[1])))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "<=",
+            "(<= x y ...)\nWhether the numbers are increasing.",
+            |args| {
+                let numbers = get_number_arguments(&args)?;
+                if numbers.is_empty() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin <= expected at least one argument, got nil"
+                    ))
+                } else {
+                    Ok(Atom::bool(numbers.windows(2).all(|w| w[0] <= w[1])))
+                }
+            },
+        );
+
+        self.add_builtin(
+            ">",
+            "(> x y ...)\nWhether the numbers are strictly decreasing.",
+            |args| {
+                let numbers = get_number_arguments(&args)?;
+                if numbers.is_empty() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin > expected at least one argument, got nil"
+                    ))
+                } else {
+                    Ok(Atom::bool(numbers.windows(2).all(|w| w[0] > w[1])))
+                }
+            },
+        );
+
+        self.add_builtin(
+            ">=",
+            "(>= x y ...)\nWhether the numbers are decreasing.",
+            |args| {
+                let numbers = get_number_arguments(&args)?;
+                if numbers.is_empty() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin >= expected at least one argument, got nil"
+                    ))
+                } else {
+                    Ok(Atom::bool(numbers.windows(2).all(|w| w[0] >= w[1])))
+                }
+            },
+        );
+
+        self.add_builtin("min", "(min x ...)\nThe smallest of the numbers.", |args| {
+            let numbers = get_number_arguments(&args)?;
+            numbers
+                .into_iter()
+                .reduce(f64::min)
+                .map(Atom::number)
+                .ok_or_else(|| {
+                    lisp_error!(Arity, "Builtin min expected at least one argument, got nil")
+                })
+        });
+
+        self.add_builtin("max", "(max x ...)\nThe largest of the numbers.", |args| {
+            let numbers = get_number_arguments(&args)?;
+            numbers
+                .into_iter()
+                .reduce(f64::max)
+                .map(Atom::number)
+                .ok_or_else(|| {
+                    lisp_error!(Arity, "Builtin max expected at least one argument, got nil")
+                })
+        });
+    }
+
+    /// Add the builtins computing powers, logarithms and trigonometric functions, and the constants `pi` and `e`.
+    fn add_math_builtins(&mut self) {
+        self.add_builtin(
+            "sqrt",
+            "(sqrt x)\nThe square root of the non-negative number x.",
+            |args| {
+                let x = get_single_number_argument("sqrt", &args)?;
+                if x < 0.0 {
+                    Err(lisp_error!(
+                        Value,
+                        "Builtin sqrt expected a non-negative number, but got {}",
+                        x
+                    ))
+                } else {
+                    Ok(Atom::number(x.sqrt()))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "expt",
+            "(expt base exponent)\nbase raised to the power exponent.",
+            |args| {
+                if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin expt expected exactly two arguments, got {}",
+                        args
+                    ))
+                } else {
+                    let base = args.car().get_number().context("As first argument")?;
+                    let exponent = args
+                        .cdr()
+                        .car()
+                        .get_number()
+                        .context("As second argument")?;
+                    Ok(Atom::number(base.powf(exponent)))
+                }
+            },
+        );
+
+        self.add_builtin("sin", "(sin x)\nThe sine of x, in radians.", |args| {
+            let x = get_single_number_argument("sin", &args)?;
+            Ok(Atom::number(x.sin()))
+        });
+
+        self.add_builtin("cos", "(cos x)\nThe cosine of x, in radians.", |args| {
+            let x = get_single_number_argument("cos", &args)?;
+            Ok(Atom::number(x.cos()))
+        });
+
+        self.add_builtin("tan", "(tan x)\nThe tangent of x, in radians.", |args| {
+            let x = get_single_number_argument("tan", &args)?;
+            Ok(Atom::number(x.tan()))
+        });
+
+        self.add_builtin("atan2", "(atan2 y x)\nThe angle of the point (x, y) from the x axis, in radians between -pi and pi.", |args| {
+            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin atan2 expected exactly two arguments, got {}",
                     args
                 ))
             } else {
-                Ok(args.car().car())
+                let y = args.car().get_number().context("As first argument")?;
+                let x = args
+                    .cdr()
+                    .car()
+                    .get_number()
+                    .context("As second argument")?;
+                Ok(Atom::number(y.atan2(x)))
             }
         });
 
-        env.add_builtin("cdr", |args| {
-            if args.is_nil() || !args.cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin cdr expected exactly one argument, got {}",
+        self.add_builtin(
+            "log",
+            "(log x)\nThe natural logarithm of the positive number x.",
+            |args| {
+                let x = get_single_number_argument("log", &args)?;
+                if x <= 0.0 {
+                    Err(lisp_error!(
+                        Value,
+                        "Builtin log expected a positive number, but got {}",
+                        x
+                    ))
+                } else {
+                    Ok(Atom::number(x.ln()))
+                }
+            },
+        );
+
+        self.add_builtin("exp", "(exp x)\ne raised to the power x.", |args| {
+            let x = get_single_number_argument("exp", &args)?;
+            Ok(Atom::number(x.exp()))
+        });
+
+        self.set(String::from("pi"), Atom::number(std::f64::consts::PI));
+        self.set(String::from("e"), Atom::number(std::f64::consts::E));
+    }
+
+    /// Add the builtins working on the bits and divisors of integers.
+    fn add_integer_builtins(&mut self) {
+        self.add_builtin(
+            "bit-and",
+            "(bit-and n ...)\nThe bitwise and of the integers, or -1 if there are none.",
+            |args| {
+                let integers = get_integer_arguments(&args)?;
+                Ok(Atom::integer(
+                    integers.into_iter().fold(-1, |acc, x| acc & x),
+                ))
+            },
+        );
+
+        self.add_builtin(
+            "bit-or",
+            "(bit-or n ...)\nThe bitwise or of the integers, or 0 if there are none.",
+            |args| {
+                let integers = get_integer_arguments(&args)?;
+                Ok(Atom::integer(
+                    integers.into_iter().fold(0, |acc, x| acc | x),
+                ))
+            },
+        );
+
+        self.add_builtin(
+            "bit-xor",
+            "(bit-xor n ...)\nThe bitwise exclusive or of the integers, or 0 if there are none.",
+            |args| {
+                let integers = get_integer_arguments(&args)?;
+                Ok(Atom::integer(
+                    integers.into_iter().fold(0, |acc, x| acc ^ x),
+                ))
+            },
+        );
+
+        self.add_builtin(
+            "bit-not",
+            "(bit-not n)\nThe bitwise complement of the integer n.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin bit-not expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    Ok(Atom::integer(!args.car().get_integer()?))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "shift-left",
+            "(shift-left n amount)\nThe integer n with its bits shifted left by amount.",
+            |args| {
+                let (x, amount) = get_shift_arguments("shift-left", &args)?;
+                Ok(Atom::integer(x << amount))
+            },
+        );
+
+        self.add_builtin("shift-right", "(shift-right n amount)\nThe integer n with its bits shifted right by amount, keeping its sign.", |args| {
+            let (x, amount) = get_shift_arguments("shift-right", &args)?;
+            Ok(Atom::integer(x >> amount))
+        });
+
+        self.add_builtin(
+            "gcd",
+            "(gcd n ...)\nThe greatest common divisor of the integers, or 0 if there are none.",
+            |args| {
+                let integers = get_integer_arguments(&args)?;
+                let result = integers.into_iter().map(i64::unsigned_abs).fold(0, gcd);
+                #[allow(clippy::cast_precision_loss)]
+                Ok(Atom::number(result as f64))
+            },
+        );
+
+        self.add_builtin(
+            "lcm",
+            "(lcm n ...)\nThe least common multiple of the integers, or 1 if there are none.",
+            |args| {
+                let integers = get_integer_arguments(&args)?;
+                let mut result: u64 = 1;
+                for x in integers {
+                    if x == 0 {
+                        return Ok(Atom::integer(0));
+                    }
+                    let x = x.unsigned_abs();
+                    result = (result / gcd(result, x)).checked_mul(x).ok_or_else(|| {
+                        lisp_error!(Value, "Builtin lcm overflowed while computing the result")
+                    })?;
+                }
+                #[allow(clippy::cast_precision_loss)]
+                Ok(Atom::number(result as f64))
+            },
+        );
+    }
+
+    /// Add the builtins giving random numbers and identifiers, and picking from lists at random.
+    #[cfg(feature = "random")]
+    fn add_random_builtins(&mut self) {
+        self.add_builtin(
+            "random",
+            "(random)\nA random number between 0 included and 1 excluded.",
+            |args| {
+                if args.is_nil() {
+                    Ok(Atom::number(rand::thread_rng().gen()))
+                } else {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin random expected no arguments, got {}",
+                        args
+                    ))
+                }
+            },
+        );
+
+        self.add_builtin("random-int", "(random-int [low] high)\nA random integer between low included and high excluded. low defaults to 0.", |args| {
+            let (low, high) = if args.is_nil() || !args.cdr().cdr().is_nil() {
+                return Err(lisp_error!(
+                    Arity,
+                    "Builtin random-int expected one or two arguments, got {}",
                     args
+                ));
+            } else if args.cdr().is_nil() {
+                (0, args.car().get_integer().context("As first argument")?)
+            } else {
+                (
+                    args.car().get_integer().context("As first argument")?,
+                    args.cdr()
+                        .car()
+                        .get_integer()
+                        .context("As second argument")?,
+                )
+            };
+            if low < high {
+                Ok(Atom::integer(rand::thread_rng().gen_range(low..high)))
+            } else {
+                Err(lisp_error!(
+                    Value,
+                    "Builtin random-int expected a non-empty range, but got {} to {}",
+                    low,
+                    high
                 ))
+            }
+        });
+
+        self.add_builtin(
+            "random-choice",
+            "(random-choice list)\nA random element of the non-empty list.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin random-choice expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    let items = Atom::list_to_vec(&args.car())?;
+                    items
+                        .choose(&mut rand::thread_rng())
+                        .cloned()
+                        .ok_or_else(|| {
+                            lisp_error!(
+                                Value,
+                                "Builtin random-choice expected a non-empty list, got nil"
+                            )
+                        })
+                }
+            },
+        );
+
+        self.add_builtin(
+            "shuffle",
+            "(shuffle list)\nThe elements of list in a random order.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin shuffle expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    let mut items = Atom::list_to_vec(&args.car())?;
+                    items.shuffle(&mut rand::thread_rng());
+                    Ok(Atom::list_from_vec(items))
+                }
+            },
+        );
+
+        self.add_builtin("uuid", "(uuid)\nA random UUID, as a string.", |args| {
+            if args.is_nil() {
+                Ok(Rc::new(Atom::String(
+                    uuid::Uuid::new_v4().to_string().into(),
+                )))
             } else {
-                Ok(args.car().cdr())
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin uuid expected no arguments, got {}",
+                    args
+                ))
             }
         });
+    }
 
-        env.add_builtin("cons", |args| {
-            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin cons expected exactly two arguments, got {}",
+    /// Add the builtins telling strings apart and taking them apart.
+    fn add_string_builtins(&mut self) {
+        self.add_builtin("string?", "(string? x)\nWhether x is a string.", |args| {
+            if args.is_nil() || !args.cdr().is_nil() {
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin string? expected exactly one argument, got {}",
                     args
                 ))
+            } else if matches!(args.car().as_ref(), Atom::String(_)) {
+                Ok(Atom::t())
             } else {
-                let car = args.car();
-                let cdr = args.cdr().car();
-                Ok(Rc::new(Atom::Pair(car, cdr)))
+                Ok(Atom::nil())
             }
         });
 
-        env.add_builtin("+", |args| {
+        self.add_builtin(
+            "string-length",
+            "(string-length s)\nThe number of characters in the string s.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin string-length expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    match args.car().as_ref() {
+                        Atom::String(s) => Ok(Atom::integer(i64::try_from(s.chars().count())?)),
+                        a => Err(lisp_error!(
+                        Type,
+                        "Builtin string-length expected its argument to be a string, but got {}",
+                        a
+                    )),
+                    }
+                }
+            },
+        );
+
+        self.add_builtin("string-ref", "(string-ref s index)\nThe character of s at index, as a string.", |args| {
             if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin + expected exactly two arguments, got {}",
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin string-ref expected exactly two arguments, got {}",
                     args
                 ))
             } else {
-                let arg1 = args.car().get_number().context("As first argument")?;
-                let arg2 = args
-                    .cdr()
-                    .car()
-                    .get_number()
-                    .context("As second argument")?;
-                Ok(Rc::new(Atom::number(arg1 + arg2)))
+                let s = args.car().get_string().context("As first argument")?;
+                let index = get_index(&args.cdr().car()).context("As second argument")?;
+                s.chars()
+                    .nth(index)
+                    .map(|c| Rc::new(Atom::String(c.to_string().into())))
+                    .ok_or_else(|| {
+                        lisp_error!(
+                            Value,
+                            "Builtin string-ref got the index {}, which is out of bounds for a string of length {}",
+                            index,
+                            s.chars().count()
+                        )
+                    })
             }
         });
 
-        env.add_builtin("-", |args| {
-            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin - expected exactly two arguments, got {}",
+        self.add_builtin("substring", "(substring s start [end])\nThe characters of s from index start included to end excluded. end defaults to the length of s.", |args| {
+            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().cdr().is_nil() {
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin substring expected two or three arguments, got {}",
                     args
                 ))
             } else {
-                let arg1 = args.car().get_number().context("As first argument")?;
-                let arg2 = args
-                    .cdr()
-                    .car()
-                    .get_number()
-                    .context("As second argument")?;
-                Ok(Rc::new(Atom::number(arg1 - arg2)))
+                let s = args.car().get_string().context("As first argument")?;
+                let length = s.chars().count();
+                let start = get_index(&args.cdr().car()).context("As second argument")?;
+                let end = if args.cdr().cdr().is_nil() {
+                    length
+                } else {
+                    get_index(&args.cdr().cdr().car()).context("As third argument")?
+                };
+                if start > end || end > length {
+                    Err(lisp_error!(
+                        Value,
+                        "Builtin substring got the range {} to {}, which is out of bounds for a string of length {}",
+                        start,
+                        end,
+                        length
+                    ))
+                } else {
+                    Ok(Rc::new(Atom::String(
+                        s.chars().skip(start).take(end - start).collect::<String>().into(),
+                    )))
+                }
             }
         });
+    }
 
-        env.add_builtin("*", |args| {
+    /// Add the builtins making strings out of others.
+    fn add_string_building_builtins(&mut self) {
+        self.add_builtin(
+            "string-append",
+            "(string-append s ...)\nThe strings joined together.",
+            |args| {
+                let mut result = String::new();
+                let mut atom = args;
+                let mut index = 1;
+                while !atom.is_nil() {
+                    let s = atom
+                        .car()
+                        .get_string()
+                        .context(format!("As argument number {index}"))?;
+                    result.push_str(&s);
+                    atom = atom.strict_cdr()?;
+                    index += 1;
+                }
+                Ok(Rc::new(Atom::String(result.into())))
+            },
+        );
+
+        self.add_builtin("string-split", "(string-split s separator)\nThe list of the parts of s between occurrences of the non-empty string separator.", |args| {
             if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin * expected exactly two arguments, got {}",
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin string-split expected exactly two arguments, got {}",
                     args
                 ))
             } else {
-                let arg1 = args.car().get_number().context("As first argument")?;
-                let arg2 = args
+                let s = args.car().get_string().context("As first argument")?;
+                let separator = args
                     .cdr()
                     .car()
-                    .get_number()
+                    .get_string()
                     .context("As second argument")?;
-                Ok(Rc::new(Atom::number(arg1 * arg2)))
+                if separator.is_empty() {
+                    Err(lisp_error!(Value, "Builtin string-split expected a non-empty separator"))
+                } else {
+                    Ok(Atom::list_from_vec(
+                        s.split(&*separator)
+                            .map(|part| Rc::new(Atom::string(part)))
+                            .collect(),
+                    ))
+                }
             }
         });
 
-        env.add_builtin("/", |args| {
-            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin / expected exactly two arguments, got {}",
+        self.add_builtin("string-join", "(string-join strings [separator])\nThe list of strings joined together, with separator between them. separator defaults to the empty string.", |args| {
+            if args.is_nil() || !args.cdr().cdr().is_nil() {
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin string-join expected one or two arguments, got {}",
                     args
                 ))
             } else {
-                let arg1 = args.car().get_number().context("As first argument")?;
-                let arg2 = args
-                    .cdr()
-                    .car()
-                    .get_number()
-                    .context("As second argument")?;
-                Ok(Rc::new(Atom::number(arg1 / arg2)))
+                let parts = Atom::list_to_vec(&args.car())
+                    .context("As first argument")?
+                    .iter()
+                    .map(|part| part.get_string())
+                    .collect::<Result<Vec<_>>>()
+                    .context("As first argument")?;
+                let separator = if args.cdr().is_nil() {
+                    Rc::from("")
+                } else {
+                    args.cdr()
+                        .car()
+                        .get_string()
+                        .context("As second argument")?
+                };
+                Ok(Rc::new(Atom::String(parts.join(&separator).into())))
             }
         });
 
-        env.add_builtin("%", |args| {
-            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin % expected exactly two arguments, got {}",
+        self.add_builtin(
+            "string-upcase",
+            "(string-upcase s)\ns in uppercase.",
+            |args| {
+                let s = get_single_string_argument("string-upcase", &args)?;
+                Ok(Rc::new(Atom::String(s.to_uppercase().into())))
+            },
+        );
+
+        self.add_builtin(
+            "string-downcase",
+            "(string-downcase s)\ns in lowercase.",
+            |args| {
+                let s = get_single_string_argument("string-downcase", &args)?;
+                Ok(Rc::new(Atom::String(s.to_lowercase().into())))
+            },
+        );
+    }
+
+    /// Add the builtins searching strings, and trimming and replacing parts of them.
+    fn add_string_search_builtins(&mut self) {
+        self.add_builtin(
+            "string-contains?",
+            "(string-contains? s part)\nWhether the string part occurs in s.",
+            |args| {
+                let (s, needle) = get_two_string_arguments("string-contains?", &args)?;
+                Ok(Atom::bool(s.contains(&*needle)))
+            },
+        );
+
+        self.add_builtin(
+            "string-starts-with?",
+            "(string-starts-with? s prefix)\nWhether s starts with the string prefix.",
+            |args| {
+                let (s, prefix) = get_two_string_arguments("string-starts-with?", &args)?;
+                Ok(Atom::bool(s.starts_with(&*prefix)))
+            },
+        );
+
+        self.add_builtin(
+            "string-ends-with?",
+            "(string-ends-with? s suffix)\nWhether s ends with the string suffix.",
+            |args| {
+                let (s, suffix) = get_two_string_arguments("string-ends-with?", &args)?;
+                Ok(Atom::bool(s.ends_with(&*suffix)))
+            },
+        );
+
+        self.add_builtin("string-index-of", "(string-index-of s part)\nThe index of the first character of the first occurrence of part in s, or nil if there is none.", |args| {
+            let (s, needle) = get_two_string_arguments("string-index-of", &args)?;
+            match s.find(&*needle) {
+                Some(byte_index) => {
+                    let index = s[..byte_index].chars().count();
+                    Ok(Atom::integer(i64::try_from(index)?))
+                }
+                None => Ok(Atom::nil()),
+            }
+        });
+
+        self.add_builtin("string-replace", "(string-replace s from to)\ns with every occurrence of the non-empty string from replaced by to.", |args| {
+            if args.is_nil()
+                || args.cdr().is_nil()
+                || args.cdr().cdr().is_nil()
+                || !args.cdr().cdr().cdr().is_nil()
+            {
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin string-replace expected exactly three arguments, got {}",
                     args
                 ))
             } else {
-                let arg1 = args.car().get_number().context("As first argument")?;
-                let arg2 = args
+                let s = args.car().get_string().context("As first argument")?;
+                let from = args
                     .cdr()
                     .car()
-                    .get_number()
+                    .get_string()
                     .context("As second argument")?;
-                Ok(Rc::new(Atom::number(arg1 % arg2)))
+                let to = args
+                    .cdr()
+                    .cdr()
+                    .car()
+                    .get_string()
+                    .context("As third argument")?;
+                if from.is_empty() {
+                    Err(lisp_error!(
+                        Value,
+                        "Builtin string-replace expected a non-empty string to replace"
+                    ))
+                } else {
+                    Ok(Rc::new(Atom::String(s.replace(&*from, &to).into())))
+                }
             }
         });
 
-        env.add_builtin("=", |args| {
-            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin = expected exactly two arguments, got {}",
+        self.add_builtin(
+            "string-trim",
+            "(string-trim s)\ns without whitespace at its start and end.",
+            |args| {
+                let s = get_single_string_argument("string-trim", &args)?;
+                Ok(Rc::new(Atom::string(s.trim())))
+            },
+        );
+
+        self.add_builtin(
+            "string-trim-left",
+            "(string-trim-left s)\ns without whitespace at its start.",
+            |args| {
+                let s = get_single_string_argument("string-trim-left", &args)?;
+                Ok(Rc::new(Atom::string(s.trim_start())))
+            },
+        );
+
+        self.add_builtin(
+            "string-trim-right",
+            "(string-trim-right s)\ns without whitespace at its end.",
+            |args| {
+                let s = get_single_string_argument("string-trim-right", &args)?;
+                Ok(Rc::new(Atom::string(s.trim_end())))
+            },
+        );
+    }
+
+    /// Add the builtins converting between strings and other values.
+    fn add_conversion_builtins(&mut self) {
+        self.add_builtin(
+            "into-pretty-string",
+            "(into-pretty-string x)\nPrint x into a string, like the REPL does.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin into-pretty-string expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    let arg = args.car();
+                    let s = format!("{arg}");
+                    Ok(Rc::new(Atom::String(s.into())))
+                }
+            },
+        );
+
+        self.add_builtin("into-string", "(into-string x)\nPrint x into a string on a single line, with strings quoted so that it can be read back.", |args| {
+            if args.is_nil() || !args.cdr().is_nil() {
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin into-string expected exactly one argument, got {}",
                     args
                 ))
             } else {
-                let arg1 = args.car();
-                let arg2 = args.cdr().car();
-                Ok(Rc::new(Atom::bool(arg1 == arg2)))
+                let arg = args.car();
+                let a = arg.as_ref();
+                let s = format!("{a:?}");
+                Ok(Rc::new(Atom::String(s.into())))
             }
         });
 
-        env.add_builtin("<", |args| {
-            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin < expected exactly two arguments, got {}",
+        self.add_builtin(
+            "string->symbol",
+            "(string->symbol s)\nThe symbol named by the non-empty string s.",
+            |args| {
+                let s = get_single_string_argument("string->symbol", &args)?;
+                if s.is_empty() {
+                    Err(lisp_error!(
+                        Value,
+                        "Builtin string->symbol expected a non-empty string"
+                    ))
+                } else {
+                    Ok(Rc::new(Atom::Symbol(s)))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "symbol->string",
+            "(symbol->string symbol)\nThe name of symbol as a string.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin symbol->string expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    Ok(Rc::new(Atom::String(args.car().get_symbol_name()?)))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "string->list",
+            "(string->list s)\nThe list of the characters of s, each as a string.",
+            |args| {
+                let s = get_single_string_argument("string->list", &args)?;
+                Ok(Atom::list_from_vec(
+                    s.chars()
+                        .map(|c| Rc::new(Atom::String(c.to_string().into())))
+                        .collect(),
+                ))
+            },
+        );
+
+        self.add_builtin(
+            "list->string",
+            "(list->string strings)\nThe list of strings joined together.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin list->string expected exactly one argument, got {}",
+                        args
+                    ))
+                } else {
+                    let s = Atom::list_to_vec(&args.car())?
+                        .iter()
+                        .map(|c| c.get_string())
+                        .collect::<Result<Vec<_>>>()?
+                        .concat();
+                    Ok(Rc::new(Atom::String(s.into())))
+                }
+            },
+        );
+    }
+
+    /// Add the builtins reading and changing files and directories.
+    fn add_file_builtins(&mut self) {
+        self.add_builtin(
+            "slurp",
+            "(slurp path)\nThe contents of the file at path.",
+            |args| {
+                let path = get_single_string_argument("slurp", &args)?;
+                let contents = std::fs::read_to_string(&*path)
+                    .context(format!("While reading file {path}"))?;
+                Ok(Rc::new(Atom::String(contents.into())))
+            },
+        );
+
+        self.add_builtin("spit", "(spit path contents [append])\nWrite the string contents to the file at path, replacing it unless append is true, and return contents.", |args| {
+            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().cdr().is_nil() {
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin spit expected two or three arguments, got {}",
                     args
                 ))
             } else {
-                let arg1 = args.car().get_number().context("As first argument")?;
-                let arg2 = args
+                let path = args.car().get_string().context("As first argument")?;
+                let contents = args
                     .cdr()
                     .car()
-                    .get_number()
+                    .get_string()
                     .context("As second argument")?;
-                Ok(Rc::new(Atom::bool(arg1 < arg2)))
+                let append = args.cdr().cdr().car().as_bool();
+                let mut file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .append(append)
+                    .truncate(!append)
+                    .open(&*path)
+                    .context(format!("While opening file {path}"))?;
+                file.write_all(contents.as_bytes())
+                    .context(format!("While writing to file {path}"))?;
+                Ok(Rc::new(Atom::String(contents)))
             }
         });
 
-        env.add_builtin("<=", |args| {
+        self.add_builtin(
+            "file-exists?",
+            "(file-exists? path)\nWhether there is a file or directory at path.",
+            |args| {
+                let path = get_single_string_argument("file-exists?", &args)?;
+                Ok(Atom::bool(std::path::Path::new(&*path).exists()))
+            },
+        );
+
+        self.add_builtin(
+            "delete-file",
+            "(delete-file path)\nDelete the file at path.",
+            |args| {
+                let path = get_single_string_argument("delete-file", &args)?;
+                std::fs::remove_file(&*path).context(format!("While deleting file {path}"))?;
+                Ok(Atom::t())
+            },
+        );
+
+        self.add_builtin(
+            "list-directory",
+            "(list-directory path)\nThe sorted names of the entries of the directory at path.",
+            |args| {
+                let path = get_single_string_argument("list-directory", &args)?;
+                let mut names = std::fs::read_dir(&*path)
+                    .context(format!("While listing directory {path}"))?
+                    .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                    .collect::<Result<Vec<_>>>()
+                    .context(format!("While listing directory {path}"))?;
+                names.sort();
+                Ok(Atom::list_from_vec(
+                    names
+                        .into_iter()
+                        .map(|name| Rc::new(Atom::String(name.into())))
+                        .collect(),
+                ))
+            },
+        );
+
+        self.add_builtin(
+            "make-directory",
+            "(make-directory path)\nCreate the directory at path, along with its missing parents.",
+            |args| {
+                let path = get_single_string_argument("make-directory", &args)?;
+                std::fs::create_dir_all(&*path)
+                    .context(format!("While creating directory {path}"))?;
+                Ok(Atom::t())
+            },
+        );
+    }
+
+    /// Add the builtins reading the clock and waiting.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    fn add_time_builtins(&mut self) {
+        self.add_builtin(
+            "current-time",
+            "(current-time)\nThe number of seconds since the unix epoch.",
+            |args| {
+                if args.is_nil() {
+                    let since_epoch = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .context("While getting the current time")?;
+                    Ok(Atom::number(since_epoch.as_secs_f64()))
+                } else {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin current-time expected no arguments, got {}",
+                        args
+                    ))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "monotonic-time",
+            "(monotonic-time)\nA number of seconds which only increases, to measure durations.",
+            |args| {
+                if args.is_nil() {
+                    static START: std::sync::OnceLock<std::time::Instant> =
+                        std::sync::OnceLock::new();
+                    let start = START.get_or_init(std::time::Instant::now);
+                    Ok(Atom::number(start.elapsed().as_secs_f64()))
+                } else {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin monotonic-time expected no arguments, got {}",
+                        args
+                    ))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "sleep",
+            "(sleep seconds)\nWait for the given number of seconds.",
+            |args| {
+                let seconds = get_single_number_argument("sleep", &args)?;
+                let duration = std::time::Duration::try_from_secs_f64(seconds).map_err(|_| {
+                    lisp_error!(
+                        Value,
+                        "Builtin sleep expected a non-negative number of seconds, but got {}",
+                        seconds
+                    )
+                })?;
+                std::thread::sleep(duration);
+                Ok(Atom::nil())
+            },
+        );
+    }
+
+    /// Add the builtins formatting and parsing times.
+    #[cfg(feature = "datetime")]
+    fn add_datetime_builtins(&mut self) {
+        self.add_builtin("time-format", "(time-format seconds format)\nFormat a number of seconds since the unix epoch in UTC, with a strftime-like format.", |args| {
             if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin <= expected exactly two arguments, got {}",
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin time-format expected exactly two arguments, got {}",
                     args
                 ))
             } else {
-                let arg1 = args.car().get_number().context("As first argument")?;
-                let arg2 = args
+                let seconds = args.car().get_number().context("As first argument")?;
+                let format = args
                     .cdr()
                     .car()
-                    .get_number()
+                    .get_string()
                     .context("As second argument")?;
-                Ok(Rc::new(Atom::bool(arg1 <= arg2)))
+                let time = seconds_to_datetime(seconds)?;
+                let items = chrono::format::StrftimeItems::new(&format).collect::<Vec<_>>();
+                if items.contains(&chrono::format::Item::Error) {
+                    Err(lisp_error!(
+                        Value,
+                        "Builtin time-format got an invalid format string {:?}",
+                        format
+                    ))
+                } else {
+                    Ok(Rc::new(Atom::String(
+                        time.format_with_items(items.into_iter()).to_string().into(),
+                    )))
+                }
             }
         });
 
-        env.add_builtin(">", |args| {
+        self.add_builtin("time-parse", "(time-parse s format)\nParse s with a strftime-like format, into a number of seconds since the unix epoch.", |args| {
+            let (s, format) = get_two_string_arguments("time-parse", &args)?;
+            let time = chrono::DateTime::parse_from_str(&s, &format)
+                .map(|time| time.with_timezone(&chrono::Utc))
+                .or_else(|_| {
+                    chrono::NaiveDateTime::parse_from_str(&s, &format).map(|time| time.and_utc())
+                })
+                .or_else(|_| {
+                    chrono::NaiveDate::parse_from_str(&s, &format)
+                        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+                })
+                .context(format!(
+                    "Builtin time-parse could not parse {s:?} with format {format:?}"
+                ))?;
+            Ok(Atom::number(datetime_to_seconds(time)))
+        });
+    }
+
+    /// Add the builtins reading and changing the environment variables of the process, and exiting it.
+    fn add_process_builtins(&mut self) {
+        self.add_builtin(
+            "getenv",
+            "(getenv name)\nThe value of the environment variable name, or nil if it is not set.",
+            |args| {
+                let name = get_single_string_argument("getenv", &args)?;
+                match std::env::var(&*name) {
+                    Ok(value) => Ok(Rc::new(Atom::String(value.into()))),
+                    Err(std::env::VarError::NotPresent) => Ok(Atom::nil()),
+                    Err(e) => Err(e).context(format!("While reading environment variable {name}")),
+                }
+            },
+        );
+
+        self.add_builtin("setenv", "(setenv name value)\nSet the environment variable name to the string value, or unset it if value is nil, and return value.", |args| {
             if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin > expected exactly two arguments, got {}",
+                Err(lisp_error!(
+                    Arity,
+                    "Builtin setenv expected exactly two arguments, got {}",
                     args
                 ))
             } else {
-                let arg1 = args.car().get_number().context("As first argument")?;
-                let arg2 = args
-                    .cdr()
-                    .car()
-                    .get_number()
-                    .context("As second argument")?;
-                Ok(Rc::new(Atom::bool(arg1 > arg2)))
+                let name = args.car().get_string().context("As first argument")?;
+                if name.is_empty() || name.contains(['=', '\0']) {
+                    return Err(lisp_error!(
+                        Value,
+                        "Builtin setenv got an invalid environment variable name {:?}",
+                        name
+                    ));
+                }
+                let value = args.cdr().car();
+                if value.is_nil() {
+                    std::env::remove_var(&*name);
+                } else {
+                    let value = value.get_string().context("As second argument")?;
+                    if value.contains('\0') {
+                        return Err(lisp_error!(
+                            Value,
+                            "Builtin setenv got a value containing a null character"
+                        ));
+                    }
+                    std::env::set_var(&*name, &*value);
+                }
+                Ok(args.cdr().car())
             }
         });
 
-        env.add_builtin(">=", |args| {
-            if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
-                Err(eyre!(
-                    "Builtin >= expected exactly two arguments, got {}",
-                    args
+        self.add_builtin(
+            "exit",
+            "(exit [status])\nStop evaluating, exiting with the integer status, which defaults to 0.",
+            |args| {
+                let code = if args.is_nil() {
+                    0
+                } else if args.cdr().is_nil() {
+                    let code = args.car().get_integer()?;
+                    i32::try_from(code).map_err(|_| {
+                        lisp_error!(Value, "Builtin exit got an out of range status {}", code)
+                    })?
+                } else {
+                    return Err(lisp_error!(
+                        Arity,
+                        "Builtin exit expected zero or one argument, got {}",
+                        args
+                    ));
+                };
+                Err(LispError::new(
+                    ErrorKind::Exit(code),
+                    format!("Exit with status {code}"),
                 ))
-            } else {
-                let arg1 = args.car().get_number().context("As first argument")?;
-                let arg2 = args
-                    .cdr()
-                    .car()
-                    .get_number()
-                    .context("As second argument")?;
-                Ok(Rc::new(Atom::bool(arg1 >= arg2)))
+            },
+        );
+    }
+
+    /// Add the builtins running other programs.
+    fn add_program_builtins(&mut self) {
+        self.add_builtin(
+            "shell",
+            "(shell command [stream])\nRun the string command with sh, like process-run.",
+            |args| {
+                let args = Atom::list_to_vec(&args)?;
+                if args.is_empty() || args.len() > 2 {
+                    return Err(lisp_error!(
+                        Arity,
+                        "Builtin shell expected one or two arguments, got {}",
+                        args.len()
+                    ));
+                }
+                let command = args[0].get_string().context("As first argument")?;
+                let stream = args.get(1).is_some_and(|stream| !stream.is_nil());
+                let mut process = std::process::Command::new("sh");
+                process.arg("-c").arg(&*command);
+                run_process(process, stream).context(format!("While running command {command}"))
+            },
+        );
+
+        self.add_builtin("process-run", "(process-run program [arguments] [stream])\nRun program with the list of string arguments, and return a list of its exit status, stdout and stderr. If stream is true, the output is shown as the program runs instead, and stdout and stderr are nil.", |args| {
+            let args = Atom::list_to_vec(&args)?;
+            if args.is_empty() || args.len() > 3 {
+                return Err(lisp_error!(
+                    Arity,
+                    "Builtin process-run expected between one and three arguments, got {}",
+                    args.len()
+                ));
             }
+            let program = args[0].get_string().context("As first argument")?;
+            let mut process = std::process::Command::new(&*program);
+            if let Some(arguments) = args.get(1) {
+                for argument in Atom::list_to_vec(arguments).context("As second argument")? {
+                    process.arg(&*argument.get_string().context("As second argument")?);
+                }
+            }
+            let stream = args.get(2).is_some_and(|stream| !stream.is_nil());
+            run_process(process, stream).context(format!("While running program {program}"))
         });
+    }
+
+    /// Add the builtins encoding and decoding strings.
+    #[cfg(feature = "encoding")]
+    fn add_encoding_builtins(&mut self) {
+        self.add_builtin(
+            "base64-encode",
+            "(base64-encode s)\nThe string s encoded in base64.",
+            |args| {
+                let s = get_single_string_argument("base64-encode", &args)?;
+                Ok(Rc::new(Atom::String(
+                    base64::engine::general_purpose::STANDARD
+                        .encode(s.as_bytes())
+                        .into(),
+                )))
+            },
+        );
+
+        self.add_builtin(
+            "base64-decode",
+            "(base64-decode s)\nThe string encoded in base64 by s. Strings are text, so it is an error if the decoded bytes are not\nUTF-8: arbitrary binary data can not be decoded.",
+            |args| {
+                let s = get_single_string_argument("base64-decode", &args)?;
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(s.trim())
+                    .context("Builtin base64-decode got invalid base64")?;
+                Ok(Rc::new(Atom::String(
+                    String::from_utf8(bytes)
+                        .context("Builtin base64-decode decoded to invalid UTF-8")?
+                        .into(),
+                )))
+            },
+        );
+
+        self.add_builtin(
+            "hex-encode",
+            "(hex-encode s)\nThe bytes of the string s in hexadecimal.",
+            |args| {
+                let s = get_single_string_argument("hex-encode", &args)?;
+                Ok(Rc::new(Atom::String(hex::encode(s.as_bytes()).into())))
+            },
+        );
+
+        self.add_builtin(
+            "hex-decode",
+            "(hex-decode s)\nThe string whose bytes are given in hexadecimal by s. Strings are text, so it is an error if the bytes\nare not UTF-8: arbitrary binary data can not be decoded.",
+            |args| {
+                let s = get_single_string_argument("hex-decode", &args)?;
+                let bytes = hex::decode(s.trim()).context("Builtin hex-decode got invalid hex")?;
+                Ok(Rc::new(Atom::String(
+                    String::from_utf8(bytes)
+                        .context("Builtin hex-decode decoded to invalid UTF-8")?
+                        .into(),
+                )))
+            },
+        );
+    }
+
+    /// Add the builtins parsing and writing data formats.
+    #[cfg(any(feature = "toml", feature = "yaml"))]
+    fn add_data_format_builtins(&mut self) {
+        #[cfg(feature = "toml")]
+        self.add_builtin(
+            "toml-parse",
+            "(toml-parse s)\nThe TOML document s, as an association list.",
+            |args| {
+                let src = get_single_string_argument("toml-parse", &args)?;
+                let value = src.parse::<toml::Table>().context("While parsing TOML")?;
+                Ok(crate::atom::convert::from_toml(&toml::Value::Table(value)))
+            },
+        );
+
+        #[cfg(feature = "toml")]
+        self.add_builtin(
+            "toml-serialize",
+            "(toml-serialize alist)\nThe association list alist, as a TOML document.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    return Err(lisp_error!(
+                        Arity,
+                        "Builtin toml-serialize expected exactly one argument, got {}",
+                        args
+                    ));
+                }
+                match crate::atom::convert::to_toml(&args.car())? {
+                    toml::Value::Table(table) => Ok(Rc::new(Atom::String(
+                        toml::to_string(&table)
+                            .context("While serializing TOML")?
+                            .into(),
+                    ))),
+                    _ => Err(lisp_error!(
+                        Type,
+                        "Builtin toml-serialize expected an association list, got {}",
+                        args.car()
+                    )),
+                }
+            },
+        );
 
-        Env::new(Some(Box::new(env)))
+        #[cfg(feature = "yaml")]
+        self.add_builtin(
+            "yaml-parse",
+            "(yaml-parse s)\nThe YAML document s, converted to atoms.",
+            |args| {
+                let src = get_single_string_argument("yaml-parse", &args)?;
+                let value: serde_yaml::Value =
+                    serde_yaml::from_str(&src).context("While parsing YAML")?;
+                crate::atom::convert::from_yaml(&value)
+            },
+        );
+
+        #[cfg(feature = "yaml")]
+        self.add_builtin(
+            "yaml-serialize",
+            "(yaml-serialize x)\nx as a YAML document.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    return Err(lisp_error!(
+                        Arity,
+                        "Builtin yaml-serialize expected exactly one argument, got {}",
+                        args
+                    ));
+                }
+                let value = crate::atom::convert::to_yaml(&args.car())?;
+                Ok(Rc::new(Atom::String(
+                    serde_yaml::to_string(&value)
+                        .context("While serializing YAML")?
+                        .into(),
+                )))
+            },
+        );
+    }
+
+    /// Add the builtins memoizing and profiling functions.
+    fn add_function_builtins(&mut self) {
+        self.add_builtin(
+            "memoize",
+            "(memoize function)\nA version of function which caches its results by arguments.",
+            |args| {
+                if args.is_nil() || !args.cdr().is_nil() {
+                    return Err(lisp_error!(
+                        Arity,
+                        "Builtin memoize expected exactly one argument, got {}",
+                        args
+                    ));
+                }
+                match args.car().as_ref() {
+                    Atom::NativeFunc(_) | Atom::Closure(_, _, _) | Atom::Memoized(_, _) => {
+                        Ok(Rc::new(Atom::Memoized(args.car(), Rc::default())))
+                    }
+                    a => Err(lisp_error!(
+                        Type,
+                        "Builtin memoize expected a function, got {}",
+                        a
+                    )),
+                }
+            },
+        );
+
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        self.add_builtin(
+            "profile-start",
+            "(profile-start)\nStart recording how much time is spent in each function.",
+            |args| {
+                if args.is_nil() {
+                    crate::profile::start();
+                    Ok(Atom::nil())
+                } else {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin profile-start expected no arguments, got {}",
+                        args
+                    ))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "profile-stop",
+            "(profile-stop)\nStop recording how much time is spent in each function.",
+            |args| {
+                if args.is_nil() {
+                    crate::profile::stop();
+                    Ok(Atom::nil())
+                } else {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin profile-stop expected no arguments, got {}",
+                        args
+                    ))
+                }
+            },
+        );
+
+        self.add_builtin(
+            "profile-report",
+            "(profile-report)\nPrint how much time was spent in each function while recording.",
+            |args| {
+                if args.is_nil() {
+                    crate::output::print(&crate::profile::report())?;
+                    Ok(Atom::nil())
+                } else {
+                    Err(lisp_error!(
+                        Arity,
+                        "Builtin profile-report expected no arguments, got {}",
+                        args
+                    ))
+                }
+            },
+        );
     }
 }
 
-fn format_for_print(arg: &Rc<Atom>) -> String {
+/// Whether `name` is already qualified with a namespace, like `util:reverse`, or is a keyword like `:key`, neither of
+/// which is put in a namespace again.
+fn is_qualified(name: &str) -> bool {
+    name.contains(':')
+}
+
+fn format_for_print(arg: &Rc<Atom>) -> Rc<str> {
     let s = match arg.as_ref() {
         Atom::String(string) => string.clone(),
-        a => {
-            format!("{}", a)
-        }
+        a => format!("{a}").into(),
     };
     s
 }
 
+/// Collect the arguments of a builtin into a vector of numbers.
+///
+/// # Errors
+/// If one of the arguments is not a number, return an error.
+fn get_number_arguments(args: &Rc<Atom>) -> Result<Vec<f64>> {
+    let mut numbers = Vec::new();
+    let mut atom = args.clone();
+    while !atom.is_nil() {
+        let number = atom
+            .car()
+            .get_number()
+            .context(format!("As argument number {}", numbers.len() + 1))?;
+        numbers.push(number);
+        atom = atom.strict_cdr()?;
+    }
+    Ok(numbers)
+}
+
+/// Collect the arguments of a builtin into a vector of integers.
+///
+/// # Errors
+/// If one of the arguments is not an integer, return an error.
+fn get_integer_arguments(args: &Rc<Atom>) -> Result<Vec<i64>> {
+    let mut integers = Vec::new();
+    let mut atom = args.clone();
+    while !atom.is_nil() {
+        let integer = atom
+            .car()
+            .get_integer()
+            .context(format!("As argument number {}", integers.len() + 1))?;
+        integers.push(integer);
+        atom = atom.strict_cdr()?;
+    }
+    Ok(integers)
+}
+
+/// Get the integer to shift and the amount to shift it by for the shift builtins.
+///
+/// # Errors
+/// If there are not exactly two integer arguments, or if the shift amount is not between 0 and 63, return an error.
+fn get_shift_arguments(name: &str, args: &Rc<Atom>) -> Result<(i64, u32)> {
+    if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
+        Err(lisp_error!(
+            Arity,
+            "Builtin {} expected exactly two arguments, got {}",
+            name,
+            args
+        ))
+    } else {
+        let x = args.car().get_integer().context("As first argument")?;
+        let amount = args
+            .cdr()
+            .car()
+            .get_integer()
+            .context("As second argument")?;
+        match u32::try_from(amount) {
+            Ok(amount) if amount < i64::BITS => Ok((x, amount)),
+            _ => Err(lisp_error!(
+                Value,
+                "Builtin {} expected a shift amount between 0 and {}, but got {}",
+                name,
+                i64::BITS - 1,
+                amount
+            )),
+        }
+    }
+}
+
+/// Greatest common divisor of the magnitudes of two integers.
+const fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Get an atom as an index into a string or list.
+///
+/// # Errors
+/// If the atom is not a non-negative integer, return an error.
+fn get_index(atom: &Rc<Atom>) -> Result<usize> {
+    let index = atom.get_integer()?;
+    usize::try_from(index)
+        .map_err(|_| lisp_error!(Value, "Expected a non-negative index, got {}", index))
+}
+
+/// Get the only argument of a builtin as a string.
+///
+/// # Errors
+/// If there is not exactly one argument, or if it is not a string, return an error.
+fn get_single_string_argument(name: &str, args: &Rc<Atom>) -> Result<Rc<str>> {
+    if args.is_nil() || !args.cdr().is_nil() {
+        Err(lisp_error!(
+            Arity,
+            "Builtin {} expected exactly one argument, got {}",
+            name,
+            args
+        ))
+    } else {
+        args.car().get_string()
+    }
+}
+
+/// Get the two arguments of a builtin as strings.
+///
+/// # Errors
+/// If there are not exactly two arguments, or if they are not strings, return an error.
+fn get_two_string_arguments(name: &str, args: &Rc<Atom>) -> Result<(Rc<str>, Rc<str>)> {
+    if args.is_nil() || args.cdr().is_nil() || !args.cdr().cdr().is_nil() {
+        Err(lisp_error!(
+            Arity,
+            "Builtin {} expected exactly two arguments, got {}",
+            name,
+            args
+        ))
+    } else {
+        let first = args.car().get_string().context("As first argument")?;
+        let second = args
+            .cdr()
+            .car()
+            .get_string()
+            .context("As second argument")?;
+        Ok((first, second))
+    }
+}
+
+/// Read a single UTF-8 encoded character from a reader.
+///
+/// Returns `None` at end of input.
+///
+/// # Errors
+/// If reading fails, or if the input is not valid UTF-8, return an error.
+fn read_char(reader: &mut impl Read) -> Result<Option<char>> {
+    let mut buffer = [0; 4];
+    if reader.read(&mut buffer[..1])? == 0 {
+        return Ok(None);
+    }
+    let length = match buffer[0] {
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4,
+    };
+    reader.read_exact(&mut buffer[1..length])?;
+    let s = std::str::from_utf8(&buffer[..length])?;
+    Ok(s.chars().next())
+}
+
+/// Run a subprocess to completion, returning a list of its exit status, stdout and stderr.
+///
+/// The exit status is nil if the process was terminated by a signal.
+/// If `stream` is true, the output of the process is not captured but passed through,
+/// and stdout and stderr are nil.
+///
+/// # Errors
+/// If the process could not be started, return an error.
+fn run_process(mut process: std::process::Command, stream: bool) -> Result<Rc<Atom>> {
+    let (status, stdout, stderr) = if stream {
+        let status = process.status()?;
+        (status, Atom::nil(), Atom::nil())
+    } else {
+        let output = process.output()?;
+        (
+            output.status,
+            Rc::new(Atom::String(
+                String::from_utf8_lossy(&output.stdout).into_owned().into(),
+            )),
+            Rc::new(Atom::String(
+                String::from_utf8_lossy(&output.stderr).into_owned().into(),
+            )),
+        )
+    };
+    let status = status
+        .code()
+        .map_or_else(Atom::nil, |code| Atom::integer(code.into()));
+    Ok(Atom::list_from_vec(vec![status, stdout, stderr]))
+}
+
+/// Convert a number of seconds since the unix epoch to a UTC date and time.
+///
+/// # Errors
+/// If the number of seconds is out of the representable range, return an error.
+#[cfg(feature = "datetime")]
+fn seconds_to_datetime(seconds: f64) -> Result<chrono::DateTime<chrono::Utc>> {
+    let whole_seconds = seconds.floor();
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let nanoseconds = (((seconds - whole_seconds) * 1e9) as u32).min(999_999_999);
+    #[allow(clippy::cast_possible_truncation)]
+    chrono::DateTime::from_timestamp(whole_seconds as i64, nanoseconds)
+        .ok_or_else(|| lisp_error!(Value, "The timestamp {} is out of range", seconds))
+}
+
+/// Convert a UTC date and time to a number of seconds since the unix epoch.
+#[cfg(feature = "datetime")]
+fn datetime_to_seconds(time: chrono::DateTime<chrono::Utc>) -> f64 {
+    #[allow(clippy::cast_precision_loss)]
+    let seconds = time.timestamp() as f64;
+    seconds + f64::from(time.timestamp_subsec_nanos()) / 1e9
+}
+
+/// Get the only argument of a builtin as a number.
+///
+/// # Errors
+/// If there is not exactly one argument, or if it is not a number, return an error.
+fn get_single_number_argument(name: &str, args: &Rc<Atom>) -> Result<f64> {
+    if args.is_nil() || !args.cdr().is_nil() {
+        Err(lisp_error!(
+            Arity,
+            "Builtin {} expected exactly one argument, got {}",
+            name,
+            args
+        ))
+    } else {
+        args.car().get_number()
+    }
+}
+
 impl Env {
     /// Create a new empty environemnt with the give parent environment
     #[must_use]
-    pub fn new(parent: Option<Box<Env>>) -> Self {
+    pub fn new(parent: Option<Rc<Env>>) -> Self {
         Self {
             bindings: HashMap::new(),
+            docs: HashMap::new(),
+            namespace: parent.as_ref().and_then(|parent| parent.namespace.clone()),
+            caches: parent
+                .as_ref()
+                .map_or_else(gc::Caches::default, |parent| parent.caches.clone()),
+            modules: parent
+                .as_ref()
+                .map_or_else(module::Modules::default, |parent| parent.modules.clone()),
             parent,
+            caller: None,
+        }
+    }
+
+    /// Create an environment with only the builtins needing one of the given capabilities, so that the others do
+    /// not exist in it.
+    #[must_use]
+    pub fn with_capabilities(capabilities: &[Capability]) -> Self {
+        let mut env = Self::builtins();
+        let denied = env
+            .iter()
+            .filter(|(name, value)| {
+                let builtin = match value.as_ref() {
+                    Atom::NativeFunc(_) => true,
+                    // special forms are bound to their own name
+                    Atom::Symbol(form) => form == *name,
+                    _ => false,
+                };
+                builtin && !Capability::of(name).is_some_and(|c| capabilities.contains(&c))
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for name in denied {
+            info!("Leaving out builtin {name}");
+            env.bindings.remove(&name);
+            env.docs.remove(&name);
+        }
+        Env::new(Some(Rc::new(env)))
+    }
+
+    /// Create a new empty environment for a call to a closure.
+    ///
+    /// Names are looked up in the environment the closure was created in first, then in the environment
+    /// it was called from. Both are shared, so this does not copy any bindings.
+    #[must_use]
+    pub fn new_call_frame(function_env: &Env, caller: &Env) -> Self {
+        Self {
+            bindings: HashMap::new(),
+            docs: HashMap::new(),
+            parent: Some(Rc::new(function_env.clone())),
+            caller: Some(Rc::new(caller.clone())),
+            namespace: function_env.namespace.clone(),
+            caches: caller.caches.clone(),
+            modules: caller.modules.clone(),
         }
     }
 
@@ -375,36 +2088,298 @@ impl Env {
     ///
     /// If the key is not found in any environment, return an error.
     pub fn get(&self, name: &str) -> Result<Rc<Atom>> {
-        match self.bindings.get(&Rc::new(name.to_string())) {
-            Some(atom) => Ok(atom.clone()),
-            None => match &self.parent {
-                Some(parent) => parent.get(name),
-                None => {
-                    info!("Symbol {name} is not bound to any value");
-                    Err(eyre!(format!("Symbol {name} is not bound to any value.")))
-                }
-            },
+        self.lookup(name).ok_or_else(|| {
+            info!("Symbol {name} is not bound to any value");
+            lisp_error!(UnboundSymbol, "Symbol {name} is not bound to any value.")
+                .with_atom(&Atom::symbol(name))
+        })
+    }
+
+    /// Get a value from the environment, trying parent environments if the key is not found.
+    ///
+    /// Unlike [`Env::get`], this returns `None` instead of an error if the key is not found.
+    ///
+    /// In a namespace, an unqualified name like `reverse` is looked up as `namespace:reverse` first, so that the
+    /// definitions of the namespace hide those made outside of it.
+    #[must_use]
+    pub fn lookup(&self, name: &str) -> Option<Rc<Atom>> {
+        match self.namespace() {
+            Some(namespace) if !is_qualified(name) => self
+                .find(&format!("{namespace}:{name}"))
+                .or_else(|| self.find(name)),
+            _ => self.find(name),
         }
     }
 
+    /// Get a value from the environment, trying the parent and then the caller environment if the key is not
+    /// found.
+    fn find(&self, name: &str) -> Option<Rc<Atom>> {
+        self.bindings
+            .get(name)
+            .cloned()
+            .or_else(|| self.parent.as_ref().and_then(|parent| parent.find(name)))
+            .or_else(|| self.caller.as_ref().and_then(|caller| caller.find(name)))
+    }
+
     /// Set a value in the environment
-    pub fn set(&mut self, name: String, value: Rc<Atom>) {
+    pub fn set(&mut self, name: impl Into<Rc<str>>, value: Rc<Atom>) {
+        let name = name.into();
         trace!("{name} is now bound to {value:?}");
-        self.bindings.insert(Rc::new(name), value);
+        self.bindings.insert(name, value);
     }
 
-    fn add_builtin(&mut self, name: &str, value: fn(Rc<Atom>) -> Result<Rc<Atom>>) {
-        info!("Adding builtin {name}");
-        self.set(String::from(name), Rc::new(Atom::NativeFunc(value)));
+    /// Bind `name` like `define` does: in the namespace of the environment, if it has one and `name` is not already
+    /// qualified.
+    pub fn define(&mut self, name: &str, value: Rc<Atom>) {
+        let name = self.qualify(name);
+        self.set(name, value);
+    }
+
+    /// The name `name` is bound to when it is defined in this environment, like `util:reverse` for `reverse` in
+    /// the namespace `util`.
+    #[must_use]
+    pub fn qualify(&self, name: &str) -> Rc<str> {
+        match self.namespace() {
+            Some(namespace) if !is_qualified(name) => format!("{namespace}:{name}").into(),
+            _ => name.into(),
+        }
+    }
+
+    /// The namespace definitions are made in, if any.
+    #[must_use]
+    pub fn namespace(&self) -> Option<Rc<str>> {
+        self.namespace.clone()
+    }
+
+    /// Make the definitions which follow in the namespace `namespace`, or outside of any if it is `None`.
+    pub fn set_namespace(&mut self, namespace: Option<Rc<str>>) {
+        info!("Now in namespace {namespace:?}");
+        self.namespace = namespace;
     }
 
-    /// Add a parent environment to the outmost parent.
-    pub fn add_furthest_parent(&mut self, parent: Env) {
-        trace!("Adding {parent:?} as furthest parent of {self:?}");
+    /// The names bound in this environment and in the environments names are looked up in, sorted and without
+    /// duplicates.
+    #[must_use]
+    pub fn symbol_names(&self) -> Vec<Rc<str>> {
+        let mut names = std::collections::BTreeSet::new();
+        let mut stack = vec![self];
+        while let Some(env) = stack.pop() {
+            names.extend(env.bindings.keys().cloned());
+            stack.extend(
+                env.parent
+                    .iter()
+                    .chain(env.caller.iter())
+                    .map(AsRef::as_ref),
+            );
+        }
+        names.into_iter().collect()
+    }
+
+    /// The bindings names are looked up in, from this environment and those it refers to, sorted by name. A name
+    /// bound in several of them has the value it is looked up as, so the bindings of a function call shadow those
+    /// around it.
+    #[must_use]
+    pub fn visible_bindings(&self) -> Vec<(Rc<str>, Rc<Atom>)> {
+        let mut bindings = std::collections::BTreeMap::new();
+        // in the order of `find`: this environment, its parents and then its caller
+        let mut stack = vec![self];
+        while let Some(env) = stack.pop() {
+            for (name, value) in &env.bindings {
+                bindings
+                    .entry(name.clone())
+                    .or_insert_with(|| value.clone());
+            }
+            stack.extend(
+                env.caller
+                    .iter()
+                    .chain(env.parent.iter())
+                    .map(AsRef::as_ref),
+            );
+        }
+        bindings.into_iter().collect()
+    }
+
+    /// The bindings of this environment, without those of the environments names are also looked up in.
+    pub fn iter(&self) -> impl Iterator<Item = (&Rc<str>, &Rc<Atom>)> {
+        self.bindings.iter()
+    }
+
+    /// How many environments are above this one through its parents, like 1 for the default environment, whose
+    /// parent binds the builtins, and 2 in a call to a function defined in it.
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        std::iter::successors(self.parent.as_deref(), |env| env.parent.as_deref()).count()
+    }
+
+    /// Write the bindings of this environment as lisp source which makes them again, like a session saved in the
+    /// REPL, including functions and macros.
+    ///
+    /// The bindings of the environments names are also looked up in, like the builtins, are not written, and names
+    /// bound to one of their values refer to it. See [`session`](crate::session) for what can not be saved.
+    #[must_use]
+    pub fn snapshot(&self) -> String {
+        let baseline = Self::new(self.parent.clone());
+        crate::session::save(self, &baseline)
+    }
+
+    /// Evaluate a [snapshot](Env::snapshot) in this environment, making its bindings again.
+    ///
+    /// # Errors
+    /// If the snapshot can not be parsed, or one of its definitions can not be evaluated, return an error.
+    pub fn restore(&mut self, snapshot: &str) -> Result<()> {
+        let atoms = crate::parsing::parser()
+            .parse(snapshot)
+            .map_err(|errs| crate::parse_error(snapshot, &errs))?;
+        for atom in atoms {
+            Atom::eval(Rc::new(atom), self).context("While restoring a snapshot")?;
+        }
+        Ok(())
+    }
+
+    /// Write a [snapshot](Env::snapshot) of this environment to the file at `path`, like an image of the default
+    /// library and the definitions of a program, which [`Env::load`] makes again without their source files.
+    ///
+    /// # Errors
+    /// If the file can not be written, return an error.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.snapshot())
+            .with_context(|| format!("While saving the environment to {}", path.display()))
+    }
+
+    /// Make a default environment with the bindings of the snapshot saved at `path` with [`Env::save`].
+    ///
+    /// # Errors
+    /// If the file can not be read, or like [`Env::restore`].
+    pub fn load(path: &Path) -> Result<Self> {
+        let snapshot = std::fs::read_to_string(path)
+            .with_context(|| format!("While loading the environment from {}", path.display()))?;
+        let mut env = Self::default();
+        env.restore(&snapshot)?;
+        Ok(env)
+    }
 
-        match &mut self.parent {
-            Some(self_parent) => self_parent.add_furthest_parent(parent),
-            None => self.parent = Some(Box::new(parent)),
+    /// The names given by [`Env::symbol_names`] which contain `part`.
+    #[must_use]
+    pub fn apropos(&self, part: &str) -> Vec<Rc<str>> {
+        let mut names = self.symbol_names();
+        names.retain(|name| name.contains(part));
+        names
+    }
+
+    /// Describe what is bound to `name`: how a function or macro is called, followed by its documentation if it has
+    /// any.
+    ///
+    /// # Errors
+    /// If `name` is not bound, return an error.
+    pub fn documentation(&self, name: &str) -> Result<String> {
+        let value = self.get(name)?;
+        let function = match value.as_ref() {
+            Atom::Memoized(function, _) => function.clone(),
+            _ => value.clone(),
+        };
+        let doc = match function.as_ref() {
+            Atom::Closure(_, params, _) | Atom::Macro(_, params, _) => {
+                let usage = Atom::Pair(Rc::new(Atom::symbol(name)), params.clone());
+                let doc = function.docstring();
+                format!("{usage}\n{}", doc.as_deref().unwrap_or("Not documented."))
+            }
+            Atom::NativeFunc(_) => self.builtin_doc(name).map_or_else(
+                || format!("{name} is a builtin, which is not documented."),
+                |doc| doc.to_string(),
+            ),
+            // special forms are bound to their own name
+            Atom::Symbol(form) if &**form == name && !matches!(name, "nil" | "t") => {
+                format!("{name} is a special form.")
+            }
+            _ => format!("{name} is bound to a {}: {value}", value.type_name()),
+        };
+        Ok(doc)
+    }
+
+    /// Get the documentation given when the builtin bound to `name` was added.
+    fn builtin_doc(&self, name: &str) -> Option<Rc<str>> {
+        self.docs
+            .get(name)
+            .cloned()
+            .or_else(|| {
+                self.parent
+                    .as_ref()
+                    .and_then(|parent| parent.builtin_doc(name))
+            })
+            .or_else(|| {
+                self.caller
+                    .as_ref()
+                    .and_then(|caller| caller.builtin_doc(name))
+            })
+    }
+
+    /// The environment names are looked up in after this one, if any.
+    pub(crate) fn parent(&self) -> Option<&Self> {
+        self.parent.as_deref()
+    }
+
+    /// The environment at the root of this one through its parents, which binds the builtins, or a copy of this one
+    /// if it is the root.
+    pub(crate) fn root(&self) -> Rc<Self> {
+        let mut root = match &self.parent {
+            Some(parent) => parent.clone(),
+            None => return Rc::new(self.clone()),
+        };
+        while let Some(parent) = root.parent.clone() {
+            root = parent;
         }
+        root
+    }
+
+    /// The modules which were required in this environment.
+    pub(crate) const fn modules(&self) -> &module::Modules {
+        &self.modules
+    }
+
+    /// The caches of memoized functions which are registered in this environment.
+    pub(crate) const fn caches(&self) -> &gc::Caches {
+        &self.caches
+    }
+
+    /// The atoms bound in this environment, and the environments it refers to.
+    pub(crate) fn references(
+        &self,
+    ) -> (
+        impl Iterator<Item = &Rc<Atom>>,
+        impl Iterator<Item = &Rc<Env>>,
+    ) {
+        (
+            self.bindings.values(),
+            self.parent.iter().chain(self.caller.iter()),
+        )
+    }
+
+    /// Bind a builtin to `name`, along with its documentation: how it is called on the first line, then what it
+    /// does.
+    ///
+    /// `function` is called with the list of the evaluated arguments. It can be a closure capturing state of the
+    /// application embedding lwhlisp, like a counter or a channel.
+    pub fn add_builtin(&mut self, name: &str, doc: &str, function: impl NativeFn + 'static) {
+        info!("Adding builtin {name}");
+        self.set(name, Rc::new(Atom::NativeFunc(Rc::new(function))));
+        self.docs.insert(name.into(), doc.into());
+    }
+
+    /// Bind a Rust function with typed arguments to `name`, along with its documentation, like
+    /// [`add_builtin`](Self::add_builtin).
+    ///
+    /// The function can be an ordinary one, like `fn(f64, f64) -> f64` or `fn(String) -> Result<Vec<String>>`,
+    /// or a closure. Calling the builtin with the wrong number of arguments, or with arguments which do not convert
+    /// to the types the function takes, is an error saying so.
+    pub fn register_fn<Args, F: TypedFn<Args> + 'static>(
+        &mut self,
+        name: &str,
+        doc: &str,
+        function: F,
+    ) {
+        let builtin: Rc<str> = name.into();
+        self.add_builtin(name, doc, move |args: Rc<Atom>| {
+            function.call_with(&builtin, &args)
+        });
     }
 }
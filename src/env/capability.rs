@@ -0,0 +1,68 @@
+//! Builtins are grouped by what they can do besides computing their result, so that an environment for untrusted
+//! code can be made without those it should not be able to do.
+
+/// What a builtin can do besides computing its result.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Nothing: computing with numbers, strings and lists, reading the clock, waiting and getting random numbers.
+    Pure,
+    /// Reading stdin and writing to stdout, like `print` and `read-line`.
+    Io,
+    /// Reading and changing files and directories, like `slurp`, `delete-file` and `require`.
+    Fs,
+    /// Using the network. No builtin does yet, so that an environment without it keeps future ones out.
+    Net,
+    /// Running other programs and changing the process, like `shell`, `exit` and `setenv`.
+    Process,
+}
+
+impl Capability {
+    /// Every capability, which the default environment has.
+    pub const ALL: [Self; 5] = [Self::Pure, Self::Io, Self::Fs, Self::Net, Self::Process];
+
+    /// The capabilities of a sandbox, which can compute and use stdin and stdout, but not touch files, the network
+    /// or other processes.
+    pub const SANDBOX: [Self; 2] = [Self::Pure, Self::Io];
+
+    /// The capability the builtin or special form `name` needs, or `None` if it is not known, so that a builtin
+    /// added without being classified here is left out of every environment with limited capabilities.
+    #[must_use]
+    pub fn of(name: &str) -> Option<Self> {
+        let capability = match name {
+            "print" | "println" | "read-line" | "read-char" | "doc" | "bench" | "profile-report" => {
+                Self::Io
+            }
+            "slurp" | "spit" | "file-exists?" | "delete-file" | "list-directory"
+            | "make-directory" | "require" => Self::Fs,
+            "getenv" | "setenv" | "exit" | "shell" | "process-run" => Self::Process,
+            // special forms
+            "quote" | "if" | "define" | "defmacro" | "lambda" | "apply" | "gc" | "apropos" | "env"
+            | "in-namespace" | "provide" | "pmap" | "memoize" | "nil" | "t"
+            // numbers
+            | "+" | "-" | "*" | "/" | "%" | "=" | "<" | "<=" | ">" | ">=" | "abs" | "min" | "max"
+            | "floor" | "ceiling" | "round" | "truncate" | "sqrt" | "exp" | "expt" | "log" | "sin"
+            | "cos" | "tan" | "atan2" | "gcd" | "lcm" | "bit-and" | "bit-or" | "bit-xor"
+            | "bit-not" | "shift-left" | "shift-right"
+            // lists, symbols and maps
+            | "car" | "cdr" | "cons" | "pair?" | "symbol?" | "keyword?" | "symbol->string"
+            | "string->symbol" | "map?" | "map-get" | "map-set"
+            // strings
+            | "string?" | "string-length" | "string-ref" | "substring" | "string-append"
+            | "string-join" | "string-split" | "string-contains?" | "string-starts-with?"
+            | "string-ends-with?" | "string-index-of" | "string-replace" | "string-upcase"
+            | "string-downcase" | "string-trim" | "string-trim-left" | "string-trim-right"
+            | "string->list" | "list->string" | "into-string" | "into-pretty-string"
+            // encodings and data formats
+            | "base64-encode" | "base64-decode" | "hex-encode" | "hex-decode" | "toml-parse"
+            | "toml-serialize" | "yaml-parse" | "yaml-serialize"
+            // the clock and randomness
+            | "current-time" | "monotonic-time" | "sleep" | "time-format" | "time-parse"
+            | "profile-start" | "profile-stop" | "random" | "random-int" | "random-choice"
+            | "shuffle" | "uuid" => {
+                Self::Pure
+            }
+            _ => return None,
+        };
+        Some(capability)
+    }
+}
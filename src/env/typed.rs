@@ -0,0 +1,127 @@
+//! A Rust function with typed arguments, like `fn(f64, f64) -> f64`, is turned into a builtin by checking that it
+//! is given as many arguments as it takes, converting each of them with [`TryFrom<&Atom>`], and converting what it
+//! returns with [`Into<Atom>`].
+
+use crate::atom::{Atom, Rc};
+use crate::error::{Context, LispError, Result};
+use crate::lisp_error;
+
+/// Nothing with the default features. With the `arc` feature, [`Send`] and [`Sync`], which builtins must be.
+#[cfg(not(feature = "arc"))]
+pub trait Shareable {}
+#[cfg(not(feature = "arc"))]
+impl<T> Shareable for T {}
+/// Nothing with the default features. With the `arc` feature, [`Send`] and [`Sync`], which builtins must be.
+#[cfg(feature = "arc")]
+pub trait Shareable: Send + Sync {}
+#[cfg(feature = "arc")]
+impl<T: Send + Sync> Shareable for T {}
+
+/// What a typed function can return: anything converting to an atom, or a result of it.
+pub trait IntoAtomResult {
+    /// Convert the returned value to an atom.
+    ///
+    /// # Errors
+    /// If the function returned an error, return it.
+    fn into_atom_result(self) -> Result<Rc<Atom>>;
+}
+
+impl<T: Into<Atom>> IntoAtomResult for T {
+    fn into_atom_result(self) -> Result<Rc<Atom>> {
+        Ok(Rc::new(self.into()))
+    }
+}
+
+impl<T: Into<Atom>> IntoAtomResult for Result<T> {
+    fn into_atom_result(self) -> Result<Rc<Atom>> {
+        self.map(|value| Rc::new(value.into()))
+    }
+}
+
+#[cfg(feature = "eyre")]
+impl<T: Into<Atom>> IntoAtomResult for Result<T, color_eyre::Report> {
+    fn into_atom_result(self) -> Result<Rc<Atom>> {
+        self.map(|value| Rc::new(value.into()))
+            .map_err(|report| LispError::from_report(&report))
+    }
+}
+
+/// A Rust function which can be registered as a builtin with [`Env::register_fn`](super::Env::register_fn).
+///
+/// `Args` is the tuple of the types of its arguments. Functions taking up to six arguments, of types which convert
+/// from atoms, like `f64`, `String`, `Vec<i64>` or `Option<bool>`, are typed functions.
+pub trait TypedFn<Args>: Shareable {
+    /// Call the function with the list of its evaluated arguments, as the builtin `name`.
+    ///
+    /// # Errors
+    /// If it is not given as many arguments as it takes, if one of them can not be converted, or if the function
+    /// returns an error, return an error.
+    fn call_with(&self, name: &str, args: &Atom) -> Result<Rc<Atom>>;
+}
+
+/// How a number of arguments, or an argument at that position, is written.
+const COUNTS: [(&str, &str); 7] = [
+    ("no", ""),
+    ("one", "first"),
+    ("two", "second"),
+    ("three", "third"),
+    ("four", "fourth"),
+    ("five", "fifth"),
+    ("six", "sixth"),
+];
+
+/// Collect the arguments of the builtin `name`, which takes exactly `count` of them.
+fn arguments<'a>(name: &str, args: &'a Atom, count: usize) -> Result<Vec<&'a Atom>> {
+    let mut items = Vec::with_capacity(count);
+    let mut rest = args;
+    while let Atom::Pair(car, cdr) = rest {
+        items.push(car.as_ref());
+        rest = cdr;
+    }
+    if items.len() == count && rest.is_nil() {
+        return Ok(items);
+    }
+    let expected = match count {
+        0 => "no arguments".to_string(),
+        1 => "exactly one argument".to_string(),
+        n => format!("exactly {} arguments", COUNTS[n].0),
+    };
+    Err(lisp_error!(
+        Arity,
+        "Builtin {name} expected {expected}, got {args}"
+    ))
+}
+
+/// Convert the argument of the builtin `name` at `index`.
+fn argument<T>(name: &str, args: &[&Atom], index: usize) -> Result<T>
+where
+    T: for<'a> TryFrom<&'a Atom, Error = LispError>,
+{
+    T::try_from(args[index])
+        .with_context(|| format!("As {} argument of {name}", COUNTS[index + 1].1))
+}
+
+macro_rules! typed_fn {
+    ($count:literal $(, $arg:ident $index:literal)*) => {
+        impl<Func, Ret, $($arg,)*> TypedFn<($($arg,)*)> for Func
+        where
+            Func: Fn($($arg),*) -> Ret + Shareable,
+            Ret: IntoAtomResult,
+            $($arg: for<'a> TryFrom<&'a Atom, Error = LispError>,)*
+        {
+            #[allow(unused_variables)]
+            fn call_with(&self, name: &str, args: &Atom) -> Result<Rc<Atom>> {
+                let args = arguments(name, args, $count)?;
+                self($(argument::<$arg>(name, &args, $index)?),*).into_atom_result()
+            }
+        }
+    };
+}
+
+typed_fn!(0);
+typed_fn!(1, A 0);
+typed_fn!(2, A 0, B 1);
+typed_fn!(3, A 0, B 1, C 2);
+typed_fn!(4, A 0, B 1, C 2, D 3);
+typed_fn!(5, A 0, B 1, C 2, D 3, E 4);
+typed_fn!(6, A 0, B 1, C 2, D 3, E 4, F 5);
@@ -0,0 +1,268 @@
+//! Errors of parsing and evaluating lisp code, which say what kind of error they are so that an application
+//! embedding lwhlisp can tell them apart.
+//!
+//! An error keeps the errors which caused it, each one adding what was being done when it happened, like
+//! `While evaluating closure`. Formatting it with `{:#}` shows the whole chain, separated by `: `. Errors are
+//! [`std::error::Error`]s whose source is their cause, so with the `eyre` feature they convert to
+//! `color_eyre::Report`s, which keep the chain and give the error back with `downcast_ref::<LispError>()`.
+
+use std::fmt;
+
+use crate::atom::Atom;
+use crate::parsing::Span;
+
+/// What went wrong.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Source which can not be parsed.
+    Parse,
+    /// A symbol which is not bound to any value.
+    UnboundSymbol,
+    /// A function, builtin or special form called with the wrong number of arguments.
+    Arity,
+    /// A value of the wrong type, like a string given where a number is expected.
+    Type,
+    /// A value of the right type which can not be used, like the logarithm of a negative number.
+    Value,
+    /// A form which is not well formed, like a `define` without a symbol or an improper list.
+    Syntax,
+    /// Reading or writing files, streams or processes failed.
+    Io,
+    /// Evaluation was interrupted, like by pressing ctrl-c.
+    Interrupted,
+    /// Evaluation was still running when its deadline passed.
+    TimedOut,
+    /// Evaluation used up its fuel.
+    FuelExhausted,
+    /// Evaluation allocated more than its limit.
+    AllocationLimitExceeded,
+    /// `exit` was called with this status. The process is not exited, so an application embedding lwhlisp decides
+    /// what to do, while the `lwhlisp` binary exits with the status.
+    Exit(i32),
+    /// Anything else, like the errors of a builtin of the application.
+    Other,
+}
+
+/// An error of parsing or evaluating lisp code.
+///
+/// It is boxed, so that results, which evaluation returns from each of its deeply nested calls, stay small. It
+/// keeps the atom it is about printed rather than the atom itself, so that it is [`Send`] and [`Sync`] even
+/// without the `arc` feature, like errors put into a `color_eyre::Report` must be.
+#[derive(Clone, Debug)]
+pub struct LispError(Box<Inner>);
+
+/// What a [`LispError`] is made of.
+#[derive(Clone, Debug)]
+struct Inner {
+    kind: ErrorKind,
+    message: String,
+    atom: Option<String>,
+    span: Option<Span>,
+    cause: Option<LispError>,
+}
+
+/// Result of parsing or evaluating lisp code.
+pub type Result<T, E = LispError> = std::result::Result<T, E>;
+
+/// Make a [`LispError`] of the given [`ErrorKind`], with a message formatted like [`format!`].
+#[macro_export]
+macro_rules! lisp_error {
+    ($kind:ident, $($arg:tt)*) => {
+        $crate::error::LispError::new($crate::error::ErrorKind::$kind, format!($($arg)*))
+    };
+}
+
+impl LispError {
+    /// Make an error of the given kind.
+    #[must_use]
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self(Box::new(Inner {
+            kind,
+            message: message.into(),
+            atom: None,
+            span: None,
+            cause: None,
+        }))
+    }
+
+    /// Make an error from a [`color_eyre::Report`], like one returned by a builtin of the application, keeping the
+    /// messages of its chain.
+    #[cfg(feature = "eyre")]
+    #[must_use]
+    pub fn from_report(report: &color_eyre::Report) -> Self {
+        Self::from_chain(ErrorKind::Other, report.chain())
+    }
+
+    /// Make an error from the messages of a chain of errors, outermost first.
+    fn from_chain<'a>(
+        kind: ErrorKind,
+        chain: impl Iterator<Item = &'a (dyn std::error::Error + 'static)>,
+    ) -> Self {
+        let mut messages = chain.map(ToString::to_string).collect::<Vec<_>>();
+        let mut error = Self::new(kind, messages.pop().unwrap_or_default());
+        while let Some(message) = messages.pop() {
+            error = error.context(message);
+        }
+        error
+    }
+
+    /// The same error, about `atom`.
+    #[must_use]
+    pub fn with_atom(mut self, atom: &Atom) -> Self {
+        self.0.atom = Some(atom.to_string());
+        self
+    }
+
+    /// The same error, at `span` in the source.
+    #[must_use]
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.0.span = Some(span);
+        self
+    }
+
+    /// Wrap the error in one saying what was being done when it happened, of the same kind.
+    #[must_use]
+    pub fn context(self, message: impl fmt::Display) -> Self {
+        Self(Box::new(Inner {
+            kind: self.0.kind,
+            message: message.to_string(),
+            atom: None,
+            span: None,
+            cause: Some(self),
+        }))
+    }
+
+    /// What kind of error this is, which is the kind of the error which caused it.
+    #[must_use]
+    pub const fn kind(&self) -> ErrorKind {
+        self.0.kind
+    }
+
+    /// The status `exit` was called with, if this error comes from calling it.
+    #[must_use]
+    pub const fn exit_status(&self) -> Option<i32> {
+        match self.0.kind {
+            ErrorKind::Exit(status) => Some(status),
+            _ => None,
+        }
+    }
+
+    /// The message of this error, without those of its causes.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.0.message
+    }
+
+    /// The atom the error is about, printed, from this error or the closest of its causes which has one.
+    #[must_use]
+    pub fn atom(&self) -> Option<&str> {
+        self.chain().find_map(|error| error.0.atom.as_deref())
+    }
+
+    /// Where in the source the error is, from this error or the closest of its causes which has one.
+    #[must_use]
+    pub fn span(&self) -> Option<&Span> {
+        self.chain().find_map(|error| error.0.span.as_ref())
+    }
+
+    /// The error which caused this one, if any.
+    #[must_use]
+    pub fn cause(&self) -> Option<&Self> {
+        self.0.cause.as_ref()
+    }
+
+    /// This error and the errors which caused it, outermost first.
+    pub fn chain(&self) -> impl Iterator<Item = &Self> {
+        std::iter::successors(Some(self), |error| error.cause())
+    }
+
+    /// The error which caused all the others.
+    #[must_use]
+    pub fn root_cause(&self) -> &Self {
+        self.chain().last().unwrap_or(self)
+    }
+}
+
+impl fmt::Display for LispError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.message)?;
+        if f.alternate() {
+            for cause in self.chain().skip(1) {
+                write!(f, ": {}", cause.0.message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LispError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.cause()
+            .map(|cause| cause as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// Make [`LispError`]s of the given kind from errors of the standard library and other crates, like those of
+/// reading a file, keeping the messages of their chain.
+macro_rules! from_errors {
+    ($kind:ident: $($(#[$attr:meta])* $error:ty),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            impl From<$error> for LispError {
+                fn from(error: $error) -> Self {
+                    Self::from_chain(
+                        ErrorKind::$kind,
+                        std::iter::successors(Some(&error as &dyn std::error::Error), |e| e.source()),
+                    )
+                }
+            }
+        )*
+    };
+}
+
+from_errors!(Io: std::io::Error);
+from_errors!(
+    Other: std::num::TryFromIntError,
+    std::str::Utf8Error,
+    std::string::FromUtf8Error,
+    std::time::SystemTimeError,
+    std::env::VarError,
+    #[cfg(feature = "encoding")]
+    base64::DecodeError,
+    #[cfg(feature = "encoding")]
+    hex::FromHexError,
+    #[cfg(feature = "datetime")]
+    chrono::ParseError,
+    #[cfg(feature = "toml")]
+    toml::de::Error,
+    #[cfg(feature = "toml")]
+    toml::ser::Error,
+    #[cfg(feature = "yaml")]
+    serde_yaml::Error,
+);
+
+/// Add what was being done to the error of a result, like [`LispError::context`].
+pub trait Context<T> {
+    /// Wrap the error in one with `message`.
+    ///
+    /// # Errors
+    /// If the result is an error, return the wrapped error.
+    fn context(self, message: impl fmt::Display) -> Result<T>;
+
+    /// Wrap the error in one with the message returned by `message`, which is only called if there is an error.
+    ///
+    /// # Errors
+    /// If the result is an error, return the wrapped error.
+    fn with_context<D: fmt::Display>(self, message: impl FnOnce() -> D) -> Result<T>;
+}
+
+impl<T, E: Into<LispError>> Context<T> for Result<T, E> {
+    fn context(self, message: impl fmt::Display) -> Result<T> {
+        self.map_err(|error| error.into().context(message))
+    }
+
+    fn with_context<D: fmt::Display>(self, message: impl FnOnce() -> D) -> Result<T> {
+        self.map_err(|error| error.into().context(message()))
+    }
+}
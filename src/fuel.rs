@@ -0,0 +1,45 @@
+//! Evaluation can be given an amount of fuel, of which evaluating each list uses one unit, so that code which
+//! loops forever, like an untrusted script, stops with an [`ErrorKind::FuelExhausted`] error instead of hanging.
+//!
+//! The fuel is kept per thread, so an evaluation started on another thread, like by `pmap`, is not limited.
+
+use std::cell::Cell;
+
+use crate::error::{ErrorKind, LispError, Result};
+use crate::lisp_error;
+
+thread_local! {
+    static FUEL: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Run `f` with the given amount of fuel, or without limit if it is `None`, returning what it returned and the
+/// fuel which is left.
+///
+/// The fuel of the current thread is restored afterwards, so that this can be nested.
+pub fn with<T>(fuel: Option<u64>, f: impl FnOnce() -> T) -> (T, Option<u64>) {
+    let previous = FUEL.with(|cell| cell.replace(fuel));
+    let result = f();
+    let left = FUEL.with(|cell| cell.replace(previous));
+    (result, left)
+}
+
+/// Use one unit of fuel, returning an [`ErrorKind::FuelExhausted`] error if there is none left.
+///
+/// # Errors
+/// If evaluation is limited and has no fuel left, return an error.
+pub fn consume() -> Result<()> {
+    FUEL.with(|cell| match cell.get() {
+        None => Ok(()),
+        Some(0) => Err(lisp_error!(FuelExhausted, "Fuel exhausted")),
+        Some(fuel) => {
+            cell.set(Some(fuel - 1));
+            Ok(())
+        }
+    })
+}
+
+/// Whether an error comes from an evaluation which ran out of fuel.
+#[must_use]
+pub fn is_exhausted(error: &LispError) -> bool {
+    error.kind() == ErrorKind::FuelExhausted
+}
@@ -0,0 +1,207 @@
+//! Atoms are immutable, so the only way to create a reference cycle is through the cache of a memoized function:
+//! a cached result can refer back to the function, for example through the environment of a closure it returned.
+//! Reference counting alone never frees such a cycle, so [`collect`] clears the caches which can no longer be
+//! reached from the environment a program is evaluated in.
+//!
+//! Clearing a cache only loses results which can be computed again, so if a cache is mistakenly considered
+//! unreachable, for example because it is only held by an expression which is currently being evaluated, the
+//! program still behaves the same.
+
+use std::collections::HashSet;
+
+use tracing::info;
+
+use crate::atom::{Atom, MemoCache, Rc, Weak};
+use crate::env::Env;
+
+/// Interior mutability for the caches of an environment, a [`std::sync::Mutex`] with the `arc` feature.
+#[cfg(not(feature = "arc"))]
+type Mutable<T> = std::cell::RefCell<T>;
+/// Interior mutability for the caches of an environment, a [`std::sync::Mutex`] with the `arc` feature.
+#[cfg(feature = "arc")]
+type Mutable<T> = std::sync::Mutex<T>;
+
+/// How many caches have to be registered before [`collect_if_due`] first collects.
+const MIN_THRESHOLD: usize = 64;
+
+/// The caches of memoized functions holding results, which are shared by an environment and the environments made
+/// in it, so that each interpreter only clears its own caches.
+#[derive(Clone, Default)]
+pub struct Caches(Rc<Mutable<Registry>>);
+
+#[derive(Default)]
+struct Registry {
+    caches: Vec<Weak<MemoCache>>,
+    /// How many caches there can be before [`collect_if_due`] collects again.
+    threshold: usize,
+}
+
+impl Caches {
+    fn lock(&self) -> impl std::ops::DerefMut<Target = Registry> + '_ {
+        #[cfg(not(feature = "arc"))]
+        let registry = self.0.borrow_mut();
+        // a panic while holding the lock at worst leaves a cache registered twice
+        #[cfg(feature = "arc")]
+        let registry = self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        registry
+    }
+}
+
+impl std::fmt::Debug for Caches {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Caches")
+    }
+}
+
+/// The caches are bookkeeping, not part of what an environment binds, so they never make environments unequal.
+impl PartialEq for Caches {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Keep track of the cache of a memoized function, so that [`collect`] can clear it once it is unreachable.
+///
+/// A cache only needs to be registered when it stores its first result, as an empty cache can not be part of a
+/// reference cycle.
+pub fn register(env: &Env, cache: &Rc<MemoCache>) {
+    let caches = env.caches();
+    let mut registry = caches.lock();
+    if registry.caches.len() == registry.caches.capacity() {
+        registry.caches.retain(|cache| cache.strong_count() > 0);
+    }
+    registry.caches.push(Rc::downgrade(cache));
+}
+
+/// Clear the caches of memoized functions registered in `root` which can not be reached from it, freeing the
+/// reference cycles they are part of.
+///
+/// Returns the number of caches that were cleared.
+pub fn collect(root: &Env) -> usize {
+    let caches = root.caches().clone();
+    let live = {
+        let mut registry = caches.lock();
+        registry.caches.retain(|cache| cache.strong_count() > 0);
+        registry
+            .caches
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>()
+    };
+    if live.is_empty() {
+        caches.lock().threshold = MIN_THRESHOLD;
+        return 0;
+    }
+
+    let reachable = mark(root);
+    let (unreachable, kept): (Vec<_>, Vec<_>) = live
+        .into_iter()
+        .partition(|cache| !reachable.contains(&Rc::as_ptr(cache).cast()));
+    let unreachable_ids = unreachable
+        .iter()
+        .map(|cache| Rc::as_ptr(cache).cast())
+        .collect::<HashSet<*const ()>>();
+    {
+        // a cleared cache registers itself again once it stores a result
+        let mut registry = caches.lock();
+        registry
+            .caches
+            .retain(|cache| !unreachable_ids.contains(&cache.as_ptr().cast()));
+        registry.threshold = MIN_THRESHOLD.max(2 * kept.len());
+    }
+    for cache in &unreachable {
+        cache.clear();
+    }
+    let cleared = unreachable.len();
+    if cleared > 0 {
+        info!("Cleared {cleared} unreachable memoization caches");
+    }
+    cleared
+}
+
+/// [`collect`], but only once enough caches were registered since the last collection that it is worth marking
+/// everything reachable from `root` again.
+pub fn collect_if_due(root: &Env) {
+    let due = {
+        let caches = root.caches();
+        let registry = caches.lock();
+        registry.caches.len() >= registry.threshold.max(MIN_THRESHOLD)
+    };
+    if due {
+        collect(root);
+    }
+}
+
+/// Something that holds strong references to other nodes.
+enum Node {
+    Atom(Rc<Atom>),
+    Env(Rc<Env>),
+    Cache(Rc<MemoCache>),
+}
+
+impl Node {
+    fn id(&self) -> *const () {
+        match self {
+            Node::Atom(atom) => Rc::as_ptr(atom).cast(),
+            Node::Env(env) => Rc::as_ptr(env).cast(),
+            Node::Cache(cache) => Rc::as_ptr(cache).cast(),
+        }
+    }
+
+    /// Add the nodes this node holds references to to `children`.
+    fn children(&self, children: &mut Vec<Node>) {
+        match self {
+            Node::Atom(atom) => match atom.as_ref() {
+                Atom::Pair(car, cdr) => {
+                    children.extend([Node::Atom(car.clone()), Node::Atom(cdr.clone())]);
+                }
+                Atom::Closure(env, args, body) | Atom::Macro(env, args, body) => {
+                    env_children(env, children);
+                    children.extend([Node::Atom(args.clone()), Node::Atom(body.clone())]);
+                }
+                Atom::Map(map) => {
+                    children.extend(map.iter().flat_map(|(key, value)| {
+                        [Node::Atom(key.clone()), Node::Atom(value.clone())]
+                    }));
+                }
+                Atom::Memoized(function, cache) => {
+                    children.extend([Node::Atom(function.clone()), Node::Cache(cache.clone())]);
+                }
+                Atom::Number(_)
+                | Atom::String(_)
+                | Atom::Symbol(_)
+                | Atom::Keyword(_)
+                | Atom::NativeFunc(_) => {}
+            },
+            Node::Env(env) => env_children(env, children),
+            // the lock is released before the children are visited, so that a cache is never locked twice
+            Node::Cache(cache) => {
+                children.extend(cache.lock().iter().flat_map(|(args, result)| {
+                    [Node::Atom(args.0.clone()), Node::Atom(result.clone())]
+                }));
+            }
+        }
+    }
+}
+
+fn env_children(env: &Env, children: &mut Vec<Node>) {
+    let (bindings, outer) = env.references();
+    children.extend(bindings.cloned().map(Node::Atom));
+    children.extend(outer.cloned().map(Node::Env));
+}
+
+/// Find the identities of all nodes reachable from `root`.
+fn mark(root: &Env) -> HashSet<*const ()> {
+    let mut reachable = HashSet::new();
+    let mut stack = Vec::new();
+    env_children(root, &mut stack);
+    while let Some(node) = stack.pop() {
+        if reachable.insert(node.id()) {
+            node.children(&mut stack);
+        }
+    }
+    reachable
+}
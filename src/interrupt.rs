@@ -0,0 +1,154 @@
+//! Evaluation checks the flag of the interpreter it runs in before evaluating each list, so that a long running
+//! evaluation can be stopped from outside, for example by pressing ctrl-c in the REPL. It also checks the deadline of
+//! the current thread, if there is one, so that an evaluation can be given a timeout.
+
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::error::{ErrorKind, LispError, Result};
+use crate::lisp_error;
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+    /// The flag of the evaluation running on this thread.
+    static WATCHED: RefCell<Option<Interrupt>> = const { RefCell::new(None) };
+}
+
+/// A flag which makes the evaluations watching it stop with an [`ErrorKind::Interrupted`] error.
+///
+/// Clones share the flag, so that a clone can be kept to interrupt evaluations from another thread, or from a signal
+/// handler.
+#[derive(Clone, Debug, Default)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    /// Make a flag which is not set.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the evaluations watching this flag stop.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Forget about an interruption which no evaluation has stopped for yet.
+    pub fn clear(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether the flag is set.
+    #[must_use]
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Run `f`, making the evaluations it does on the current thread stop once `interrupt` is set.
+///
+/// The flag stays set until the outermost call for it returns, so that catching the error in lisp does not let
+/// evaluation go on, and is cleared then, so that it does not stop the next evaluation. The flag watched before is
+/// restored afterwards, so that this can be nested.
+pub fn with<T>(interrupt: &Interrupt, f: impl FnOnce() -> T) -> T {
+    let outermost = !current().is_some_and(|current| Arc::ptr_eq(&current.0, &interrupt.0));
+    let result = watch(interrupt, f);
+    if outermost {
+        interrupt.clear();
+    }
+    result
+}
+
+/// Run `f` like [`with`], but leave the flag as it is afterwards, for threads helping an evaluation which clears it
+/// itself.
+pub(crate) fn watch<T>(interrupt: &Interrupt, f: impl FnOnce() -> T) -> T {
+    let previous = WATCHED.with(|cell| cell.replace(Some(interrupt.clone())));
+    let result = f();
+    WATCHED.with(|cell| cell.replace(previous));
+    result
+}
+
+/// The flag watched by the evaluation running on this thread, if there is one, so that threads it starts can watch
+/// it too.
+#[must_use]
+pub fn current() -> Option<Interrupt> {
+    WATCHED.with(|cell| cell.borrow().clone())
+}
+
+/// Run `f`, making the evaluations it does on the current thread stop with an [`ErrorKind::TimedOut`] error once
+/// `deadline` passes.
+///
+/// The deadline of the current thread is restored afterwards, so that this can be nested.
+pub fn with_deadline<T>(deadline: Instant, f: impl FnOnce() -> T) -> T {
+    let previous = DEADLINE.with(|cell| cell.replace(Some(deadline)));
+    let result = f();
+    DEADLINE.with(|cell| cell.set(previous));
+    result
+}
+
+/// Return an [`ErrorKind::Interrupted`] error if the flag watched on the current thread is set, or an
+/// [`ErrorKind::TimedOut`] error if the deadline of the current thread passed.
+///
+/// # Errors
+/// If evaluation was interrupted or timed out, return an error.
+pub fn check() -> Result<()> {
+    if WATCHED.with(|cell| {
+        cell.borrow()
+            .as_ref()
+            .is_some_and(Interrupt::is_interrupted)
+    }) {
+        Err(lisp_error!(Interrupted, "Interrupted"))
+    } else if DEADLINE
+        .with(Cell::get)
+        .is_some_and(|deadline| Instant::now() >= deadline)
+    {
+        Err(lisp_error!(TimedOut, "Timed out"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether an error comes from an interrupted evaluation.
+#[must_use]
+pub fn is_interrupted(error: &LispError) -> bool {
+    error.kind() == ErrorKind::Interrupted
+}
+
+/// Whether an error comes from an evaluation which timed out.
+#[must_use]
+pub fn is_timed_out(error: &LispError) -> bool {
+    error.kind() == ErrorKind::TimedOut
+}
+
+/// The flag set when ctrl-c is pressed.
+#[cfg(all(unix, feature = "cli"))]
+static CTRL_C: std::sync::OnceLock<Interrupt> = std::sync::OnceLock::new();
+
+/// Set `interrupt` when ctrl-c is pressed, instead of exiting.
+///
+/// Only the flag given the first time is set.
+#[cfg(all(unix, feature = "cli"))]
+pub fn interrupt_on_ctrl_c(interrupt: &Interrupt) {
+    extern "C" fn handle(_signal: libc::c_int) {
+        if let Some(interrupt) = CTRL_C.get() {
+            interrupt.interrupt();
+        }
+    }
+
+    if CTRL_C.set(interrupt.clone()).is_err() {
+        return;
+    }
+    // SAFETY: the handler only loads the flag, which was set before, and stores to an atomic, which is safe to do
+    // in a signal handler
+    unsafe {
+        libc::signal(libc::SIGINT, handle as *const () as libc::sighandler_t);
+    }
+}
+
+/// Set `interrupt` when ctrl-c is pressed, instead of exiting.
+///
+/// This is only supported on unix with the `cli` feature, which pulls in libc, so this does nothing otherwise.
+#[cfg(not(all(unix, feature = "cli")))]
+pub const fn interrupt_on_ctrl_c(_interrupt: &Interrupt) {}
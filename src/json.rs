@@ -0,0 +1,98 @@
+use std::fmt::Write as _;
+
+use crate::atom::Atom;
+use crate::parsing::{Span, SpanTree};
+
+/// Quote and escape `s` as a JSON string.
+#[must_use]
+pub fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A JSON object with the given fields, whose values are already written as JSON.
+#[must_use]
+pub fn object(fields: &[(&str, String)]) -> String {
+    let fields = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{value}", string(key)))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// A span as a JSON array of its start and end, in characters.
+#[must_use]
+pub fn span(span: &Span) -> String {
+    format!("[{},{}]", span.start, span.end)
+}
+
+/// The syntax tree of a parsed atom as a JSON object.
+///
+/// Every node has a `type` and a `span`. Numbers, strings, symbols and keywords have a `value`, keywords without
+/// their `:`. Lists have their `elements`, and a `tail` if they are improper, like `(a . b)`. Maps have their printed
+/// form as `value`.
+#[must_use]
+pub fn syntax_tree(atom: &Atom, spans: &SpanTree) -> String {
+    let span = self::span(&spans.span);
+    match atom {
+        Atom::Number(x) if x.is_finite() => {
+            format!(r#"{{"type":"number","value":{x},"span":{span}}}"#)
+        }
+        Atom::Number(_) => format!(r#"{{"type":"number","value":null,"span":{span}}}"#),
+        Atom::String(s) | Atom::Symbol(s) | Atom::Keyword(s) => {
+            let kind = match atom {
+                Atom::String(_) => "string",
+                Atom::Symbol(_) => "symbol",
+                _ => "keyword",
+            };
+            format!(r#"{{"type":"{kind}","value":{},"span":{span}}}"#, string(s))
+        }
+        Atom::Pair(_, _) => {
+            // the elements of a list given by a reader macro have no spans, so they get the span of the list
+            let expanded = SpanTree {
+                span: spans.span.clone(),
+                elements: Vec::new(),
+                comments: Vec::new(),
+            };
+            let mut element_spans = spans.elements.iter();
+            let mut elements = Vec::new();
+            let mut rest = atom;
+            while let Atom::Pair(car, cdr) = rest {
+                elements.push(syntax_tree(car, element_spans.next().unwrap_or(&expanded)));
+                rest = cdr;
+            }
+            let tail = if rest.is_nil() {
+                String::new()
+            } else {
+                let tail = syntax_tree(rest, element_spans.next().unwrap_or(&expanded));
+                format!(r#","tail":{tail}"#)
+            };
+            format!(
+                r#"{{"type":"list","elements":[{}]{tail},"span":{span}}}"#,
+                elements.join(",")
+            )
+        }
+        Atom::Map(_) => format!(
+            r#"{{"type":"map","value":{},"span":{span}}}"#,
+            string(&atom.to_string())
+        ),
+        // only reader macros can give these, which have no syntax of their own
+        a => format!(
+            r#"{{"type":"other","value":{},"span":{span}}}"#,
+            string(&a.to_string())
+        ),
+    }
+}
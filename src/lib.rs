@@ -9,29 +9,91 @@
 // I find this clearer sometimes
 #![allow(clippy::use_self)]
 
+#[cfg(feature = "eyre")]
 use std::{fs::File, io::Read};
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
 
+#[cfg(feature = "diagnostics")]
 use ariadne::{Color, Fmt, Label, Report, Source};
 use chumsky::prelude::*;
+#[cfg(feature = "eyre")]
 use color_eyre::eyre::Context;
+#[cfg(feature = "diagnostics")]
 use tracing::info;
+use tracing::warn;
 
+use crate::{
+    atom::{Atom, Rc},
+    env::Env,
+    error::LispError,
+    parsing::{line_column, parser_with_reader_macros, ReaderMacros},
+};
+
+/// Limiting how much memory evaluation can allocate
+pub mod allocation;
 /// s-expressions and evaluating
 pub mod atom;
+/// Caching of parsed source files
+#[cfg(feature = "cli")]
+pub mod cache;
+/// Settings and library files outside of the command line arguments
+#[cfg(feature = "cli")]
+pub mod config;
 /// Environment and data storage
 pub mod env;
+/// Errors of parsing and evaluating
+pub mod error;
+/// Limiting how much evaluation can do
+pub mod fuel;
+/// Collection of reference cycles
+pub mod gc;
+/// Interruption of evaluation
+pub mod interrupt;
+/// Writing of JSON, for tools reading the output of lwhlisp
+pub mod json;
+/// Checks of code without evaluating it
+pub mod lint;
+/// Loading of modules with require and provide
+pub mod module;
+/// Hooks called by the evaluator
+pub mod observer;
+/// Simplification of atoms before evaluation
+pub mod optimize;
+/// Where the print builtins write
+pub mod output;
 /// Parsing of s-expressions
 pub mod parsing;
+/// Profiling of function calls
+pub mod profile;
+/// Saving the bindings of a REPL session
+pub mod session;
+/// JavaScript API, for running in a browser
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 #[cfg(test)]
 mod tests;
 
-/// Convenience function to read a file to a string.
+/// Source of the default library, evaluated at startup unless other library files are given.
+pub const DEFAULT_LIBRARY: &str = include_str!("../lib/lib.lisp");
+
+/// Convenience function to read a file to a string. The path `-` reads from stdin instead.
 ///
 /// # Errors
 /// If there is an error opening or reading the file, this will return an error.
+#[cfg(feature = "eyre")]
 pub fn read_file_to_string(path: &str) -> Result<String, color_eyre::Report> {
-    let mut library_file = File::open(path).context(format!("While opening file {}", path))?;
+    if path == "-" {
+        let mut src = String::new();
+        std::io::stdin()
+            .read_to_string(&mut src)
+            .context("While reading stdin")?;
+        return Ok(src);
+    }
+    let mut library_file = File::open(path).context(format!("While opening file {path}"))?;
     let mut src = String::new();
     library_file
         .read_to_string(&mut src)
@@ -39,39 +101,65 @@ pub fn read_file_to_string(path: &str) -> Result<String, color_eyre::Report> {
     Ok(src)
 }
 
+/// Describe a parse error in a sentence.
+#[must_use]
+pub fn parse_error_message(e: &Simple<char>) -> String {
+    if let chumsky::error::SimpleReason::Custom(msg) = e.reason() {
+        msg.clone()
+    } else {
+        format!(
+            "{}{}, expected {}",
+            if e.found().is_some() {
+                "Unexpected token"
+            } else {
+                "Unexpected end of input"
+            },
+            e.label()
+                .map_or_else(String::new, |label| format!(" while parsing {label}")),
+            if e.expected().len() == 0 {
+                "something else".to_string()
+            } else {
+                let res = e
+                    .expected()
+                    .map(|expected| {
+                        expected
+                            .as_ref()
+                            .map_or_else(|| "end of input".to_string(), ToString::to_string)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                res
+            },
+        )
+    }
+}
+
+/// Make an error of kind [`Parse`](error::ErrorKind::Parse) listing the parse errors of `src`, at the span of the
+/// first one.
+#[must_use]
+pub fn parse_error(src: &str, errs: &[Simple<char>]) -> LispError {
+    let messages = errs
+        .iter()
+        .map(|e| {
+            let (line, column) = line_column(src, e.span().start);
+            format!("{line}:{column}: {}", parse_error_message(e))
+        })
+        .collect::<Vec<_>>();
+    let error = lisp_error!(Parse, "Could not parse the source: {}", messages.join(", "));
+    match errs.first() {
+        Some(e) => error.with_span(e.span()),
+        None => error,
+    }
+}
+
 /// Pretty-print parse errors using ariadne.
 ///
 /// # Panics
 /// This may panic.
+#[cfg(feature = "diagnostics")]
 pub fn print_parse_errs(errs: Vec<Simple<char>>, src: &str) {
     for e in errs {
-        let msg = if let chumsky::error::SimpleReason::Custom(msg) = e.reason() {
-            msg.clone()
-        } else {
-            format!(
-                "{}{}, expected {}",
-                if e.found().is_some() {
-                    "Unexpected token"
-                } else {
-                    "Unexpected end of input"
-                },
-                e.label()
-                    .map_or_else(String::new, |label| format!(" while parsing {}", label)),
-                if e.expected().len() == 0 {
-                    "something else".to_string()
-                } else {
-                    let res = e
-                        .expected()
-                        .map(|expected| match expected {
-                            Some(expected) => expected.to_string(),
-                            None => "end of input".to_string(),
-                        })
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    res
-                },
-            )
-        };
+        let msg = parse_error_message(&e);
 
         let label = Label::new(e.span()).with_message(match e.reason() {
             chumsky::error::SimpleReason::Custom(msg) => msg.clone(),
@@ -110,3 +198,241 @@ pub fn print_parse_errs(errs: Vec<Simple<char>>, src: &str) {
         report.finish().eprint(Source::from(&src)).unwrap();
     }
 }
+
+/// Get the reader macros defined by lisp code, as an association list from names to functions bound to
+/// `*reader-macros*`.
+///
+/// The functions are called with a copy of `env`, so definitions they make are forgotten.
+#[must_use]
+pub fn reader_macros(env: &Env) -> ReaderMacros {
+    let mut reader_macros = ReaderMacros::default();
+    let entries = match env
+        .lookup("*reader-macros*")
+        .map(|list| Atom::list_to_vec(&list))
+    {
+        None => return reader_macros,
+        Some(Ok(entries)) => entries,
+        Some(Err(e)) => {
+            warn!("Ignoring *reader-macros*, which is not a list: {e}");
+            return reader_macros;
+        }
+    };
+
+    for entry in entries {
+        let name = match entry.car().get_string() {
+            Ok(name) => name,
+            Err(e) => {
+                warn!("Ignoring reader macro {entry}, whose name is not a string: {e}");
+                continue;
+            }
+        };
+        let function = entry.cdr();
+        let env = env.clone();
+        reader_macros.insert(&*name, move |argument| {
+            let argument =
+                Atom::list_from_vec(vec![Rc::new(Atom::symbol("quote")), Rc::new(argument)]);
+            let call = Atom::list_from_vec(vec![function.clone(), argument]);
+            Atom::eval(call, &mut env.clone())
+                .map(|expanded| expanded.as_ref().clone())
+                .map_err(|e| format!("{e:#}"))
+        });
+    }
+    reader_macros
+}
+
+/// An interpreter with its environment, for applications embedding lwhlisp.
+///
+/// It parses source with the reader macros of its environment, and evaluates it there, so that definitions are
+/// kept from one call to the next.
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    env: Env,
+    /// Fuel left for evaluation, or `None` if it is not limited.
+    fuel: Option<u64>,
+    /// Bytes each call can allocate, or `None` if it is not limited.
+    allocation_limit: Option<usize>,
+    /// Where the print builtins write, or `None` for stdout.
+    output: Option<output::Output>,
+    /// Flag stopping the evaluations of this interpreter.
+    interrupt: interrupt::Interrupt,
+}
+
+/// A clone gets a flag of its own, so that interrupting one interpreter does not stop the other.
+impl Clone for Interpreter {
+    fn clone(&self) -> Self {
+        Self {
+            env: self.env.clone(),
+            fuel: self.fuel,
+            allocation_limit: self.allocation_limit,
+            output: self.output.clone(),
+            interrupt: interrupt::Interrupt::new(),
+        }
+    }
+}
+
+impl Interpreter {
+    /// Make an interpreter with only the builtins defined, see [`Interpreter::load_library`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make an interpreter with only the builtins needing one of the given capabilities, like
+    /// [`Capability::SANDBOX`](env::Capability::SANDBOX) for untrusted code.
+    #[must_use]
+    pub fn with_capabilities(capabilities: &[env::Capability]) -> Self {
+        Self {
+            env: Env::with_capabilities(capabilities),
+            ..Self::default()
+        }
+    }
+
+    /// Evaluate the [default library](DEFAULT_LIBRARY), which defines functions like `map` and macros like `let`.
+    ///
+    /// # Errors
+    /// If an s-expression of the library can not be evaluated.
+    pub fn load_library(&mut self) -> error::Result<()> {
+        self.eval_str(DEFAULT_LIBRARY)
+            .map_err(|e| e.context("While loading the default library"))?;
+        Ok(())
+    }
+
+    /// Evaluate each top-level s-expression of `src`, returning their results.
+    ///
+    /// # Errors
+    /// If `src` can not be parsed, nothing is evaluated. Else, evaluation stops at the first s-expression which
+    /// can not be evaluated, whose error is returned. A parse error is at the span of the first error of the source.
+    pub fn eval_str(&mut self, src: &str) -> error::Result<Vec<Rc<Atom>>> {
+        // only the end is trimmed, so that the spans and lines of parse errors are those of `src`
+        let src = src.trim_end();
+        let atoms = parser_with_reader_macros(reader_macros(&self.env))
+            .parse(src)
+            .map_err(|errs| parse_error(src, &errs))?;
+        let env = &mut self.env;
+        let (results, fuel) = fuel::with(self.fuel, || {
+            allocation::with(self.allocation_limit, || {
+                output::with(self.output.clone(), || {
+                    interrupt::with(&self.interrupt, || {
+                        atoms
+                            .into_iter()
+                            .map(|(atom, _)| Atom::eval(Rc::new(atom), env))
+                            .collect()
+                    })
+                })
+            })
+        });
+        self.fuel = fuel;
+        results
+    }
+
+    /// Evaluate `src` like [`Interpreter::eval_str`], stopping with an
+    /// [`ErrorKind::TimedOut`](error::ErrorKind::TimedOut) error if it is still running after `timeout`.
+    ///
+    /// The definitions evaluated before the deadline passed are kept.
+    ///
+    /// # Errors
+    /// Like [`Interpreter::eval_str`], or if evaluation timed out.
+    pub fn eval_with_timeout(
+        &mut self,
+        src: &str,
+        timeout: Duration,
+    ) -> error::Result<Vec<Rc<Atom>>> {
+        // a timeout too long to have a deadline never passes
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => interrupt::with_deadline(deadline, || self.eval_str(src)),
+            None => self.eval_str(src),
+        }
+    }
+
+    /// Evaluate `src` like [`Interpreter::eval_str`], telling `observer` about each application, and give the
+    /// observer back with the results.
+    pub fn eval_observed<O: observer::EvalObserver + 'static>(
+        &mut self,
+        src: &str,
+        observer: O,
+    ) -> (error::Result<Vec<Rc<Atom>>>, O) {
+        observer::observe(observer, || self.eval_str(src))
+    }
+
+    /// Evaluate each top-level s-expression of the file at `path`, returning their results.
+    ///
+    /// # Errors
+    /// If the file can not be read, or like [`Interpreter::eval_str`].
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> error::Result<Vec<Rc<Atom>>> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path)
+            .map_err(|e| LispError::from(e).context(format!("While reading {}", path.display())))?;
+        module::in_file(path, || self.eval_str(&src))
+            .map_err(|e| e.context(format!("While evaluating {}", path.display())))
+    }
+
+    /// Limit evaluation to `fuel` more reductions, each evaluation of a list using one of them.
+    ///
+    /// Once it is used up, evaluation stops with an [`ErrorKind::FuelExhausted`](error::ErrorKind::FuelExhausted)
+    /// error, until more fuel is given. The fuel is shared by all later calls, until it is set again.
+    pub const fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+    }
+
+    /// Let evaluation run without limit again.
+    pub const fn clear_fuel(&mut self) {
+        self.fuel = None;
+    }
+
+    /// The fuel left for evaluation, or `None` if it is not limited.
+    #[must_use]
+    pub const fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Limit each later call to allocating about `bytes` of memory, counted as described in [`allocation`].
+    ///
+    /// Going over it stops evaluation with an
+    /// [`ErrorKind::AllocationLimitExceeded`](error::ErrorKind::AllocationLimitExceeded) error.
+    pub const fn set_allocation_limit(&mut self, bytes: usize) {
+        self.allocation_limit = Some(bytes);
+    }
+
+    /// Let evaluation allocate without limit again.
+    pub const fn clear_allocation_limit(&mut self) {
+        self.allocation_limit = None;
+    }
+
+    /// Make the print builtins write to `output` instead of stdout, like a [`Buffer`](output::Buffer) to capture
+    /// what scripts print.
+    pub fn set_output(&mut self, output: output::Output) {
+        self.output = Some(output);
+    }
+
+    /// Make the print builtins write to stdout again.
+    pub fn clear_output(&mut self) {
+        self.output = None;
+    }
+
+    /// Look for the modules required by this interpreter in `directories`, after the directory of the file being
+    /// evaluated, and before those of [`module::PATH_VARIABLE`].
+    pub fn set_module_path(&mut self, directories: Vec<std::path::PathBuf>) {
+        module::set_search_path(&self.env, directories);
+    }
+
+    /// A handle on the flag stopping the evaluations of this interpreter, which can be set from another thread or a
+    /// signal handler to make the running evaluation stop with an
+    /// [`ErrorKind::Interrupted`](error::ErrorKind::Interrupted) error.
+    ///
+    /// The flag is cleared once the evaluation it stopped returns.
+    #[must_use]
+    pub fn interrupt_handle(&self) -> interrupt::Interrupt {
+        self.interrupt.clone()
+    }
+
+    /// The environment the interpreter evaluates in.
+    #[must_use]
+    pub const fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// The environment the interpreter evaluates in, to define or change bindings.
+    pub const fn env_mut(&mut self) -> &mut Env {
+        &mut self.env
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+
+use crate::atom::{Atom, Rc};
+use crate::env::Env;
+
+/// Find the symbols in `atoms` which would be evaluated, but are not bound to anything.
+///
+/// A symbol is bound if it is bound in `env`, defined anywhere at the top level of `atoms`, or is a local name of an
+/// enclosing function. Quoted atoms, the arguments of macros, and the bodies of macro definitions are left alone, as
+/// it is not known which of their symbols are evaluated.
+///
+/// The returned atoms are those from `atoms`, so that their spans can be looked up in a
+/// [`SourceMap`](crate::parsing::SourceMap).
+#[must_use]
+pub fn unbound_symbols(atoms: &[Rc<Atom>], env: &Env) -> Vec<Rc<Atom>> {
+    let mut checker = Checker {
+        env,
+        defined: HashSet::new(),
+        macros: HashSet::new(),
+        local: HashSet::new(),
+        unbound: Vec::new(),
+    };
+    // a name can be used before the top-level definition of it is evaluated, for example in a function body
+    for atom in atoms {
+        checker.define(atom);
+    }
+    for atom in atoms {
+        checker.check(atom);
+    }
+    checker.unbound
+}
+
+struct Checker<'a> {
+    env: &'a Env,
+    /// Names defined at the top level.
+    defined: HashSet<Rc<str>>,
+    /// Names of the macros defined at the top level.
+    macros: HashSet<Rc<str>>,
+    /// Names bound by enclosing functions and local definitions.
+    local: HashSet<Rc<str>>,
+    unbound: Vec<Rc<Atom>>,
+}
+
+impl Checker<'_> {
+    /// Record the name defined by `atom`, if it is a definition.
+    fn define(&mut self, atom: &Rc<Atom>) {
+        let Some((form, name)) = definition(atom) else {
+            return;
+        };
+        if &*form == "defmacro" {
+            self.macros.insert(name.clone());
+        }
+        self.defined.insert(name);
+    }
+
+    fn is_bound(&self, name: &Rc<str>) -> bool {
+        self.local.contains(name) || self.defined.contains(name) || self.env.lookup(name).is_some()
+    }
+
+    fn is_macro(&self, name: &Rc<str>) -> bool {
+        if self.local.contains(name) {
+            return false;
+        }
+        self.macros.contains(name)
+            || matches!(self.env.lookup(name).as_deref(), Some(Atom::Macro(_, _, _)))
+    }
+
+    fn check(&mut self, atom: &Rc<Atom>) {
+        let (op, args) = match atom.as_ref() {
+            Atom::Symbol(name) => {
+                if !self.is_bound(name) {
+                    self.unbound.push(atom.clone());
+                }
+                return;
+            }
+            Atom::Pair(op, args) => (op, args),
+            _ => return,
+        };
+        let Ok(items) = Atom::list_to_vec(args) else {
+            return;
+        };
+
+        let special_form = match op.as_ref() {
+            Atom::Symbol(name) if self.is_macro(name) => return,
+            Atom::Symbol(name) if !self.local.contains(name) => {
+                match self.env.lookup(name).as_deref() {
+                    Some(Atom::Symbol(special_form)) => Some(special_form.clone()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match (special_form.as_deref(), items.as_slice()) {
+            (Some("quote" | "defmacro" | "doc"), _) => {}
+            (Some("define"), [name, value]) if matches!(name.as_ref(), Atom::Symbol(_)) => {
+                self.check(value);
+            }
+            (Some("define"), [signature, body @ ..]) if Atom::is_list(signature) => {
+                self.check_body(&signature.cdr(), body);
+            }
+            (Some("lambda"), [params, body @ ..]) => self.check_body(params, body),
+            _ => {
+                self.check(op);
+                for item in &items {
+                    self.check(item);
+                }
+            }
+        }
+    }
+
+    /// Check the body of a function, where the names in `params` and those defined in the body are local.
+    fn check_body(&mut self, params: &Rc<Atom>, body: &[Rc<Atom>]) {
+        let outer = self.local.clone();
+        let mut params = params.clone();
+        while let Atom::Pair(param, rest) = params.as_ref() {
+            if let Atom::Symbol(name) = param.as_ref() {
+                self.local.insert(name.clone());
+            }
+            let rest = rest.clone();
+            params = rest;
+        }
+        if let Atom::Symbol(name) = params.as_ref() {
+            self.local.insert(name.clone());
+        }
+        for form in body {
+            if let Some((_, name)) = definition(form) {
+                self.local.insert(name);
+            }
+        }
+        for form in body {
+            self.check(form);
+        }
+        self.local = outer;
+    }
+}
+
+/// Get the kind of definition and the defined name, if `atom` is a `define` or `defmacro` form.
+fn definition(atom: &Rc<Atom>) -> Option<(Rc<str>, Rc<str>)> {
+    let Atom::Pair(op, rest) = atom.as_ref() else {
+        return None;
+    };
+    let form = op.get_symbol_name().ok()?;
+    if !matches!(&*form, "define" | "defmacro") {
+        return None;
+    }
+    let name = rest.car();
+    let name = if Atom::is_list(&name) {
+        name.car()
+    } else {
+        name
+    };
+    Some((form, name.get_symbol_name().ok()?))
+}
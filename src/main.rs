@@ -9,28 +9,72 @@
 // I find this clearer sometimes
 #![allow(clippy::use_self)]
 
-use std::rc::Rc;
+use std::borrow::Cow;
+use std::ops::{ControlFlow, Range};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use chumsky::Parser as _;
+use ariadne::{Color, Fmt};
+
+use chumsky::{error::Simple, Parser as _};
 use clap::Parser as _;
-use color_eyre::{eyre::Context, Result};
-use lwhlisp::{atom::Atom, env::Env, parsing::parser, print_parse_errs, read_file_to_string};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Report, Result,
+};
+use lwhlisp::{
+    atom::{colors, Atom, Rc},
+    cache,
+    config::{self, Config},
+    env::{Capability, Env},
+    error, gc, interrupt, json, lint, module,
+    optimize::optimize,
+    output::{self, Buffer, Output},
+    parse_error_message,
+    parsing::{line_column, parser_with_reader_macros, Parsed, SourceMap, SourceText, Span},
+    print_parse_errs, profile, read_file_to_string, reader_macros, session, DEFAULT_LIBRARY,
+};
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
 use tracing::{info, instrument};
+use tracing_subscriber::{filter::LevelFilter, EnvFilter};
 
 /// lwhlisp -- Lisp interpreter in Rust
 /// Run a file or a REPL. If not FILE is give, run a REPL
 #[derive(clap::Parser, Debug)]
 #[clap(author, version, about, propagate_version = true)]
+// the code generated for `last = true` arguments trips this lint
+#[allow(clippy::uninlined_format_args)]
+// each flag is a bool, as clap parses them
+#[allow(clippy::struct_excessive_bools)]
 struct Args {
-    /// Overide library files to evaluate at startup
+    /// Overide library files to evaluate at startup. If none are given, the default library is used: the first one
+    /// found in `LWHLISP_LIBRARY_PATH`, the library paths of the configuration files or `~/.config/lwhlisp`, or
+    /// else the one embedded in the binary
     #[clap(long)]
     library: Vec<String>,
 
-    /// Files to evaluate
+    /// Do not evaluate any library files, so that only the builtins are defined
+    #[clap(long, conflicts_with = "library")]
+    no_library: bool,
+
+    /// Files to evaluate. `-` reads a program from stdin
     #[clap(short, long)]
     files: Vec<String>,
 
-    /// Start a REPL. Implied if no FILE is given
+    /// Expressions to evaluate after the files, printing their results
+    #[clap(short, long)]
+    eval: Vec<String>,
+
+    /// Look for the modules loaded with `(require 'name)` in DIR, after the directory of the file requiring them and
+    /// before the directories of `LWHLISP_PATH`
+    #[clap(long, value_name = "DIR")]
+    module_path: Vec<String>,
+
+    /// Evaluate a session saved with `:save-session` before the files
+    #[clap(long, value_name = "FILE")]
+    restore: Option<String>,
+
+    /// Start a REPL. Implied if no FILE or expression is given
     #[clap(long)]
     repl: bool,
 
@@ -38,74 +82,405 @@ struct Args {
     #[clap(long)]
     debug_library: bool,
 
+    /// Always parse library files, instead of using and updating the cached parse results
+    #[clap(long)]
+    no_library_cache: bool,
+
     /// Show debugging information in evaluated files
     #[clap(long)]
     debug: bool,
+
+    /// Print how much time was spent in each function after running the files
+    #[clap(long)]
+    profile: bool,
+
+    /// Print how long each top-level s-expression of the files, expressions and REPL took to evaluate
+    #[clap(long)]
+    time: bool,
+
+    /// Simplify code before evaluating it, by folding constant arithmetic ahead of time
+    #[clap(long)]
+    optimize: bool,
+
+    /// Only parse the files, reporting parse errors and warning about unbound symbols, instead of evaluating them
+    #[clap(long, conflicts_with_all = &["eval", "repl"])]
+    check: bool,
+
+    /// Only parse the files, printing each parsed s-expression as FORMAT instead of evaluating them
+    #[clap(long, value_enum, value_name = "FORMAT", conflicts_with_all = &["eval", "repl", "check"])]
+    dump_ast: Option<AstFormat>,
+
+    /// How to print the results and errors of the files and expressions. With `json`, each top-level s-expression
+    /// and parse error is printed as a JSON object on its own line of stdout, for editors and other tools
+    #[clap(long, value_enum, value_name = "FORMAT", default_value = "text")]
+    output: OutputFormat,
+
+    /// Leave out the builtins which use files, the network or other processes, like `slurp`, `delete-file` and
+    /// `shell`, for running untrusted code. Printing and reading stdin stay available
+    #[clap(long)]
+    sandbox: bool,
+
+    /// Stop at the first error in a file or expression, instead of printing it and going on with the next
+    /// s-expression
+    #[clap(long)]
+    strict: bool,
+
+    // clap turns `{n}` into a line break in the help, so it is spelled out
+    /// Prompt of the REPL, where n between braces is replaced by the number of the input. Defaults to the prompt of
+    /// the configuration files, or else the string bound to `*prompt*`, or else `user> `
+    #[clap(long)]
+    prompt: Option<String>,
+
+    /// Prompt of the REPL while an s-expression is unfinished, like `--prompt`. Defaults to the continuation prompt
+    /// of the configuration files, or else the string bound to `*continuation-prompt*`, or else `....> `
+    #[clap(long)]
+    continuation_prompt: Option<String>,
+
+    /// Do not use colors in the REPL and in error reports. Colors are also disabled if `NO_COLOR` is set, and in the
+    /// REPL by the configuration files
+    #[clap(long)]
+    no_color: bool,
+
+    /// Only log errors
+    #[clap(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log more than warnings and errors: `-v` for informational messages, `-vv` for debugging messages, and `-vvv`
+    /// for everything. Without `-q` or `-v`, `RUST_LOG` is used if it is set
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Arguments given to the evaluated files, bound to `*args*` as a list of strings
+    #[clap(last = true)]
+    script_arguments: Vec<String>,
+}
+
+/// Formats in which `--dump-ast` prints parsed s-expressions.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum AstFormat {
+    /// One s-expression per line, with shorthands like `'a` and reader macros expanded
+    Sexp,
+    /// One JSON object per line, giving the type and span of each atom
+    Json,
+}
+
+/// Formats in which `--output` prints results and errors.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// Results on stdout, as they are printed in the REPL, and errors on stderr
+    Text,
+    /// One JSON object per line on stdout, with the `source` and `span` of an s-expression, and either its `result`
+    /// or an `error` with a `message` and a `span`
+    Json,
 }
 
 fn main() -> Result<()> {
-    color_eyre::install()?;
+    let result = run();
+    // `exit` stops evaluation with an error, which only ends the process here
+    if let Some(status) = result.as_ref().err().and_then(exit_status) {
+        std::process::exit(status);
+    }
+    result
+}
+
+/// Do what the command line arguments ask for.
+fn run() -> Result<()> {
+    let mut args = Args::parse();
+    let hook = if use_color(&args) {
+        color_eyre::config::HookBuilder::default()
+    } else {
+        color_eyre::config::HookBuilder::default().theme(color_eyre::config::Theme::new())
+    };
+    // the location is where the interpreter turned the error into a report, which says nothing about the lisp code
+    hook.display_location_section(false).install()?;
     let subscriber = tracing_subscriber::fmt()
         .pretty()
+        .with_env_filter(log_filter(&args))
         .with_writer(std::io::stderr)
         .with_file(true)
         .with_line_number(true)
         .finish();
     tracing::subscriber::set_global_default(subscriber)?;
-    let mut args = Args::parse();
+    // this comes after installing the error report hook, which is used by the errors of the configuration
+    let config = Config::load()?;
+    apply_config(&mut args, &config);
 
-    if args.files.is_empty() {
-        info!("No files to execute, scheduling REPL start");
+    let only_parse = args.check || args.dump_ast.is_some();
+    if args.files.is_empty() && args.eval.is_empty() && !only_parse {
+        info!("No files or expressions to execute, scheduling REPL start");
         args.repl = true;
     }
 
-    let mut env = Env::default();
+    let mut env = if args.sandbox {
+        Env::with_capabilities(&Capability::SANDBOX)
+    } else {
+        Env::default()
+    };
+    module::set_search_path(&env, args.module_path.iter().map(PathBuf::from).collect());
 
-    if args.library.is_empty() {
-        let default_library_path = String::from("lib/lib.lisp");
-        info!("No library files given, adding default library {default_library_path}");
-        args.library.push(default_library_path);
-    }
+    let mut errors = load_library(&args, &config, &mut env)?;
+
+    if let Some(format) = args.dump_ast {
+        errors += dump_files(&args, &env, format)?;
+    } else if args.check {
+        errors += check_files(&args, &env)?;
+    } else {
+        set_script_arguments(&args, &mut env);
+        // the bindings which are saved with `:save-session` are those which change from here
+        let baseline = env.clone();
+
+        if args.profile {
+            profile::start();
+        }
 
-    load_library(&args, &mut env)?;
+        if let Some(session) = &args.restore {
+            errors += run_file(session, &mut env, &args)?;
+        }
+        errors += run_files(&args, &mut env)?;
+        errors += run_expressions(&args, &mut env)?;
 
-    run_files(&args, &mut env)?;
+        if args.profile {
+            profile::stop();
+            eprint!("{}", profile::report());
+        }
 
-    if args.repl {
-        run_repl(env)?;
+        if args.repl {
+            run_repl(env, &baseline, &args)?;
+        }
     }
 
+    if errors > 0 {
+        info!("Exiting with an error status, as there were {errors} errors");
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-fn run_files(args: &Args, env: &mut Env) -> Result<(), color_eyre::Report> {
+/// The status to exit the process with, if `error` comes from calling `exit`.
+fn exit_status(error: &Report) -> Option<i32> {
+    error
+        .downcast_ref::<error::LispError>()
+        .and_then(error::LispError::exit_status)
+}
+
+/// Select what is logged, from `-q` and `-v`, or else from `RUST_LOG`. By default, only warnings and errors are.
+fn log_filter(args: &Args) -> EnvFilter {
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => {
+            if let Ok(filter) = EnvFilter::try_from_default_env() {
+                return filter;
+            }
+            LevelFilter::WARN
+        }
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    EnvFilter::new(level.to_string())
+}
+
+/// Use the REPL settings of the configuration files which are not given on the command line.
+fn apply_config(args: &mut Args, config: &Config) {
+    args.prompt = args.prompt.take().or_else(|| config.prompt.clone());
+    args.continuation_prompt = args
+        .continuation_prompt
+        .take()
+        .or_else(|| config.continuation_prompt.clone());
+    if config.color == Some(false) {
+        args.no_color = true;
+    }
+}
+
+/// Whether to use colors, unless `--no-color` is given or `NO_COLOR` is set to anything but an empty string.
+fn use_color(args: &Args) -> bool {
+    !args.no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Bind the arguments given after `--` to `*args*`, as a list of strings.
+fn set_script_arguments(args: &Args, env: &mut Env) {
+    let script_arguments = args
+        .script_arguments
+        .iter()
+        .map(|arg| Rc::new(Atom::String(arg.as_str().into())))
+        .collect();
+    env.set(
+        String::from("*args*"),
+        Atom::list_from_vec(script_arguments),
+    );
+}
+
+/// Evaluate the files, returning the number of errors which were printed.
+fn run_files(args: &Args, env: &mut Env) -> Result<usize, color_eyre::Report> {
+    let mut errors = 0;
     for file in &args.files {
-        run_file(file, env, args)?;
+        errors += module::in_file(Path::new(file), || run_file(file, env, args))?;
     }
-    Ok(())
+    Ok(errors)
 }
 
+/// Parse the files without evaluating them, warning about unbound symbols.
+///
+/// Returns the number of parse errors which were printed.
+fn check_files(args: &Args, env: &Env) -> Result<usize> {
+    let mut errors = 0;
+    for file in &args.files {
+        let src = read_file_to_string(file)?;
+        let src = src.trim_end();
+        let (atoms, errs) =
+            parser_with_reader_macros(reader_macros(env)).parse_recovery_verbose(src);
+        errors += errs.len();
+        print_parse_errs(errs, src);
+
+        let mut source_map = SourceMap::default();
+        let atoms = atoms
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(atom, spans)| {
+                let atom = Rc::new(atom);
+                source_map.insert(&atom, &spans);
+                atom
+            })
+            .collect::<Vec<_>>();
+        let file = if file == "-" { "<stdin>" } else { file };
+        for symbol in lint::unbound_symbols(&atoms, env) {
+            let (line, column) = source_map
+                .get(&symbol)
+                .map_or((0, 0), |span| line_column(src, span.start));
+            eprintln!("{file}:{line}:{column}: warning: {symbol} is not bound");
+        }
+    }
+    Ok(errors)
+}
+
+/// Parse the files without evaluating them, printing the parsed s-expressions.
+///
+/// Returns the number of parse errors which were printed.
+fn dump_files(args: &Args, env: &Env, format: AstFormat) -> Result<usize> {
+    let mut errors = 0;
+    for file in &args.files {
+        let src = read_file_to_string(file)?;
+        let src = src.trim_end();
+        let (atoms, errs) =
+            parser_with_reader_macros(reader_macros(env)).parse_recovery_verbose(src);
+        errors += errs.len();
+        print_parse_errs(errs, src);
+
+        for (atom, spans) in atoms.unwrap_or_default() {
+            match format {
+                AstFormat::Sexp => println!("{atom:?}"),
+                AstFormat::Json => println!("{}", json::syntax_tree(&atom, &spans)),
+            }
+        }
+    }
+    Ok(errors)
+}
+
+/// Evaluate the expressions given with `--eval`, printing their results.
+///
+/// Returns the number of errors which were printed.
+fn run_expressions(args: &Args, env: &mut Env) -> Result<usize> {
+    let mut errors = 0;
+    for src in &args.eval {
+        let src = src.trim();
+        let (atoms, errs) =
+            parser_with_reader_macros(reader_macros(env)).parse_recovery_verbose(src);
+        let parsed_cleanly = errs.is_empty();
+        errors += errs.len();
+        report_parse_errs(errs, src, None, args);
+        if args.strict && !parsed_cleanly {
+            return Err(eyre!("Could not parse the expression {src:?}"));
+        }
+        let text = SourceText::new(src);
+        for (atom, spans) in atoms.unwrap_or_default() {
+            let atom = prepare(&Rc::new(atom), env, args);
+            let start = Instant::now();
+            let (result, printed) = eval_top_level(&atom, env, args);
+            if args.time {
+                print_time(&atom.to_string(), start);
+            }
+            if let Some(printed) = printed {
+                print_json_result(None, &text, &spans.span, &printed, &result);
+            }
+            match result.map_err(Report::from) {
+                Ok(result) => {
+                    if args.output == OutputFormat::Text {
+                        println!("{result}");
+                    }
+                }
+                Err(e) if exit_status(&e).is_some() => return Err(e),
+                Err(e) if args.strict => return Err(e.wrap_err(format!("While evaluating {atom}"))),
+                Err(e) => {
+                    errors += 1;
+                    if args.output == OutputFormat::Text {
+                        eprintln!("{atom}\n!! {e:?}");
+                    }
+                }
+            }
+        }
+    }
+    Ok(errors)
+}
+
+/// Evaluate a file, returning the number of errors which were printed.
 #[instrument(skip(args, env))]
-fn run_file(file: &String, env: &mut Env, args: &Args) -> Result<(), color_eyre::Report> {
+fn run_file(file: &String, env: &mut Env, args: &Args) -> Result<usize, color_eyre::Report> {
     info!("Running file '{file}'...");
     let src = read_file_to_string(file)?;
 
-    let (atoms, errs) = parser().parse_recovery_verbose(src.trim());
-    print_parse_errs(errs, src.trim());
+    // only the end is trimmed, so that spans match the lines and columns of the file
+    let src = src.trim_end();
+    let (atoms, errs) = parser_with_reader_macros(reader_macros(env)).parse_recovery_verbose(src);
+    let parsed_cleanly = errs.is_empty();
+    let mut errors = errs.len();
+    let file = if file == "-" { "<stdin>" } else { file };
+    report_parse_errs(errs, src, Some(file), args);
+    if args.strict && !parsed_cleanly {
+        return Err(eyre!("Could not parse {file}"));
+    }
 
     if let Some(atoms) = atoms {
-        for atom in atoms {
-            let atom = Rc::new(atom);
-            let result = Atom::eval(atom.clone(), env);
-            match result {
+        let mut source_map = SourceMap::default();
+        let text = SourceText::new(src);
+        for (atom, spans) in atoms {
+            let parsed = Rc::new(atom);
+            source_map.insert(&parsed, &spans);
+            let atom = prepare(&parsed, env, args);
+            let location = || {
+                source_map.get(&parsed).map(|span| {
+                    let (line, column) = line_column(src, span.start);
+                    format!("{file}:{line}:{column}")
+                })
+            };
+            let start = Instant::now();
+            let (result, printed) = eval_top_level(&atom, env, args);
+            if args.time {
+                print_time(&location().unwrap_or_else(|| file.to_owned()), start);
+            }
+            if let Some(printed) = printed {
+                print_json_result(Some(file), &text, &spans.span, &printed, &result);
+            }
+            match result.map_err(Report::from) {
                 Ok(result) => {
-                    if args.debug {
-                        println!("{}", atom);
-                        println!("=> {}", result);
+                    if args.debug && args.output == OutputFormat::Text {
+                        println!("{atom}");
+                        println!("=> {result}");
                     }
                 }
+                Err(e) if exit_status(&e).is_some() => return Err(e),
                 Err(e) => {
-                    eprintln!("{}\n!! {:?}", atom, e);
+                    let location = location();
+                    if args.strict {
+                        let location = location.as_deref().unwrap_or(file);
+                        return Err(e.wrap_err(format!("While evaluating {atom} at {location}")));
+                    }
+                    errors += 1;
+                    if args.output == OutputFormat::Text {
+                        if let Some(location) = location {
+                            eprintln!("{location}");
+                        }
+                        eprintln!("{atom}\n!! {e:?}");
+                    }
                 }
             }
         }
@@ -113,14 +488,112 @@ fn run_file(file: &String, env: &mut Env, args: &Args) -> Result<(), color_eyre:
 
     info!("Done running file '{file}'!");
 
-    Ok(())
+    Ok(errors)
+}
+
+/// Evaluate a top-level s-expression, giving what it printed as well for `--output json`, so that it is not mixed
+/// with the JSON lines on stdout.
+fn eval_top_level(
+    atom: &Rc<Atom>,
+    env: &mut Env,
+    args: &Args,
+) -> (error::Result<Rc<Atom>>, Option<String>) {
+    if args.output == OutputFormat::Text {
+        return (Atom::eval(atom.clone(), env), None);
+    }
+    let buffer = Buffer::default();
+    let result = output::with(Some(Output::new(buffer.clone())), || {
+        Atom::eval(atom.clone(), env)
+    });
+    (result, Some(buffer.take()))
+}
+
+/// Print parse errors, as JSON lines for `--output json`, or else with ariadne.
+fn report_parse_errs(errs: Vec<Simple<char>>, src: &str, file: Option<&str>, args: &Args) {
+    if args.output == OutputFormat::Text {
+        print_parse_errs(errs, src);
+        return;
+    }
+    for e in errs {
+        let mut fields = file
+            .map(|file| ("file", json::string(file)))
+            .into_iter()
+            .collect::<Vec<_>>();
+        let error = [
+            ("message", json::string(&parse_error_message(&e))),
+            ("span", json::span(&e.span())),
+        ];
+        fields.push(("error", json::object(&error)));
+        println!("{}", json::object(&fields));
+    }
 }
 
-fn load_library(args: &Args, env: &mut Env) -> Result<()> {
+/// Print the outcome of evaluating the top-level s-expression of `text` at `span`, which printed `printed`, as a JSON
+/// line, for `--output json`.
+fn print_json_result(
+    file: Option<&str>,
+    text: &SourceText,
+    span: &Span,
+    printed: &str,
+    result: &error::Result<Rc<Atom>>,
+) {
+    let mut fields = file
+        .map(|file| ("file", json::string(file)))
+        .into_iter()
+        .collect::<Vec<_>>();
+    fields.push(("source", json::string(text.get(span))));
+    fields.push(("span", json::span(span)));
+    fields.push(("output", json::string(printed)));
+    match result {
+        Ok(result) => fields.push(("result", json::string(&result.to_string()))),
+        Err(e) => {
+            let error = [
+                ("message", json::string(&format!("{e:#}"))),
+                // errors do not know where in the s-expression they happened
+                ("form_span", json::span(span)),
+            ];
+            fields.push(("error", json::object(&error)));
+        }
+    }
+    println!("{}", json::object(&fields));
+}
+
+/// Print how long an evaluation which started at `start` took, for `--time`.
+fn print_time(label: &str, start: Instant) {
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    eprintln!("{label}: {elapsed:.3} ms");
+}
+
+/// Prepare a parsed atom for evaluation, optimizing it if requested.
+fn prepare(atom: &Rc<Atom>, env: &Env, args: &Args) -> Rc<Atom> {
+    if args.optimize {
+        optimize(atom, env)
+    } else {
+        atom.clone()
+    }
+}
+
+/// Evaluate the library files, returning the number of errors which were printed.
+fn load_library(args: &Args, config: &Config, env: &mut Env) -> Result<usize> {
+    if args.no_library {
+        info!("Not loading any library");
+        return Ok(0);
+    }
+    let mut errors = 0;
+    if args.library.is_empty() {
+        if let Some(path) = config::default_library_path(config) {
+            info!("No library files given, loading the default library from {path:?}");
+            errors += load_library_file(&path.display().to_string(), env, args)?;
+        } else {
+            info!("No library files given, loading the embedded default library");
+            // the embedded library is part of the binary, so caching it would save nothing
+            errors += load_library_source("default library", DEFAULT_LIBRARY, None, env, args);
+        }
+    }
     for library_path in &args.library {
-        load_library_file(library_path, env, args)?;
+        errors += load_library_file(library_path, env, args)?;
     }
-    Ok(())
+    Ok(errors)
 }
 
 #[instrument(skip(args, env))]
@@ -128,74 +601,660 @@ fn load_library_file(
     library_path: &String,
     env: &mut Env,
     args: &Args,
-) -> Result<(), color_eyre::Report> {
+) -> Result<usize, color_eyre::Report> {
     info!("Loading library file '{library_path}'...");
     let src = read_file_to_string(library_path).context("While opening library file")?;
+    let cache_path = cache::cache_path(library_path);
+    let errors = load_library_source(library_path, &src, Some(&cache_path), env, args);
+    info!("Done loading library file '{library_path}'!");
+
+    Ok(errors)
+}
 
-    let (atoms, errs) = parser().parse_recovery_verbose(src.trim());
-    print_parse_errs(errs, src.trim());
+/// Evaluate the source of a library, using the cached parse result at `cache_path`, if there is one, if it is up to
+/// date.
+///
+/// Returns the number of errors which were printed.
+fn load_library_source(
+    name: &str,
+    src: &str,
+    cache_path: Option<&Path>,
+    env: &mut Env,
+    args: &Args,
+) -> usize {
+    let mut errors = 0;
+    let reader_macros = reader_macros(env);
+    // the cached parse results do not depend on reader macros
+    let cache_path = cache_path.filter(|_| !args.no_library_cache && reader_macros.is_empty());
+    let cached = cache_path.and_then(|cache_path| cache::read(cache_path, src));
+    let atoms = cached.or_else(|| {
+        let (atoms, errs) = parser_with_reader_macros(reader_macros)
+            .map(|atoms| atoms.into_iter().map(|(atom, _)| atom).collect::<Vec<_>>())
+            .parse_recovery_verbose(src.trim());
+        let parsed_cleanly = errs.is_empty();
+        errors += errs.len();
+        print_parse_errs(errs, src.trim());
+        if let (true, Some(cache_path), Some(atoms)) = (parsed_cleanly, cache_path, &atoms) {
+            if let Err(e) = cache::write(cache_path, src, atoms) {
+                info!("Could not cache library '{name}': {e}");
+            }
+        }
+        atoms
+    });
 
     if let Some(atoms) = atoms {
         for atom in atoms {
-            let atom = Rc::new(atom);
-            let result = Atom::eval(atom.clone(), env);
+            let atom = prepare(&Rc::new(atom), env, args);
+            let result = Atom::eval(atom.clone(), env).map_err(Report::from);
             match result {
                 Ok(result) => {
                     if args.debug_library {
-                        println!("{}", atom);
-                        println!("=> {}", result);
+                        println!("{atom}");
+                        println!("=> {result}");
                     }
                 }
                 Err(e) => {
-                    eprintln!("{}\n!! {:?}", atom, e);
+                    errors += 1;
+                    eprintln!("{atom}\n!! {e:?}");
                 }
             }
         }
     }
-
-    info!("Done loading library file '{library_path}'!");
-
-    Ok(())
+    errors
 }
 
 /// Run a read-eval-print loop.
-fn run_repl(mut env: Env) -> Result<()> {
-    let mut rl = rustyline::Editor::<()>::new();
+///
+/// `baseline` is the environment before any file was evaluated, to find the bindings to save with `:save-session`.
+fn run_repl(mut env: Env, baseline: &Env, args: &Args) -> Result<()> {
+    let mut rl = rustyline::Editor::<ReplHelper>::new();
+    let colored = use_color(args);
+    rl.set_helper(Some(ReplHelper {
+        colored,
+        names: env.symbol_names(),
+        filenames: FilenameCompleter::new(),
+    }));
+    for name in RESULT_HISTORY {
+        env.set(name, Atom::nil());
+    }
+    let interrupt = interrupt::Interrupt::new();
+    interrupt::interrupt_on_ctrl_c(&interrupt);
     let histfile = &".lisphistory.txt";
     drop(rl.load_history(histfile));
+    // lines of an s-expression which is not finished yet
+    let mut src = String::new();
+    // number of the next input, for the prompt
+    let mut input = 1;
+    // the error of calling `exit`, which ends the REPL
+    let mut exit = None;
     loop {
-        let readline = rl.readline("user> ");
+        let prompt = if src.is_empty() {
+            prompt(args.prompt.as_deref(), "*prompt*", "user> ", &env, input)
+        } else {
+            let setting = args.continuation_prompt.as_deref();
+            prompt(setting, "*continuation-prompt*", "....> ", &env, input)
+        };
+        let readline = rl.readline(&prompt);
         match readline {
+            // ctrl-c drops an unfinished s-expression, instead of exiting
+            Err(rustyline::error::ReadlineError::Interrupted) if !src.is_empty() => src.clear(),
             Err(_) => break,
-            Ok(src) => {
-                rl.add_history_entry(&src);
+            Ok(line) => {
+                // ctrl-c pressed while no evaluation was running should not interrupt the next one
+                interrupt.clear();
+                if src.is_empty() {
+                    if let Some(flow) = run_repl_command(line.trim(), &mut env, baseline, args) {
+                        rl.add_history_entry(line.trim_end());
+                        input += 1;
+                        if let ControlFlow::Break(error) = flow {
+                            exit = error;
+                            break;
+                        }
+                        if let Some(helper) = rl.helper_mut() {
+                            helper.names = env.symbol_names();
+                        }
+                        continue;
+                    }
+                }
 
-                let (atoms, errs) = parser().parse_recovery_verbose(src.trim());
-                print_parse_errs(errs, src.trim());
-                if let Some(atoms) = atoms {
-                    eval_and_print_result(atoms, &mut env);
+                src.push_str(&line);
+                src.push('\n');
+
+                let parsed =
+                    Parsed::from(parser_with_reader_macros(reader_macros(&env)).parse(src.trim()));
+                match parsed {
+                    Parsed::Incomplete => continue,
+                    Parsed::Complete(atoms) => {
+                        let atoms = atoms.into_iter().map(|(atom, _)| atom).collect();
+                        exit = interrupt::with(&interrupt, || {
+                            eval_and_print_result(atoms, &mut env, colored, args)
+                        })
+                        .err()
+                        .map(Report::from);
+                    }
+                    Parsed::Error(errs) => print_parse_errs(errs, src.trim()),
+                }
+                rl.add_history_entry(src.trim_end());
+                if exit.is_some() {
+                    break;
+                }
+                src.clear();
+                input += 1;
+                gc::collect_if_due(&env);
+                if let Some(helper) = rl.helper_mut() {
+                    helper.names = env.symbol_names();
                 }
             }
         }
     }
     rl.save_history(histfile)?;
-    Ok(())
+    exit.map_or(Ok(()), Err)
+}
+
+/// Get a prompt of the REPL: the one given on the command line, or else the string bound to `name`, or else
+/// `default`. `{n}` is replaced by the number of the input.
+fn prompt(setting: Option<&str>, name: &str, default: &str, env: &Env, input: usize) -> String {
+    let bound = env.lookup(name);
+    let prompt = match (setting, bound.as_deref()) {
+        (Some(prompt), _) => prompt,
+        (None, Some(Atom::String(prompt))) => prompt,
+        (None, _) => default,
+    };
+    prompt.replace("{n}", &input.to_string())
 }
 
+/// Commands of the REPL, along with what they do.
+const REPL_COMMANDS: &[(&str, &str)] = &[
+    (":help", "Show this help"),
+    (
+        ":env [prefix]",
+        "List the bound names, or only those starting with prefix",
+    ),
+    (":apropos text", "List the bound names which contain text"),
+    (
+        ":doc name",
+        "Show the documentation of what is bound to name",
+    ),
+    (":load file", "Evaluate a file"),
+    (":reload", "Evaluate the library files again"),
+    (
+        ":save-session file",
+        "Save the definitions made since the start, to restore them with --restore",
+    ),
+    (
+        ":type expr",
+        "Evaluate an expression, and show the type of its value",
+    ),
+    (":quit", "Exit the REPL"),
+];
+
+/// Run a REPL command, which is a line starting with one of [`REPL_COMMANDS`].
+///
+/// Returns `None` if the line is not a command, so that it is evaluated instead, or a break, with the error of
+/// calling `exit` if that is why, if the REPL should end.
+fn run_repl_command(
+    line: &str,
+    env: &mut Env,
+    baseline: &Env,
+    args: &Args,
+) -> Option<ControlFlow<Option<Report>>> {
+    let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let argument = argument.trim();
+    match command {
+        ":help" => {
+            for (usage, description) in REPL_COMMANDS {
+                println!("{usage:<16}{description}");
+            }
+        }
+        ":env" => {
+            for name in env.symbol_names() {
+                if name.starts_with(argument) {
+                    println!("{name}");
+                }
+            }
+        }
+        ":apropos" => {
+            for name in env.apropos(argument) {
+                println!("{name}");
+            }
+        }
+        ":doc" | ":load" | ":save-session" | ":type" if argument.is_empty() => {
+            eprintln!("{command} expects an argument, see :help");
+        }
+        ":doc" => match env.documentation(argument) {
+            Ok(doc) => println!("{doc}"),
+            Err(e) => eprintln!("!! {e}"),
+        },
+        ":load" => match run_file(&argument.to_owned(), env, args) {
+            Err(e) if exit_status(&e).is_some() => return Some(ControlFlow::Break(Some(e))),
+            Err(e) => eprintln!("!! {e:?}"),
+            Ok(_) => {}
+        },
+        ":save-session" => {
+            // the results of the last inputs are not part of the session, so they are left out by binding them to
+            // the same value in both
+            let (mut saved, mut baseline) = (env.clone(), baseline.clone());
+            let nil = Atom::nil();
+            for name in RESULT_HISTORY {
+                saved.set(name, nil.clone());
+                baseline.set(name, nil.clone());
+            }
+            if let Err(e) = std::fs::write(argument, session::save(&saved, &baseline)) {
+                eprintln!("!! Could not save the session to {argument}: {e}");
+            }
+        }
+        ":reload" => {
+            // the library paths of the configuration files may have changed too
+            if let Err(e) = Config::load().and_then(|config| load_library(args, &config, env)) {
+                eprintln!("!! {e:?}");
+            }
+        }
+        ":type" => match parser_with_reader_macros(reader_macros(env)).parse(argument) {
+            Ok(atoms) => {
+                for (atom, _) in atoms {
+                    let atom = prepare(&Rc::new(atom), env, args);
+                    match Atom::eval(atom, env).map_err(Report::from) {
+                        Ok(result) => println!("{}", result.type_name()),
+                        Err(e) if exit_status(&e).is_some() => {
+                            return Some(ControlFlow::Break(Some(e)))
+                        }
+                        Err(e) => eprintln!("!! {e:?}"),
+                    }
+                }
+            }
+            Err(errs) => print_parse_errs(errs, argument),
+        },
+        ":quit" => return Some(ControlFlow::Break(None)),
+        _ => return None,
+    }
+    Some(ControlFlow::Continue(()))
+}
+
+/// Completion of names bound in the REPL, and of file paths inside of strings.
+struct ReplHelper {
+    /// Whether the line is highlighted.
+    colored: bool,
+    /// Names bound in the environment, sorted.
+    names: Vec<Rc<str>>,
+    filenames: FilenameCompleter,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+
+        // find whether the cursor is in a string, and where it starts
+        let mut string_start = None;
+        let mut escaped = false;
+        for (i, c) in before.char_indices() {
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            match c {
+                '\\' if string_start.is_some() => escaped = true,
+                '"' => {
+                    string_start = if string_start.is_some() {
+                        None
+                    } else {
+                        Some(i)
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = string_start {
+            let (path_start, paths) = self.filenames.complete_path(&line[start..], pos - start)?;
+            return Ok((start + path_start, paths));
+        }
+
+        let start = before
+            .rfind(|c: char| c.is_whitespace() || "()'`,\"".contains(c))
+            .map_or(0, |i| i + 1);
+        let prefix = &before[start..];
+        let names = self
+            .names
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| Pair {
+                display: name.to_string(),
+                replacement: name.to_string(),
+            })
+            .collect();
+        Ok((start, names))
+    }
+}
+
+impl rustyline::hint::Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl rustyline::highlight::Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if !self.colored {
+            return Cow::Borrowed(line);
+        }
+        let tokens = tokens(line);
+        let matching = matching_paren(&tokens, line, pos);
+        let mut highlighted = String::with_capacity(line.len());
+        for (range, token) in tokens {
+            let text = &line[range.clone()];
+            let color = match token {
+                Token::Paren if Some(range.start) == matching => Some(Color::Blue),
+                Token::String => Some(colors::STRING),
+                Token::Comment => Some(Color::Fixed(8)),
+                Token::Symbol if text.starts_with(':') => Some(colors::KEYWORD),
+                Token::Symbol if is_number(text) => Some(colors::NUMBER),
+                Token::Symbol
+                    if self
+                        .names
+                        .binary_search_by(|name| (**name).cmp(text))
+                        .is_ok() =>
+                {
+                    Some(colors::SYMBOL)
+                }
+                Token::Paren | Token::Symbol | Token::Other => None,
+            };
+            match color {
+                Some(color) => highlighted.push_str(&text.fg(color).to_string()),
+                None => highlighted.push_str(text),
+            }
+        }
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        // the matching parenthesis changes as the cursor moves
+        self.colored
+    }
+}
+
+/// Kinds of text in a line of the REPL, for highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Paren,
+    String,
+    Comment,
+    Symbol,
+    /// Whitespace, and the characters of shorthands like `'a`.
+    Other,
+}
+
+/// Split a line into tokens, including unfinished strings and comments at its end.
+fn tokens(line: &str) -> Vec<(Range<usize>, Token)> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            '(' | ')' => Token::Paren,
+            '"' => {
+                let mut escaped = false;
+                for (_, c) in chars.by_ref() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                Token::String
+            }
+            '#' if chars.peek().map(|(_, c)| *c) == Some('|') => {
+                chars.next();
+                let mut depth = 1;
+                let mut previous = ' ';
+                for (_, c) in chars.by_ref() {
+                    match (previous, c) {
+                        ('#', '|') => depth += 1,
+                        ('|', '#') => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    // `#|#` does not both open and close a comment
+                    previous = if matches!((previous, c), ('#', '|') | ('|', '#')) {
+                        ' '
+                    } else {
+                        c
+                    };
+                }
+                Token::Comment
+            }
+            c if c.is_whitespace() || "'`,@".contains(c) => Token::Other,
+            _ => {
+                while chars
+                    .peek()
+                    .is_some_and(|(_, c)| !c.is_whitespace() && !"()\"'`,".contains(*c))
+                {
+                    chars.next();
+                }
+                Token::Symbol
+            }
+        };
+        let end = chars.peek().map_or(line.len(), |(end, _)| *end);
+        tokens.push((start..end, token));
+    }
+    tokens
+}
+
+/// Whether a symbol token is read as a number.
+fn is_number(token: &str) -> bool {
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    digits.starts_with(|c: char| c.is_ascii_digit())
+        && token.replace('_', "").parse::<f64>().is_ok()
+}
+
+/// Find the start of the parenthesis matching the one right before the cursor, or else the one under it.
+fn matching_paren(tokens: &[(Range<usize>, Token)], line: &str, pos: usize) -> Option<usize> {
+    let parens = tokens
+        .iter()
+        .filter(|(_, token)| *token == Token::Paren)
+        .map(|(range, _)| range.start)
+        .collect::<Vec<_>>();
+    let at_cursor = parens
+        .iter()
+        .position(|&i| i + 1 == pos)
+        .or_else(|| parens.iter().position(|&i| i == pos))?;
+
+    // the depth is counted from the parenthesis at the cursor, towards the one matching it
+    let opening = |start: &usize| line.as_bytes()[*start] == b'(';
+    let mut depth = 0;
+    if opening(&parens[at_cursor]) {
+        parens[at_cursor..]
+            .iter()
+            .find(|start| {
+                depth += if opening(start) { 1 } else { -1 };
+                depth == 0
+            })
+            .copied()
+    } else {
+        parens[..=at_cursor]
+            .iter()
+            .rev()
+            .find(|start| {
+                depth += if opening(start) { -1 } else { 1 };
+                depth == 0
+            })
+            .copied()
+    }
+}
+
+impl rustyline::validate::Validator for ReplHelper {}
+
+impl rustyline::Helper for ReplHelper {}
+
+/// Names bound to the results of the last evaluations in the REPL, starting with the most recent one.
+const RESULT_HISTORY: [&str; 3] = ["*1", "*2", "*3"];
+
 /// Eval atoms and print the result.
 ///
 /// Will evaluate the given atoms in order, and print stack traces on error.
-fn eval_and_print_result(atoms: Vec<Atom>, env: &mut Env) {
+/// Results are bound to the names in [`RESULT_HISTORY`], and printed with colors if `colored` is true.
+/// The atoms are optimized first with `--optimize`, and how long each evaluation took is printed with `--time`.
+///
+/// Returns the error of calling `exit`, if one of the atoms did, without evaluating the others.
+fn eval_and_print_result(
+    atoms: Vec<Atom>,
+    env: &mut Env,
+    colored: bool,
+    args: &Args,
+) -> error::Result<()> {
     for atom in atoms {
         let atom = Rc::new(atom);
-        let result = Atom::eval(atom.clone(), env);
+        let start = Instant::now();
+        let result = Atom::eval(prepare(&atom, env, args), env);
+        if args.time {
+            print_time("time", start);
+        }
         match result {
             Ok(result) => {
-                println!("=> {}", result);
+                if colored {
+                    println!("=> {}", result.colored());
+                } else {
+                    println!("=> {result}");
+                }
+                for names in RESULT_HISTORY.windows(2).rev() {
+                    let previous = env.lookup(names[0]).unwrap_or_else(Atom::nil);
+                    env.set(names[1], previous);
+                }
+                env.set(RESULT_HISTORY[0], result);
             }
             Err(e) => {
-                eprintln!("{}\n!! {:?}", atom, e);
+                let marker = if colored {
+                    "!!".fg(Color::Red).to_string()
+                } else {
+                    "!!".to_owned()
+                };
+                if interrupt::is_interrupted(&e) {
+                    eprintln!("{marker} Interrupted");
+                    break;
+                }
+                if e.exit_status().is_some() {
+                    return Err(e);
+                }
+                eprintln!("{atom}\n{marker} {:?}", Report::from(e));
             }
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(arguments: &[&str]) -> Args {
+        Args::parse_from(std::iter::once("lwhlisp").chain(arguments.iter().copied()))
+    }
+
+    fn temp_file(name: &str, src: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("lwhlisp-main-test-{}-{name}", std::process::id()));
+        std::fs::write(&path, src).unwrap();
+        path.display().to_string()
+    }
+
+    fn eval(src: &str, env: &mut Env) -> Rc<Atom> {
+        let atoms = parser_with_reader_macros(reader_macros(env))
+            .parse(src)
+            .unwrap();
+        let mut result = Atom::nil();
+        for (atom, _) in atoms {
+            result = Atom::eval(Rc::new(atom), env).unwrap();
+        }
+        result
+    }
+
+    #[test]
+    fn script_arguments() {
+        let mut env = Env::default();
+        set_script_arguments(&args(&["-f", "script.lisp", "--", "a", "b c"]), &mut env);
+        assert_eq!(eval("*args*", &mut env).to_string(), r#"("a" "b c")"#);
+        set_script_arguments(&args(&[]), &mut env);
+        assert!(eval("*args*", &mut env).is_nil());
+    }
+
+    #[test]
+    fn prompts() {
+        let mut env = Env::default();
+        let continuation =
+            |env: &Env, setting| prompt(setting, "*continuation-prompt*", "....> ", env, 3);
+        assert_eq!(continuation(&env, None), "....> ");
+        eval(r#"(define *continuation-prompt* "{n}.. ")"#, &mut env);
+        assert_eq!(continuation(&env, None), "3.. ");
+        // the command line comes first
+        assert_eq!(continuation(&env, Some("> ")), "> ");
+    }
+
+    #[test]
+    fn expressions() {
+        let mut env = Env::default();
+        let errors = run_expressions(
+            &args(&["-e", "(define x 2) (car)", "-e", "(+ x 1"]),
+            &mut env,
+        );
+        // an evaluation error and a parse error
+        assert_eq!(errors.unwrap(), 2);
+        assert_eq!(eval("x", &mut env), Atom::integer(2));
+        assert!(
+            run_expressions(&args(&["--strict", "-e", "(car) (define y 1)"]), &mut env).is_err()
+        );
+        assert!(env.lookup("y").is_none());
+    }
+
+    #[test]
+    fn result_history() {
+        let mut env = Env::default();
+        let atoms = (1..=4).map(|n| Atom::Number(n.into())).collect();
+        eval_and_print_result(atoms, &mut env, false, &args(&["--time"])).unwrap();
+        for (name, expected) in RESULT_HISTORY.into_iter().zip([4, 3, 2]) {
+            assert_eq!(env.lookup(name).unwrap(), Atom::integer(expected), "{name}");
+        }
+    }
+
+    #[test]
+    fn no_library() {
+        let mut env = Env::default();
+        let errors = load_library(&args(&["--no-library"]), &Config::default(), &mut env);
+        assert_eq!(errors.unwrap(), 0);
+        assert!(env.lookup("reverse").is_none());
+        assert!(
+            Args::try_parse_from(["lwhlisp", "--no-library", "--library", "lib.lisp"]).is_err()
+        );
+    }
+
+    #[test]
+    fn errors_in_files() {
+        let mut env = Env::default();
+        let file = temp_file("errors.lisp", "(car)\n(define y 1)\n(cdr)\n");
+        assert_eq!(run_file(&file, &mut env, &args(&["--time"])).unwrap(), 2);
+        assert!(env.lookup("y").is_some());
+        // --strict stops at the first error
+        let mut env = Env::default();
+        let error = run_file(&file, &mut env, &args(&["--strict"])).unwrap_err();
+        assert!(format!("{error:?}").contains(":1:1"), "{error:?}");
+        assert!(env.lookup("y").is_none());
+        std::fs::write(&file, "(define z 1))").unwrap();
+        assert_eq!(run_file(&file, &mut env, &args(&[])).unwrap(), 1);
+        assert!(run_file(&file, &mut env, &args(&["--strict"])).is_err());
+        std::fs::remove_file(&file).unwrap();
+        assert!(run_file(&file, &mut env, &args(&[])).is_err());
+    }
+
+    #[test]
+    fn log_levels() {
+        let level = |arguments: &[&str]| log_filter(&args(arguments)).to_string();
+        assert_eq!(level(&["-q"]), "error");
+        assert_eq!(level(&["-v"]), "info");
+        assert_eq!(level(&["-vv"]), "debug");
+        assert_eq!(level(&["-vvvv"]), "trace");
+    }
 }
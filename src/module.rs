@@ -0,0 +1,248 @@
+//! `(require 'name)` evaluates the file `name.lisp` in an environment of its own, which only has the builtins, and
+//! binds the names it exports in the environment it was required from. A module is only evaluated the first time it
+//! is required by an interpreter, later requires binding the same values again.
+//!
+//! A module exports the names given to `(provide 'name ...)`, or else every name it defines. It is looked for next
+//! to the file being evaluated, or in the current directory outside of files, then in the directories of the search
+//! path, like those given with `--module-path`, then in those of the `LWHLISP_PATH` environment variable.
+//!
+//! A module is evaluated outside of any namespace. If it enters one with `(in-namespace name)`, the names it exports
+//! are qualified, like `name:reverse`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chumsky::Parser as _;
+use tracing::info;
+
+use crate::atom::{Atom, Rc};
+use crate::env::Env;
+use crate::error::{Context, Result};
+use crate::lisp_error;
+
+/// The environment variable listing directories modules are looked for in, separated like `PATH`.
+pub const PATH_VARIABLE: &str = "LWHLISP_PATH";
+
+/// The name a module binds to the list of the names given to `provide`.
+const PROVIDED: &str = "*provided*";
+
+/// The bindings a module exports.
+type Exports = Vec<(Rc<str>, Rc<Atom>)>;
+
+/// Interior mutability for the modules of an environment, a [`std::sync::Mutex`] with the `arc` feature.
+#[cfg(not(feature = "arc"))]
+type Mutable<T> = std::cell::RefCell<T>;
+/// Interior mutability for the modules of an environment, a [`std::sync::Mutex`] with the `arc` feature.
+#[cfg(feature = "arc")]
+type Mutable<T> = std::sync::Mutex<T>;
+
+thread_local! {
+    static FILES: RefCell<Vec<PathBuf>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Where modules are looked for and the modules which were evaluated, which are shared by an environment and the
+/// environments made in it, so that each interpreter has its own.
+#[derive(Clone, Default)]
+pub struct Modules(Rc<Mutable<Registry>>);
+
+#[derive(Default)]
+struct Registry {
+    search_path: Vec<PathBuf>,
+    /// The exports of the modules which were evaluated, by path.
+    exports: HashMap<PathBuf, Exports>,
+    /// The modules being evaluated, to tell when a module requires itself.
+    loading: Vec<PathBuf>,
+}
+
+impl Modules {
+    fn lock(&self) -> impl std::ops::DerefMut<Target = Registry> + '_ {
+        #[cfg(not(feature = "arc"))]
+        let registry = self.0.borrow_mut();
+        // a panic while holding the lock at worst leaves a module marked as being evaluated
+        #[cfg(feature = "arc")]
+        let registry = self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        registry
+    }
+}
+
+impl fmt::Debug for Modules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Modules")
+    }
+}
+
+/// The modules are bookkeeping, not part of what an environment binds, so they never make environments unequal.
+impl PartialEq for Modules {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Look for the modules required in `env` in `directories`, after the directory of the file being evaluated, and
+/// before those of [`PATH_VARIABLE`].
+pub fn set_search_path(env: &Env, directories: Vec<PathBuf>) {
+    env.modules().lock().search_path = directories;
+}
+
+/// Run `f`, which evaluates the file at `path`, so that the modules it requires are looked for next to it.
+pub fn in_file<T>(path: &Path, f: impl FnOnce() -> T) -> T {
+    FILES.with(|cell| cell.borrow_mut().push(path.to_path_buf()));
+    let result = f();
+    FILES.with(|cell| cell.borrow_mut().pop());
+    result
+}
+
+/// The directories the modules required in `env` are looked for in, in order.
+fn directories(env: &Env) -> Vec<PathBuf> {
+    let current = FILES
+        .with(|cell| {
+            cell.borrow()
+                .last()
+                .and_then(|file| file.parent())
+                .map(Path::to_path_buf)
+        })
+        .unwrap_or_default();
+    let mut directories = vec![current];
+    directories.extend(env.modules().lock().search_path.iter().cloned());
+    if let Some(paths) = std::env::var_os(PATH_VARIABLE) {
+        directories.extend(std::env::split_paths(&paths));
+    }
+    directories
+}
+
+/// Find the file of the module `name`, required in `env`.
+///
+/// # Errors
+/// If there is no `name.lisp` in any of the directories modules are looked for in, return an error.
+pub fn find(name: &str, env: &Env) -> Result<PathBuf> {
+    let file = format!("{name}.lisp");
+    let directories = directories(env);
+    directories
+        .iter()
+        .map(|directory| directory.join(&file))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            let directories = directories
+                .iter()
+                .map(|directory| match directory.to_str() {
+                    Some("") => ".".to_string(),
+                    _ => directory.display().to_string(),
+                })
+                .collect::<Vec<_>>();
+            lisp_error!(
+                Io,
+                "Could not find module {name}, looked for {file} in {}",
+                directories.join(", ")
+            )
+        })
+}
+
+/// Evaluate `(require 'name)` in `env`, binding the names the module exports, which are returned.
+///
+/// # Errors
+/// If the module can not be found, read or parsed, if one of its s-expressions can not be evaluated, if it requires
+/// itself, or if it provides a name it does not define, return an error.
+pub fn require(name: &str, env: &mut Env) -> Result<Rc<Atom>> {
+    let path = find(name, env)?;
+    let path = path.canonicalize().unwrap_or(path);
+    let modules = env.modules().clone();
+    let cached = modules.lock().exports.get(&path).cloned();
+    let exports = if let Some(exports) = cached {
+        exports
+    } else {
+        if modules.lock().loading.contains(&path) {
+            return Err(lisp_error!(
+                Value,
+                "Module {name} requires itself, through {}",
+                path.display()
+            ));
+        }
+        modules.lock().loading.push(path.clone());
+        let exports = load(&path, env);
+        modules.lock().loading.pop();
+        let exports = exports.with_context(|| format!("While requiring module {name}"))?;
+        modules.lock().exports.insert(path, exports.clone());
+        exports
+    };
+    let mut names = Vec::with_capacity(exports.len());
+    for (name, value) in exports {
+        names.push(Rc::new(Atom::Symbol(name.clone())));
+        env.set(name, value);
+    }
+    Ok(Atom::list_from_vec(names))
+}
+
+/// Evaluate the module at `path`, in an environment of its own with the builtins of `env`, returning the bindings it
+/// exports.
+///
+/// The module does not see the other bindings of `env`, so that it does the same whichever environment requires it
+/// first.
+fn load(path: &Path, env: &Env) -> Result<Exports> {
+    info!("Loading module {}", path.display());
+    let src = std::fs::read_to_string(path)
+        .with_context(|| format!("While reading {}", path.display()))?;
+    let src = src.trim_end();
+    let mut module_env = Env::new(Some(env.root()));
+    module_env.set_namespace(None);
+    let atoms = crate::parsing::parser_with_reader_macros(crate::reader_macros(&module_env))
+        .parse(src)
+        .map_err(|errs| crate::parse_error(src, &errs))?;
+    in_file(path, || {
+        atoms
+            .into_iter()
+            .try_for_each(|(atom, _)| Atom::eval(Rc::new(atom), &mut module_env).map(drop))
+    })?;
+
+    let provided = module_env
+        .iter()
+        .find(|(name, _)| &***name == PROVIDED)
+        .map(|(_, names)| names.clone());
+    let Some(provided) = provided else {
+        return Ok(module_env
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect());
+    };
+    Atom::list_to_vec(&provided)?
+        .into_iter()
+        .map(|name| {
+            // in a namespace, the name is defined qualified
+            let name = module_env.qualify(&name.get_symbol_name()?);
+            let value = module_env
+                .iter()
+                .find(|(bound, _)| **bound == name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| {
+                    lisp_error!(
+                        UnboundSymbol,
+                        "Module {} provides {name}, which it does not define",
+                        path.display()
+                    )
+                    .with_atom(&Atom::Symbol(name.clone()))
+                })?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Evaluate `(provide 'name ...)` in `env`, adding the names, which are returned, to those the module exports.
+///
+/// # Errors
+/// If one of the names is not a symbol, return an error.
+pub fn provide(names: &Rc<Atom>, env: &mut Env) -> Result<Rc<Atom>> {
+    let mut provided = env
+        .iter()
+        .find(|(name, _)| &***name == PROVIDED)
+        .map_or_else(|| Ok(Vec::new()), |(_, names)| Atom::list_to_vec(names))?;
+    for name in Atom::list_to_vec(names)? {
+        name.get_symbol_name()?;
+        provided.push(name);
+    }
+    env.set(PROVIDED, Atom::list_from_vec(provided));
+    Ok(names.clone())
+}
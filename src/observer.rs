@@ -0,0 +1,63 @@
+//! An [`EvalObserver`] is told about each application the evaluator makes, the call of a function, builtin, macro
+//! or special form, so that an application embedding lwhlisp can trace, measure or debug evaluation.
+//!
+//! The observer is kept per thread, like the fuel. Evaluation the observer does itself is not observed.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::atom::Atom;
+use crate::error::Result;
+
+thread_local! {
+    static OBSERVER: RefCell<Option<Rc<RefCell<dyn EvalObserver>>>> = const { RefCell::new(None) };
+}
+
+/// Something told about each application the evaluator makes.
+pub trait EvalObserver {
+    /// Called before the application `expr` is evaluated, like `(f 1 2)`.
+    fn on_enter(&mut self, expr: &Atom) {
+        let _ = expr;
+    }
+
+    /// Called after the application `expr` was evaluated, with its result.
+    fn on_exit(&mut self, expr: &Atom, result: &Result<crate::atom::Rc<Atom>>) {
+        let _ = (expr, result);
+    }
+}
+
+/// Run `f` with `observer` told about the applications it evaluates on the current thread, returning what `f`
+/// returned and the observer.
+///
+/// The observer of the current thread is restored afterwards, so that this can be nested.
+pub fn observe<O: EvalObserver + 'static, T>(observer: O, f: impl FnOnce() -> T) -> (T, O) {
+    let observer = Rc::new(RefCell::new(observer));
+    let previous = OBSERVER.with(|cell| cell.replace(Some(observer.clone())));
+    let result = f();
+    OBSERVER.with(|cell| cell.replace(previous));
+    let observer = Rc::try_unwrap(observer)
+        .unwrap_or_else(|_| unreachable!("the observer is only referred to while f runs"));
+    (result, observer.into_inner())
+}
+
+/// Tell the observer of the current thread, if it is not already busy, about an application.
+fn notify(tell: impl FnOnce(&mut dyn EvalObserver)) {
+    let Some(observer) = OBSERVER.with(|cell| cell.borrow().clone()) else {
+        return;
+    };
+    // the observer evaluating code itself would borrow it again
+    let Ok(mut observer) = observer.try_borrow_mut() else {
+        return;
+    };
+    tell(&mut *observer);
+}
+
+/// Tell the observer of the current thread that the application `expr` is about to be evaluated.
+pub fn enter(expr: &Atom) {
+    notify(|observer| observer.on_enter(expr));
+}
+
+/// Tell the observer of the current thread that the application `expr` was evaluated.
+pub fn exit(expr: &Atom, result: &Result<crate::atom::Rc<Atom>>) {
+    notify(|observer| observer.on_exit(expr, result));
+}
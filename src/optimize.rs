@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+
+use crate::atom::{Atom, Rc};
+use crate::env::Env;
+
+/// Builtins without side effects, which can be called ahead of time when all their arguments are constants.
+const PURE_BUILTINS: &[&str] = &[
+    "+",
+    "-",
+    "*",
+    "/",
+    "sqrt",
+    "expt",
+    "abs",
+    "floor",
+    "ceiling",
+    "round",
+    "truncate",
+    "min",
+    "max",
+    "sin",
+    "cos",
+    "tan",
+    "atan2",
+    "log",
+    "exp",
+    "bit-and",
+    "bit-or",
+    "bit-xor",
+    "bit-not",
+    "shift-left",
+    "shift-right",
+    "gcd",
+    "lcm",
+];
+
+/// Simplify an atom before it is evaluated in the given environment.
+///
+/// This folds calls to arithmetic builtins whose arguments are all numbers, and replaces `(quote x)` by `x` when `x`
+/// evaluates to itself.
+///
+/// Names are resolved in the environment at the time of the call, so this assumes builtins that are folded are not
+/// redefined afterwards. The arguments of macros, the bodies of macro definitions, and forms whose head is not bound
+/// yet, which could be a macro defined later, are left alone.
+#[must_use]
+pub fn optimize(atom: &Rc<Atom>, env: &Env) -> Rc<Atom> {
+    Optimizer {
+        env,
+        shadowed: HashSet::new(),
+    }
+    .optimize(atom)
+}
+
+struct Optimizer<'a> {
+    env: &'a Env,
+    /// Names bound by enclosing lambdas and local definitions, which may not refer to the builtins in `env`.
+    shadowed: HashSet<Rc<str>>,
+}
+
+impl Optimizer<'_> {
+    fn optimize(&mut self, atom: &Rc<Atom>) -> Rc<Atom> {
+        let Atom::Pair(op, args) = atom.as_ref() else {
+            return atom.clone();
+        };
+        let Ok(items) = Atom::list_to_vec(args) else {
+            return atom.clone();
+        };
+        let resolved = match op.as_ref() {
+            Atom::Symbol(name) if !self.shadowed.contains(name) => self.env.lookup(name),
+            _ => None,
+        };
+        match resolved.as_deref() {
+            Some(Atom::Symbol(special_form)) => {
+                self.optimize_special_form(special_form, atom, &items)
+            }
+            Some(Atom::Macro(_, _, _)) => atom.clone(),
+            Some(Atom::NativeFunc(f)) => {
+                let args = self.optimize_all(&items);
+                let name = op.get_symbol_name().unwrap_or_default();
+                let constant_args = args
+                    .iter()
+                    .all(|arg| matches!(arg.as_ref(), Atom::Number(_)));
+                if PURE_BUILTINS.contains(&&*name) && constant_args {
+                    if let Ok(result) = f(Atom::list_from_vec(args.clone())) {
+                        if matches!(result.as_ref(), Atom::Number(_)) {
+                            return result;
+                        }
+                    }
+                }
+                // the builtin itself can not be printed or saved, so the call keeps its name
+                Rc::new(Atom::Pair(op.clone(), Atom::list_from_vec(args)))
+            }
+            None if matches!(op.as_ref(), Atom::Symbol(name) if !self.shadowed.contains(name)) => {
+                atom.clone()
+            }
+            _ => Rc::new(Atom::Pair(
+                self.optimize(op),
+                Atom::list_from_vec(self.optimize_all(&items)),
+            )),
+        }
+    }
+
+    fn optimize_all(&mut self, atoms: &[Rc<Atom>]) -> Vec<Rc<Atom>> {
+        atoms.iter().map(|atom| self.optimize(atom)).collect()
+    }
+
+    fn optimize_special_form(
+        &mut self,
+        special_form: &str,
+        atom: &Rc<Atom>,
+        args: &[Rc<Atom>],
+    ) -> Rc<Atom> {
+        let op = atom.car();
+        match (special_form, args) {
+            ("quote", [quoted]) if matches!(quoted.as_ref(), Atom::Number(_) | Atom::String(_)) => {
+                quoted.clone()
+            }
+            ("if" | "apply", _) => {
+                Rc::new(Atom::Pair(op, Atom::list_from_vec(self.optimize_all(args))))
+            }
+            ("define", [name, value]) if matches!(name.as_ref(), Atom::Symbol(_)) => {
+                Atom::list_from_vec(vec![op, name.clone(), self.optimize(value)])
+            }
+            ("define", [signature, body @ ..])
+                if matches!(signature.as_ref(), Atom::Pair(_, _)) =>
+            {
+                let mut items = vec![op, signature.clone()];
+                items.extend(self.optimize_body(signature, body));
+                Atom::list_from_vec(items)
+            }
+            ("lambda", [params, body @ ..]) => {
+                let mut items = vec![op, params.clone()];
+                items.extend(self.optimize_body(params, body));
+                Atom::list_from_vec(items)
+            }
+            _ => atom.clone(),
+        }
+    }
+
+    /// Optimize the body of a function, where the names in `params` and those defined in the body are local.
+    fn optimize_body(&mut self, params: &Rc<Atom>, body: &[Rc<Atom>]) -> Vec<Rc<Atom>> {
+        let outer = self.shadowed.clone();
+        let mut params = params.clone();
+        while let Atom::Pair(param, rest) = params.as_ref() {
+            if let Atom::Symbol(name) = param.as_ref() {
+                self.shadowed.insert(name.clone());
+            }
+            let rest = rest.clone();
+            params = rest;
+        }
+        if let Atom::Symbol(name) = params.as_ref() {
+            if !params.is_nil() {
+                self.shadowed.insert(name.clone());
+            }
+        }
+        for form in body {
+            if let Atom::Pair(op, rest) = form.as_ref() {
+                if op.get_symbol_name().is_ok_and(|op| &*op == "define") {
+                    let name = rest.car();
+                    let name = if Atom::is_list(&name) {
+                        name.car()
+                    } else {
+                        name
+                    };
+                    if let Atom::Symbol(name) = name.as_ref() {
+                        self.shadowed.insert(name.clone());
+                    }
+                }
+            }
+        }
+        let body = self.optimize_all(body);
+        self.shadowed = outer;
+        body
+    }
+}
@@ -0,0 +1,116 @@
+//! The print builtins write to the output of the current thread, which is stdout unless an application embedding
+//! lwhlisp sets another one, like a [`Buffer`] capturing what a script prints.
+
+use std::cell::RefCell;
+use std::io::Write;
+
+use crate::atom::Rc;
+use crate::error::{Context, Result};
+
+/// A writer which can be shared. With the `arc` feature, it must also be [`Send`] and [`Sync`].
+#[cfg(not(feature = "arc"))]
+type Writer = dyn Write;
+/// A writer which can be shared. With the `arc` feature, it must also be [`Send`] and [`Sync`].
+#[cfg(feature = "arc")]
+type Writer = dyn Write + Send + Sync;
+
+/// Interior mutability for a shared writer, a [`std::sync::Mutex`] with the `arc` feature.
+#[cfg(not(feature = "arc"))]
+type Mutable<T> = std::cell::RefCell<T>;
+/// Interior mutability for a shared writer, a [`std::sync::Mutex`] with the `arc` feature.
+#[cfg(feature = "arc")]
+type Mutable<T> = std::sync::Mutex<T>;
+
+thread_local! {
+    static OUTPUT: RefCell<Option<Output>> = const { RefCell::new(None) };
+}
+
+/// Get exclusive access to a shared value.
+fn lock<T: ?Sized>(mutable: &Mutable<T>) -> impl std::ops::DerefMut<Target = T> + '_ {
+    #[cfg(not(feature = "arc"))]
+    let value = mutable.borrow_mut();
+    // a panic while holding the lock at worst loses some of the output
+    #[cfg(feature = "arc")]
+    let value = mutable
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    value
+}
+
+/// Where the print builtins write, which can be cloned to keep a handle on it.
+#[derive(Clone)]
+pub struct Output(Rc<Mutable<Writer>>);
+
+impl Output {
+    /// Make the print builtins write to `writer`.
+    #[cfg(not(feature = "arc"))]
+    pub fn new(writer: impl Write + 'static) -> Self {
+        Self(Rc::new(RefCell::new(writer)))
+    }
+
+    /// Make the print builtins write to `writer`.
+    #[cfg(feature = "arc")]
+    pub fn new(writer: impl Write + Send + Sync + 'static) -> Self {
+        Self(Rc::new(std::sync::Mutex::new(writer)))
+    }
+}
+
+impl std::fmt::Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Output")
+    }
+}
+
+/// Text written to memory, which can be cloned to read what was written through another clone.
+#[derive(Clone, Debug, Default)]
+pub struct Buffer(Rc<Mutable<Vec<u8>>>);
+
+impl Buffer {
+    /// What was written so far.
+    #[must_use]
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&lock(&self.0)).into_owned()
+    }
+
+    /// Forget what was written so far, returning it.
+    #[must_use]
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *lock(&self.0));
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        lock(&self.0).write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run `f` with the print builtins writing to `output`, or to stdout if it is `None`.
+///
+/// The output of the current thread is restored afterwards, so that this can be nested.
+pub fn with<T>(output: Option<Output>, f: impl FnOnce() -> T) -> T {
+    let previous = OUTPUT.with(|cell| cell.replace(output));
+    let result = f();
+    OUTPUT.with(|cell| cell.replace(previous));
+    result
+}
+
+/// Write `s` to the output of the current thread.
+///
+/// # Errors
+/// If it can not be written.
+pub fn print(s: &str) -> Result<()> {
+    if let Some(output) = OUTPUT.with(|cell| cell.borrow().clone()) {
+        lock(&output.0)
+            .write_all(s.as_bytes())
+            .context("While writing to the output")
+    } else {
+        print!("{s}");
+        Ok(())
+    }
+}
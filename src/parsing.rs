@@ -1,16 +1,26 @@
+use std::collections::HashMap;
+
 use chumsky::prelude::*;
 
-use crate::atom::Atom;
+use crate::atom::{Atom, Map, Rc};
 
+/// A symbol, or the characters of a number.
 fn symbol() -> impl Parser<char, String, Error = Simple<char>> {
-    let id_start_char = one_of("abcdefghijklmnopqrstuvwxyz")
-        .or(one_of("ABCDEFGHIJKLMNOPQRSTUVWXYZ"))
-        .or(one_of("+-*/%_=<>?"))
+    let id_start_char = filter(|c: &char| c.is_alphabetic())
+        .or(one_of("0123456789"))
+        .or(one_of("+-*/%_=<>?:!&"))
         .labelled("symbol start character");
+    // `.` and `#` can not start a symbol, so that they can start dotted pairs and comments,
+    // and `#` can not start a comment inside of a symbol either
     let id_char = id_start_char
         .clone()
-        .or(one_of("0123456789"))
-        .or(one_of(":"))
+        .or(just('.'))
+        .or(just('#').then_ignore(
+            filter(|c| *c != '|' && *c != ';')
+                .ignored()
+                .or(end())
+                .rewind(),
+        ))
         .labelled("symbol character");
 
     id_start_char
@@ -19,41 +29,250 @@ fn symbol() -> impl Parser<char, String, Error = Simple<char>> {
         .labelled("symbol")
 }
 
-/// Parse a series of s-expressions.
+/// A symbol, a keyword if it starts with `:`, or a number if all of the characters of the symbol are part of it,
+/// so that `1+` is a symbol.
+fn symbol_or_number() -> impl Parser<char, Atom, Error = Simple<char>> {
+    let number = number().then_ignore(end());
+    symbol().validate(move |s, span, emit| {
+        if let Some(name) = s.strip_prefix(':') {
+            if name.is_empty() {
+                emit(Simple::custom(
+                    span,
+                    "Expected the name of a keyword after :",
+                ));
+            }
+            return Atom::keyword(name);
+        }
+        number.parse(s.as_str()).map_or_else(
+            |_| Atom::Symbol(s.into()),
+            |x| Atom::Number(x.parse().unwrap()),
+        )
+    })
+}
+
+/// A range of character indices in parsed source code.
+pub type Span = std::ops::Range<usize>;
+
+/// Where a parsed atom is in the source, along with where its elements are if it is a list.
 ///
-/// # Panics
-/// If the parser is incorrect about how to parse numbers, this may panic.
-pub fn parser() -> impl Parser<char, Vec<Atom>, Error = Simple<char>> {
-    let open_paren = just('(').labelled("opening parenthesis").padded();
-    let close_paren = just(')').labelled("closing parenthesis").padded();
-    let pair_separator = just('.').labelled("pair separator").padded();
-    let quote = just('\'').labelled("quote").padded();
-    let quasiquote = just('`').labelled("quasiquote").padded();
-    let unquote = just(',').labelled("unquote").padded();
-    let unquote_splicing = just(",@").labelled("unquote-splicing").padded();
+/// The atom after the `.` of an improper list is its last element, and the list read from a shorthand like `'a` has
+/// the span of the `'` as its first element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanTree {
+    /// Span of the whole atom.
+    pub span: Span,
+    /// Spans of the elements of the atom, if it is a list.
+    pub elements: Vec<SpanTree>,
+    /// Spans of the comments inside of the atom which are not inside of one of its elements, in order.
+    pub comments: Vec<Span>,
+}
+
+impl SpanTree {
+    const fn leaf(span: Span) -> Self {
+        Self {
+            span,
+            elements: Vec::new(),
+            comments: Vec::new(),
+        }
+    }
+
+    /// Whether there are comments anywhere inside of the atom.
+    #[must_use]
+    pub fn has_comments(&self) -> bool {
+        !self.comments.is_empty() || self.elements.iter().any(SpanTree::has_comments)
+    }
+}
+
+/// Parsed source which keeps its comments, for tools like the formatter which need to reproduce them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commented {
+    /// Top-level atoms, each with the spans of the comments right before it.
+    pub atoms: Vec<(Vec<Span>, Atom, SpanTree)>,
+    /// Spans of the comments after the last atom.
+    pub trailing_comments: Vec<Span>,
+}
+
+/// Source which can be sliced by the spans of the parser, which count characters rather than bytes.
+#[derive(Debug, Clone)]
+pub struct SourceText<'a> {
+    src: &'a str,
+    /// Byte offset of each character, and of the end of the source.
+    offsets: Vec<usize>,
+}
+
+impl<'a> SourceText<'a> {
+    /// Index the characters of `src`, once for all the spans which are looked up.
+    #[must_use]
+    pub fn new(src: &'a str) -> Self {
+        // `chain` would be the one of chumsky's `Parser`
+        let offsets =
+            Iterator::chain(src.char_indices().map(|(offset, _)| offset), [src.len()]).collect();
+        Self { src, offsets }
+    }
+
+    /// Get the text in `span`, or the part of it which is in the source.
+    #[must_use]
+    pub fn get(&self, span: &Span) -> &'a str {
+        let offset = |index: usize| self.offsets[index.min(self.offsets.len() - 1)];
+        &self.src[offset(span.start)..offset(span.end.max(span.start))]
+    }
+}
+
+/// Spans of parsed atoms, which can be looked up once the atoms are in an [`Rc`].
+///
+/// Atoms are looked up by address, so the map keeps them alive to make sure their address is not reused.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    spans: HashMap<*const Atom, Span>,
+    atoms: Vec<Rc<Atom>>,
+}
+
+impl SourceMap {
+    /// Record the spans of `atom` and of its elements.
+    pub fn insert(&mut self, atom: &Rc<Atom>, spans: &SpanTree) {
+        let mut stack = vec![(atom.clone(), spans)];
+        while let Some((atom, spans)) = stack.pop() {
+            self.spans.insert(Rc::as_ptr(&atom), spans.span.clone());
+            let mut rest = atom;
+            for element in &spans.elements {
+                if let Atom::Pair(car, cdr) = rest.as_ref() {
+                    stack.push((car.clone(), element));
+                    rest = cdr.clone();
+                } else {
+                    // the atom after the `.` of an improper list
+                    stack.push((rest, element));
+                    break;
+                }
+            }
+        }
+        self.atoms.push(atom.clone());
+    }
+
+    /// Get the span of an atom, if it was parsed.
+    #[must_use]
+    pub fn get(&self, atom: &Rc<Atom>) -> Option<Span> {
+        self.spans.get(&Rc::as_ptr(atom)).cloned()
+    }
+}
+
+/// Get the line and column of the character at `index` in `src`, both starting at 1.
+#[must_use]
+pub fn line_column(src: &str, index: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut column = 1;
+    for c in src.chars().take(index) {
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    (line, column)
+}
+
+/// Function expanding a reader macro: it gets the string or list right after the name of the macro, or nil if there
+/// is none, and returns the atom to read instead, or an error message.
+pub type ReaderMacro = std::rc::Rc<dyn Fn(Atom) -> Result<Atom, String>>;
+
+/// Reader macros, which are expanded while parsing.
+///
+/// A reader macro is written as `#` followed by its name, and optionally directly followed by a string or a list,
+/// like `#t` or `#rx"a+"`.
+#[derive(Clone, Default)]
+pub struct ReaderMacros(HashMap<String, ReaderMacro>);
+
+impl ReaderMacros {
+    /// Add a reader macro, replacing any other with the same name.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        expand: impl Fn(Atom) -> Result<Atom, String> + 'static,
+    ) {
+        self.0.insert(name.into(), std::rc::Rc::new(expand));
+    }
+
+    /// Whether there are no reader macros.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A comment delimited by `#|` and `|#`, which can be nested.
+fn block_comment() -> impl Parser<char, (), Error = Simple<char>> + Clone {
+    recursive(|block_comment| {
+        // any character, as long as it does not start `#|` or `|#`
+        let content = filter(|c| *c != '#' && *c != '|')
+            .or(just('#').then_ignore(filter(|c| *c != '|').rewind()))
+            .or(just('|').then_ignore(filter(|c| *c != '#').rewind()))
+            .ignored();
+
+        just("#|")
+            .ignore_then(block_comment.or(content).repeated())
+            .then_ignore(just("|#"))
+            .ignored()
+    })
+    .labelled("block comment")
+}
+
+/// A number literal.
+fn number() -> impl Parser<char, String, Error = Simple<char>> {
+    // digits can be grouped with underscores, like `1_000_000`, which are left out of the result
+    let digits = filter(char::is_ascii_digit)
+        .repeated()
+        .at_least(1)
+        .separated_by(just('_'))
+        .at_least(1)
+        .flatten();
 
-    let frac = just('.').chain(text::digits(10));
+    let frac = just('.').chain(digits);
 
     let exp = just('e')
         .or(just('E'))
         .chain(just('+').or(just('-')).or_not())
-        .chain(text::digits(10));
+        .chain(digits);
 
-    let number = just('-')
+    just('-')
         .or_not()
-        .chain(text::int(10))
+        .chain(digits)
         .chain(frac.or_not().flatten())
         .chain::<char, _, _>(exp.or_not().flatten())
         .collect::<String>()
         .labelled("number")
-        .padded();
+}
+
+/// A first line like `#!/usr/bin/env lwhlisp`, so that scripts can be executable.
+fn shebang() -> impl Parser<char, (), Error = Simple<char>> {
+    just("#!")
+        .then(filter(|c| *c != '\n').repeated())
+        .ignored()
+        .labelled("shebang")
+}
 
-    let symbol = symbol().padded();
+/// The character of a shorthand like `'a`, giving the name of the symbol it stands for.
+fn shorthand() -> impl Parser<char, &'static str, Error = Simple<char>> {
+    let quote = just('\'').to("quote").labelled("quote");
+    let quasiquote = just('`').to("quasiquote").labelled("quasiquote");
+    let unquote = just(',').to("unquote").labelled("unquote");
+    let unquote_splicing = just(",@")
+        .to("unquote-splicing")
+        .labelled("unquote-splicing");
+    quote.or(quasiquote).or(unquote_splicing).or(unquote)
+}
 
-    let number = number.map(|x| Atom::Number(x.parse().unwrap()));
-    let symbol = symbol.map(Atom::Symbol);
+/// A string literal.
+fn string() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+    just('"')
+        .ignore_then(filter(|c| *c != '\\' && *c != '"').or(escape()).repeated())
+        .then_ignore(just('"'))
+        .collect::<String>()
+        .labelled("string")
+}
 
-    let escape = just('\\').ignore_then(
+/// An escape sequence in a string, like `\n`.
+fn escape() -> impl Parser<char, char, Error = Simple<char>> + Clone {
+    just('\\').ignore_then(
         just('\\')
             .or(just('/'))
             .or(just('"'))
@@ -62,10 +281,21 @@ pub fn parser() -> impl Parser<char, Vec<Atom>, Error = Simple<char>> {
             .or(just('n').to('\n'))
             .or(just('r').to('\r'))
             .or(just('t').to('\t'))
+            .or(just('0').to('\0'))
+            .or(just('x').ignore_then(
+                filter(char::is_ascii_hexdigit)
+                    .repeated()
+                    .exactly(2)
+                    .collect::<String>()
+                    .map(|digits| char::from(u8::from_str_radix(&digits, 16).unwrap())),
+            ))
             .or(just('u').ignore_then(
                 filter(char::is_ascii_hexdigit)
                     .repeated()
-                    .exactly(4)
+                    .at_least(1)
+                    .at_most(6)
+                    .delimited_by(just('{'), just('}'))
+                    .or(filter(char::is_ascii_hexdigit).repeated().exactly(4))
                     .collect::<String>()
                     .validate(|digits, span: _, emit| {
                         char::from_u32(u32::from_str_radix(&digits, 16).unwrap()).unwrap_or_else(
@@ -76,72 +306,333 @@ pub fn parser() -> impl Parser<char, Vec<Atom>, Error = Simple<char>> {
                         )
                     }),
             )),
+    )
+}
+
+/// Outcome of parsing source code which may not be finished yet, like a line typed in the REPL.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Parsed<T> {
+    /// The source was parsed without errors.
+    Complete(T),
+    /// The source ends before an s-expression does, but could become valid if more is added to it.
+    Incomplete,
+    /// The source has errors which adding more to it can not fix.
+    Error(Vec<Simple<char>>),
+}
+
+impl<T> From<Result<T, Vec<Simple<char>>>> for Parsed<T> {
+    fn from(result: Result<T, Vec<Simple<char>>>) -> Self {
+        match result {
+            Ok(parsed) => Self::Complete(parsed),
+            // only running out of input can be fixed by adding more of it
+            Err(errs)
+                if errs.iter().all(|e| {
+                    e.found().is_none()
+                        && !matches!(e.reason(), chumsky::error::SimpleReason::Custom(_))
+                }) =>
+            {
+                Self::Incomplete
+            }
+            Err(errs) => Self::Error(errs),
+        }
+    }
+}
+
+/// Parse a series of s-expressions, telling apart source with errors from source which is only unfinished, like
+/// an unclosed list or string.
+#[must_use]
+pub fn parse_or_incomplete(src: &str) -> Parsed<Vec<Atom>> {
+    parser().parse(src).into()
+}
+
+/// Parse a series of s-expressions.
+///
+/// # Panics
+/// If the parser is incorrect about how to parse numbers, this may panic.
+#[must_use]
+pub fn parser() -> impl Parser<char, Vec<Atom>, Error = Simple<char>> {
+    parser_with_spans().map(|atoms| atoms.into_iter().map(|(atom, _)| atom).collect())
+}
+
+/// Parse a series of s-expressions, along with where each of them and their elements are in the source.
+///
+/// # Panics
+/// If the parser is incorrect about how to parse numbers, this may panic.
+#[must_use]
+pub fn parser_with_spans() -> impl Parser<char, Vec<(Atom, SpanTree)>, Error = Simple<char>> {
+    parser_with_reader_macros(ReaderMacros::default())
+}
+
+/// Parse a series of s-expressions like [`parser_with_spans`], expanding the given reader macros.
+///
+/// # Panics
+/// If the parser is incorrect about how to parse numbers, this may panic.
+#[must_use]
+pub fn parser_with_reader_macros(
+    reader_macros: ReaderMacros,
+) -> impl Parser<char, Vec<(Atom, SpanTree)>, Error = Simple<char>> {
+    commented_parser(reader_macros).map(|commented| {
+        commented
+            .atoms
+            .into_iter()
+            .map(|(_, atom, spans)| (atom, spans))
+            .collect()
+    })
+}
+
+/// Parse a series of s-expressions like [`parser_with_spans`], keeping the comments.
+///
+/// # Panics
+/// If the parser is incorrect about how to parse numbers, this may panic.
+#[must_use]
+pub fn parser_with_comments() -> impl Parser<char, Commented, Error = Simple<char>> {
+    commented_parser(ReaderMacros::default())
+}
+
+fn commented_parser(
+    reader_macros: ReaderMacros,
+) -> impl Parser<char, Commented, Error = Simple<char>> {
+    // whitespace and comments around s-expressions, giving the spans of the comments.
+    // it is only defined after `atom`, as a `#;` comment contains an s-expression
+    let mut padding = Recursive::declare();
+    let close_paren = just(')').labelled("closing parenthesis");
+    let open_brace = just('{').labelled("opening brace");
+    let close_brace = just('}').labelled("closing brace");
+
+    let reader_macro_name = just('#').ignore_then(symbol());
+
+    let string = string().map(|s| Atom::String(s.into()));
+
+    let atom = recursive(|atom| {
+        // an element along with the comments around it
+        let element = padding
+            .clone()
+            .then(atom.clone())
+            .then(padding.clone())
+            .map(|((mut comments, atom), after): ((Vec<Span>, _), _)| {
+                comments.extend(after);
+                (atom, comments)
+            });
+
+        let list = list(element.clone(), padding.clone());
+
+        let map = open_brace
+            .ignore_then(padding.clone())
+            .then(element.clone().repeated())
+            .then_ignore(close_brace)
+            .validate(|(comments, elements), span, emit| {
+                if elements.len() % 2 != 0 {
+                    emit(Simple::custom(
+                        span,
+                        "Expected a value after the last key of the map",
+                    ));
+                }
+                map_from_elements(comments, elements)
+            })
+            .labelled("map");
+
+        let reader_macro = reader_macro_name
+            .then(string.clone().or(list.clone().map(|(a, _, _)| a)).or_not())
+            .validate(move |(name, argument), span, emit| {
+                let expanded = reader_macros.0.get(&name).map_or_else(
+                    || Err(format!("Unknown reader macro #{name}")),
+                    |expand| expand(argument.unwrap_or_else(|| Atom::symbol("nil"))),
+                );
+                let expanded = expanded.unwrap_or_else(|message| {
+                    emit(Simple::custom(span, message));
+                    Atom::symbol("nil")
+                });
+                (expanded, Vec::new(), Vec::new())
+            })
+            .labelled("reader macro");
+
+        // `'a` is read as `(quote a)`, and likewise for the other shorthands
+        let shorthand = shorthand()
+            .map_with_span(|name, span| (name, span))
+            .then(padding.clone().then(atom))
+            .map(|((name, name_span), (comments, (a, spans)))| {
+                (
+                    Atom::cons(Atom::symbol(name), Atom::cons(a, Atom::symbol("nil"))),
+                    vec![SpanTree::leaf(name_span), spans],
+                    comments,
+                )
+            });
+
+        symbol_or_number()
+            .or(string)
+            .map(|a| (a, Vec::new(), Vec::new()))
+            .or(list)
+            .or(map)
+            .or(shorthand)
+            .or(reader_macro)
+            .map_with_span(|(a, elements, comments), span| {
+                (
+                    a,
+                    SpanTree {
+                        span,
+                        elements,
+                        comments,
+                    },
+                )
+            })
+    });
+
+    let datum_comment = just("#;")
+        .ignore_then(padding.clone().then(atom.clone()))
+        .ignored()
+        .labelled("datum comment");
+    padding.define(
+        text::whitespace().ignore_then(
+            block_comment()
+                .or(datum_comment)
+                .map_with_span(|(), span| span)
+                .then_ignore(text::whitespace())
+                .repeated(),
+        ),
     );
 
-    let string = just('"')
-        .ignore_then(filter(|c| *c != '\\' && *c != '"').or(escape).repeated())
-        .then_ignore(just('"'))
-        .collect::<String>()
-        .map(Atom::String)
-        .labelled("string");
-
-    let atom =
-        recursive(|atom| {
-            let empty_list = open_paren.then(close_paren).ignored().to(Atom::nil());
-
-            let proper_list = open_paren
-                .ignore_then(atom.clone().padded().repeated().at_least(1))
-                .then_ignore(close_paren)
-                .map(|x| create_list(&x));
-
-            let improper_list = open_paren
-                .ignore_then(atom.clone().padded().repeated().at_least(1))
-                .then_ignore(pair_separator)
-                .then(atom.clone().padded())
-                .then_ignore(close_paren)
-                .map(|(atoms, last)| create_improper_list(&atoms, last));
-
-            let list = empty_list.or(proper_list).or(improper_list).padded();
-
-            number
-                .or(symbol)
-                .or(string)
-                .or(list)
-                .or(quote.ignore_then(
-                    atom.clone()
-                        .padded()
-                        .map(|a| Atom::cons(Atom::symbol("quote"), Atom::cons(a, Atom::nil()))),
-                ))
-                .or(quasiquote.ignore_then(
-                    atom.clone().padded().map(|a| {
-                        Atom::cons(Atom::symbol("quasiquote"), Atom::cons(a, Atom::nil()))
-                    }),
-                ))
-                .or(unquote.ignore_then(
-                    atom.clone()
-                        .padded()
-                        .map(|a| Atom::cons(Atom::symbol("unquote"), Atom::cons(a, Atom::nil()))),
-                ))
-                .or(unquote_splicing.ignore_then(atom.clone().padded().map(|a| {
-                    Atom::cons(Atom::symbol("unquote-splicing"), Atom::cons(a, Atom::nil()))
-                })))
-        });
+    // an s-expression with errors is left out, so that the others can still be used
+    let top_level_atom = atom
+        .map(Some)
+        .recover_with(nested_delimiters('(', ')', [('{', '}')], |_| None))
+        .or(close_paren.validate(|_, span, emit| {
+            emit(Simple::custom(span, "Unexpected closing parenthesis"));
+            None
+        }));
 
-    atom.padded().repeated().then_ignore(end())
+    shebang()
+        .or_not()
+        .ignore_then(padding.clone().then(top_level_atom).repeated())
+        .then(padding)
+        .then_ignore(end())
+        .map(|(atoms, trailing_comments)| Commented::new(atoms, trailing_comments))
 }
 
-// converts a Vec<Atom> into a corresponding lisp cons list
-fn create_list(x: &[Atom]) -> Atom {
-    match x.first().cloned() {
-        Some(first) => Atom::cons(first, create_list(&x[1..])),
-        None => Atom::nil(),
+impl Commented {
+    /// Collect the top-level atoms, along with the comments before them.
+    /// An atom is `None` if it was left out because of errors.
+    fn new(atoms: Vec<(Vec<Span>, Option<ParsedAtom>)>, trailing_comments: Vec<Span>) -> Self {
+        let mut commented = Self {
+            atoms: Vec::new(),
+            trailing_comments: Vec::new(),
+        };
+        // the comments before an s-expression which was left out go with the next one
+        let mut comments = Vec::new();
+        for (before, atom) in atoms {
+            comments.extend(before);
+            if let Some((atom, spans)) = atom {
+                commented
+                    .atoms
+                    .push((std::mem::take(&mut comments), atom, spans));
+            }
+        }
+        comments.extend(trailing_comments);
+        commented.trailing_comments = comments;
+        commented
     }
 }
 
-fn create_improper_list(atoms: &[Atom], last: Atom) -> Atom {
-    if let Some(first) = atoms.first().cloned() {
-        Atom::cons(first, create_improper_list(&atoms[1..], last))
+/// An atom, along with where it is in the source.
+type ParsedAtom = (Atom, SpanTree);
+
+/// A list element, along with the comments around it.
+type Element = (ParsedAtom, Vec<Span>);
+
+/// A list, empty, proper or improper, of `element`s, with `padding` between the parentheses of an empty one.
+fn list<E, P>(
+    element: E,
+    padding: P,
+) -> impl Parser<char, (Atom, Vec<SpanTree>, Vec<Span>), Error = Simple<char>> + Clone
+where
+    E: Parser<char, Element, Error = Simple<char>> + Clone,
+    P: Parser<char, Vec<Span>, Error = Simple<char>> + Clone,
+{
+    let open_paren = just('(').labelled("opening parenthesis");
+    let close_paren = just(')').labelled("closing parenthesis");
+    let pair_separator = just('.').labelled("pair separator");
+
+    let empty_list = open_paren
+        .ignore_then(padding)
+        .then_ignore(close_paren)
+        .map(|comments| (Atom::symbol("nil"), Vec::new(), comments));
+
+    let proper_list = open_paren
+        .ignore_then(element.clone().repeated().at_least(1))
+        .then_ignore(close_paren)
+        .map(|elements| list_from_elements(elements, None));
+
+    let improper_list = open_paren
+        .ignore_then(element.clone().repeated().at_least(1))
+        .then_ignore(pair_separator)
+        .then(element)
+        .then_ignore(close_paren)
+        .map(|(elements, last)| list_from_elements(elements, Some(last)));
+
+    empty_list.or(proper_list).or(improper_list)
+}
+
+/// Build a list from its elements and the atom after its `.`, if there is one.
+fn list_from_elements(
+    elements: Vec<Element>,
+    last: Option<Element>,
+) -> (Atom, Vec<SpanTree>, Vec<Span>) {
+    let (elements, comments): (Vec<_>, Vec<_>) = elements.into_iter().unzip();
+    let (atoms, mut spans): (Vec<_>, Vec<_>) = elements.into_iter().unzip();
+    let mut comments = comments.concat();
+    let Some(((last, last_spans), last_comments)) = last else {
+        return (create_list(&atoms), spans, comments);
+    };
+
+    comments.extend(last_comments);
+    // elements of a list after the `.` are elements of the whole list
+    if last.is_nil() || matches!(last, Atom::Pair(_, _)) {
+        spans.extend(last_spans.elements);
+        comments.extend(last_spans.comments);
     } else {
-        last
+        spans.push(last_spans);
+    }
+    (create_improper_list(&atoms, last), spans, comments)
+}
+
+/// Build a map from its keys and values, in turn, leaving out a key without a value.
+///
+/// The entries of a map are not in the order they were written in, so the map gets no spans for its elements, and
+/// all the comments inside of it, including those inside of its keys and values, are its own.
+fn map_from_elements(
+    mut comments: Vec<Span>,
+    elements: Vec<Element>,
+) -> (Atom, Vec<SpanTree>, Vec<Span>) {
+    fn collect_comments(spans: SpanTree, comments: &mut Vec<Span>) {
+        comments.extend(spans.comments);
+        for element in spans.elements {
+            collect_comments(element, comments);
+        }
+    }
+
+    let mut map = Map::new();
+    let mut elements = elements.into_iter();
+    while let Some(((key, key_spans), key_comments)) = elements.next() {
+        comments.extend(key_comments);
+        collect_comments(key_spans, &mut comments);
+        let Some(((value, value_spans), value_comments)) = elements.next() else {
+            break;
+        };
+        comments.extend(value_comments);
+        collect_comments(value_spans, &mut comments);
+        map.insert(Rc::new(key), Rc::new(value));
     }
+    comments.sort_by_key(|comment| comment.start);
+    (Atom::Map(map), Vec::new(), comments)
+}
+
+// converts a Vec<Atom> into a corresponding lisp cons list
+fn create_list(x: &[Atom]) -> Atom {
+    create_improper_list(x, Atom::symbol("nil"))
+}
+
+fn create_improper_list(atoms: &[Atom], last: Atom) -> Atom {
+    atoms
+        .iter()
+        .rev()
+        .fold(last, |cdr, car| Atom::cons(car.clone(), cdr))
 }
@@ -0,0 +1,126 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+use crate::atom::{Atom, Rc};
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::default());
+}
+
+#[derive(Default)]
+struct Profiler {
+    stats: HashMap<Rc<str>, Stats>,
+    stack: Vec<Frame>,
+}
+
+/// Accumulated statistics for a single function.
+#[derive(Default, Clone, Copy)]
+struct Stats {
+    calls: usize,
+    /// Time spent in the function, including time spent in the functions it called.
+    inclusive: Duration,
+    /// Time spent in the function itself.
+    exclusive: Duration,
+}
+
+struct Frame {
+    name: Rc<str>,
+    start: Instant,
+    /// Time spent in functions called from this one.
+    children: Duration,
+}
+
+/// Start profiling function calls, discarding previously collected statistics.
+///
+/// The calls which are still running, like the one starting the profiler, are kept, so that they finish in the
+/// right frame.
+pub fn start() {
+    PROFILER.with(|profiler| profiler.borrow_mut().stats.clear());
+    ENABLED.with(|enabled| enabled.set(true));
+}
+
+/// Stop profiling function calls, keeping the collected statistics.
+pub fn stop() {
+    ENABLED.with(|enabled| enabled.set(false));
+}
+
+/// Record a call to the function given by `op`, until the returned guard is dropped.
+///
+/// Returns `None` if profiling is not enabled.
+#[must_use]
+pub fn enter(op: &Rc<Atom>) -> Option<Guard> {
+    if !ENABLED.with(Cell::get) {
+        return None;
+    }
+    let name = match op.as_ref() {
+        Atom::Symbol(name) => name.clone(),
+        _ => Rc::from("<anonymous>"),
+    };
+    PROFILER.with(|profiler| {
+        profiler.borrow_mut().stack.push(Frame {
+            name,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    });
+    Some(Guard)
+}
+
+/// Finishes recording a function call when dropped.
+pub struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        PROFILER.with(|profiler| {
+            let mut profiler = profiler.borrow_mut();
+            let Some(frame) = profiler.stack.pop() else {
+                return;
+            };
+            let elapsed = frame.start.elapsed();
+            // for recursive calls, only the outermost call counts towards inclusive time
+            let recursive = profiler.stack.iter().any(|f| f.name == frame.name);
+            if let Some(parent) = profiler.stack.last_mut() {
+                parent.children += elapsed;
+            }
+            let stats = profiler.stats.entry(frame.name).or_default();
+            stats.calls += 1;
+            stats.exclusive += elapsed.saturating_sub(frame.children);
+            if !recursive {
+                stats.inclusive += elapsed;
+            }
+        });
+    }
+}
+
+/// Format the collected statistics as a table, sorted by exclusive time.
+#[must_use]
+pub fn report() -> String {
+    PROFILER.with(|profiler| {
+        let profiler = profiler.borrow();
+        let mut stats = profiler.stats.iter().collect::<Vec<_>>();
+        stats.sort_by(|(a_name, a), (b_name, b)| {
+            b.exclusive
+                .cmp(&a.exclusive)
+                .then_with(|| a_name.cmp(b_name))
+        });
+        let mut report = format!(
+            "{:<30} {:>10} {:>16} {:>16}\n",
+            "function", "calls", "inclusive (ms)", "exclusive (ms)"
+        );
+        for (name, stats) in stats {
+            // writing to a string can not fail
+            let _ = writeln!(
+                report,
+                "{:<30} {:>10} {:>16.3} {:>16.3}",
+                name,
+                stats.calls,
+                stats.inclusive.as_secs_f64() * 1000.0,
+                stats.exclusive.as_secs_f64() * 1000.0
+            );
+        }
+        report
+    })
+}
@@ -0,0 +1,247 @@
+//! A session is saved as lisp source which defines its bindings again, so restoring it is evaluating that source.
+//!
+//! Functions and macros are saved as the `lambda` and `defmacro` forms which created them. Names bound to the same
+//! value as a name which was already bound when the session started, like `(define first car)`, are saved as
+//! referring to that name. A function returned by another one is saved along with the local names it uses, like
+//! `((lambda (n) (lambda (x) (+ x n))) 5)`, so that they do not refer to top-level bindings once it is restored.
+//! A local function calling itself is defined again inside such a function, like
+//! `((lambda () (define (count-down n) (if (= n 0) n (count-down (- n 1)))) count-down))`. Functions calling each
+//! other, or capturing a value which can not be saved, can not be saved either.
+//!
+//! The functions and macros of a namespace, like `util:flip`, are defined again in it, between
+//! `(in-namespace util)` and `(in-namespace nil)`, so that the names they use are looked up in it first again.
+
+use std::fmt::Write as _;
+
+use crate::atom::{Atom, Rc};
+use crate::env::Env;
+
+/// Write the bindings of `env` which changed since it was `baseline` as lisp source, sorted by name.
+///
+/// Bindings which can not be written, like a list containing a function, are left out, with a comment saying so.
+#[must_use]
+pub fn save(env: &Env, baseline: &Env) -> String {
+    let mut bindings = env
+        .iter()
+        .filter(|(name, value)| {
+            baseline
+                .lookup(name)
+                .is_none_or(|previous| !Rc::ptr_eq(&previous, value))
+        })
+        .collect::<Vec<_>>();
+    bindings.sort_by_key(|(name, _)| *name);
+
+    let mut src = String::from("#| lwhlisp session, restore it with --restore or :load |#\n");
+    let mut current = None;
+    for (name, value) in bindings {
+        match definition(name, value, baseline) {
+            Some(definition) => {
+                let namespace = namespace(name, value);
+                if namespace != current {
+                    let entered = namespace.as_deref().unwrap_or("nil");
+                    writeln!(src, "\n(in-namespace {entered})").unwrap();
+                    current = namespace;
+                }
+                writeln!(src, "\n{definition}").unwrap();
+            }
+            None => writeln!(
+                src,
+                "\n#| {name} is bound to a {}, which can not be saved |#",
+                value.type_name()
+            )
+            .unwrap(),
+        }
+    }
+    if current.is_some() {
+        writeln!(src, "\n(in-namespace nil)").unwrap();
+    }
+    src
+}
+
+/// The namespace the function or macro `value` was made in, if `name` is qualified with it, so that the names it
+/// uses are looked up in that namespace first once it is defined again in it.
+fn namespace(name: &str, value: &Rc<Atom>) -> Option<Rc<str>> {
+    let namespace = match value.as_ref() {
+        Atom::Closure(env, _, _) | Atom::Macro(env, _, _) => env.namespace()?,
+        Atom::Memoized(function, _) => return namespace(name, function),
+        _ => return None,
+    };
+    // an unqualified name would be put in the namespace
+    name.strip_prefix(&*namespace)?
+        .starts_with(':')
+        .then_some(namespace)
+}
+
+/// The form binding `name` to `value`, if there is one.
+fn definition(name: &Rc<str>, value: &Rc<Atom>, baseline: &Env) -> Option<Rc<Atom>> {
+    let name = Rc::new(Atom::Symbol(name.clone()));
+    if let Atom::Macro(_, params, body) = value.as_ref() {
+        let signature = Rc::new(Atom::Pair(name, params.clone()));
+        return Some(Rc::new(Atom::Pair(
+            Rc::new(Atom::symbol("defmacro")),
+            Rc::new(Atom::Pair(signature, body.clone())),
+        )));
+    }
+    let value = expression(value, baseline, &mut Vec::new())?;
+    Some(Atom::list_from_vec(vec![
+        Rc::new(Atom::symbol("define")),
+        name,
+        value,
+    ]))
+}
+
+/// An expression which evaluates to `value`, if there is one. `closures` are the bodies of the closures whose
+/// expression is being made, which can not be part of it: a local function calling itself is bound to a copy of
+/// itself, with the same body, in the names it captured, so it is defined again under those names instead.
+fn expression(
+    value: &Rc<Atom>,
+    baseline: &Env,
+    closures: &mut Vec<*const Atom>,
+) -> Option<Rc<Atom>> {
+    if let Some(name) = baseline_name(value, baseline) {
+        return Some(Rc::new(Atom::Symbol(name)));
+    }
+    match value.as_ref() {
+        Atom::Number(_) | Atom::String(_) | Atom::Keyword(_) => Some(value.clone()),
+        // maps evaluate to themselves
+        Atom::Map(_) if is_data(value) => Some(value.clone()),
+        Atom::Symbol(_) | Atom::Pair(_, _) if is_data(value) => Some(Atom::list_from_vec(vec![
+            Rc::new(Atom::symbol("quote")),
+            value.clone(),
+        ])),
+        Atom::Closure(function_env, params, body) => {
+            let lambda = Rc::new(Atom::Pair(
+                Rc::new(Atom::symbol("lambda")),
+                Rc::new(Atom::Pair(params.clone(), body.clone())),
+            ));
+            if closures.contains(&Rc::as_ptr(body)) {
+                return None;
+            }
+            let (own_names, captured): (Vec<_>, Vec<_>) =
+                captured(function_env, params, body, baseline)
+                    .into_iter()
+                    .partition(|(_, value)| {
+                        matches!(value.as_ref(), Atom::Closure(_, _, other) if Rc::ptr_eq(other, body))
+                    });
+            closures.push(Rc::as_ptr(body));
+            let captured = captured
+                .into_iter()
+                .map(|(name, value)| Some((name, expression(&value, baseline, closures)?)))
+                .collect::<Option<Vec<_>>>();
+            closures.pop();
+            let (names, values): (Vec<_>, Vec<_>) = captured?.into_iter().unzip();
+            // the function is defined under the first name it calls itself by, and the others refer to it
+            let mut own_names = own_names.into_iter().map(|(name, _)| name);
+            let returned = match own_names.next() {
+                Some(name) => {
+                    let signature = Rc::new(Atom::Pair(name.clone(), params.clone()));
+                    let mut body = vec![Rc::new(Atom::Pair(
+                        Rc::new(Atom::symbol("define")),
+                        Rc::new(Atom::Pair(signature, body.clone())),
+                    ))];
+                    body.extend(own_names.map(|other| {
+                        Atom::list_from_vec(vec![
+                            Rc::new(Atom::symbol("define")),
+                            other,
+                            name.clone(),
+                        ])
+                    }));
+                    body.push(name);
+                    body
+                }
+                None if names.is_empty() => return Some(lambda),
+                None => vec![lambda],
+            };
+            // the captured names are bound again by calling a function taking them, which returns the closure
+            let binding = Rc::new(Atom::Pair(
+                Rc::new(Atom::symbol("lambda")),
+                Rc::new(Atom::Pair(
+                    Atom::list_from_vec(names),
+                    Atom::list_from_vec(returned),
+                )),
+            ));
+            Some(Rc::new(Atom::Pair(binding, Atom::list_from_vec(values))))
+        }
+        Atom::Memoized(function, _) => Some(Atom::list_from_vec(vec![
+            Rc::new(Atom::symbol("memoize")),
+            expression(function, baseline, closures)?,
+        ])),
+        _ => None,
+    }
+}
+
+/// The names bound in the environments of the calls a closure was created in, below the top-level environment,
+/// which its body uses, with their values.
+fn captured(
+    function_env: &Env,
+    params: &Rc<Atom>,
+    body: &Rc<Atom>,
+    baseline: &Env,
+) -> Vec<(Rc<Atom>, Rc<Atom>)> {
+    let params = symbols(params);
+    let used = symbols(body);
+    let mut captured = Vec::<(Rc<Atom>, Rc<Atom>)>::new();
+    let frames = std::iter::successors(Some(function_env), |env| env.parent())
+        .take_while(|env| env.depth() > baseline.depth());
+    for frame in frames {
+        for (name, value) in frame.iter() {
+            let symbol = Rc::new(Atom::Symbol(name.clone()));
+            // the frames closest to the closure come first, and shadow the bindings of those further away
+            if used.contains(name)
+                && !params.contains(name)
+                && !captured.iter().any(|(bound, _)| *bound == symbol)
+            {
+                captured.push((symbol, value.clone()));
+            }
+        }
+    }
+    captured.sort_by_key(|(name, _)| name.to_string());
+    captured
+}
+
+/// The names of the symbols in `atom`.
+fn symbols(atom: &Rc<Atom>) -> Vec<Rc<str>> {
+    let mut symbols = Vec::new();
+    let mut stack = vec![atom.clone()];
+    while let Some(atom) = stack.pop() {
+        match atom.as_ref() {
+            Atom::Symbol(name) => symbols.push(name.clone()),
+            Atom::Pair(car, cdr) => stack.extend([car.clone(), cdr.clone()]),
+            _ => {}
+        }
+    }
+    symbols
+}
+
+/// Find a name which was already bound to `value` when the session started.
+fn baseline_name(value: &Rc<Atom>, baseline: &Env) -> Option<Rc<str>> {
+    // numbers and symbols like nil are shared, so they would be found under unrelated names
+    if !matches!(
+        value.as_ref(),
+        Atom::NativeFunc(_) | Atom::Closure(_, _, _) | Atom::Macro(_, _, _) | Atom::Memoized(_, _)
+    ) {
+        return None;
+    }
+    baseline.symbol_names().into_iter().find(|name| {
+        baseline
+            .lookup(name)
+            .is_some_and(|bound| Rc::ptr_eq(&bound, value))
+    })
+}
+
+/// Whether `atom` only contains data which is read back the same, so that it can be quoted.
+fn is_data(atom: &Rc<Atom>) -> bool {
+    let mut stack = vec![atom.clone()];
+    while let Some(atom) = stack.pop() {
+        match atom.as_ref() {
+            Atom::Number(_) | Atom::String(_) | Atom::Symbol(_) | Atom::Keyword(_) => {}
+            Atom::Pair(car, cdr) => stack.extend([car.clone(), cdr.clone()]),
+            Atom::Map(map) => stack.extend(
+                map.iter()
+                    .flat_map(|(key, value)| [key.clone(), value.clone()]),
+            ),
+            _ => return false,
+        }
+    }
+    true
+}
@@ -1,8 +1,17 @@
-use std::rc::Rc;
-
 use chumsky::Parser;
 
-use crate::{atom::Atom, env::Env, parsing::parser};
+use crate::{
+    atom::{Atom, Layout, Rc},
+    env::Env,
+    gc, json, lint,
+    parsing::{
+        line_column, parse_or_incomplete, parser, parser_with_comments, parser_with_reader_macros,
+        parser_with_spans, Parsed, ReaderMacros, SourceMap, SourceText, SpanTree,
+    },
+    reader_macros, session, Interpreter,
+};
+#[cfg(feature = "cli")]
+use crate::{cache, config};
 
 fn parse_has_error(mut src: &str) {
     src = src.trim();
@@ -27,16 +36,15 @@ fn parse_one(src: &str) -> Atom {
 
 // converts a Vec<Atom> into a corresponding lisp cons list
 fn create_list(x: &[Atom]) -> Atom {
-    match x.first().cloned() {
-        Some(first) => Atom::cons(first, create_list(&x[1..])),
-        None => Atom::nil(),
-    }
+    x.iter()
+        .rev()
+        .fold(Atom::symbol("nil"), |cdr, car| Atom::cons(car.clone(), cdr))
 }
 
 fn run_code(src: &str) -> Rc<Atom> {
     let mut env = Env::default();
     let atoms = parse(src);
-    let mut final_result = Rc::new(Atom::nil());
+    let mut final_result = Atom::nil();
     for atom in atoms {
         let atom = Rc::new(atom);
         let result = Atom::eval(atom.clone(), &mut env);
@@ -44,11 +52,11 @@ fn run_code(src: &str) -> Rc<Atom> {
         match result {
             Ok(result) => {
                 final_result = result.clone();
-                print!("{}", atom);
-                println!(" => {}", result);
+                print!("{atom}");
+                println!(" => {result}");
             }
             Err(e) => {
-                panic!("{} !! {:?}", atom, e);
+                panic!("{atom} !! {e:?}");
             }
         }
     }
@@ -64,10 +72,10 @@ fn run_has_error(src: &str) {
 
         match result {
             Ok(result) => {
-                panic!("{}\n => {}", atom, result);
+                panic!("{atom}\n => {result}");
             }
             Err(e) => {
-                println!("{} !! {:?}", atom, e);
+                println!("{atom} !! {e:?}");
             }
         }
     }
@@ -86,33 +94,40 @@ fn x(x: &str) {
     helper(x, x);
 }
 
+// a path in the temporary directory which no other test, or other run of the tests, uses
+fn temp_path(name: &str) -> std::path::PathBuf {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    std::env::temp_dir().join(format!("lwhlisp-test-{}-{n}-{name}", std::process::id()))
+}
+
 // //// //// //// // BASIC TESTS // //// //// //// //
 
 #[test]
 fn can_run_empty_string() {
     let src = "";
     let expected = Atom::nil();
-    assert_eq!(run_code(src).as_ref().clone(), expected);
+    assert_eq!(run_code(src), expected);
 }
 
 #[test]
 fn nil_is_nil() {
     let src = "nil";
     let expected = Atom::nil();
-    assert_eq!(run_code(src).as_ref().clone(), expected);
+    assert_eq!(run_code(src), expected);
 }
 
 #[test]
 fn empty_list_is_nil() {
-    assert_eq!(parse_one("()"), Atom::nil());
-    assert_eq!(parse_one("(  )"), Atom::nil());
+    assert_eq!(parse_one("()"), *Atom::nil());
+    assert_eq!(parse_one("(  )"), *Atom::nil());
 }
 
 #[test]
 fn t_is_t() {
     let src = "t";
     let expected = Atom::t();
-    assert_eq!(run_code(src).as_ref().clone(), expected);
+    assert_eq!(run_code(src), expected);
 }
 
 #[test]
@@ -123,6 +138,10 @@ fn x_is_x() {
     x("if");
     x("quote");
     x("apply");
+    x("bench");
+    x("gc");
+    #[cfg(feature = "arc")]
+    x("pmap");
 }
 
 #[test]
@@ -137,6 +156,10 @@ fn builtins_exist() {
     exists("println");
     exists("pair?");
     exists("symbol?");
+    exists("keyword?");
+    exists("map?");
+    exists("map-get");
+    exists("map-set");
     exists("string?");
     exists("string-length");
     exists("car");
@@ -152,6 +175,93 @@ fn builtins_exist() {
     exists(">");
     exists(">=");
     exists("<=");
+    exists("sqrt");
+    exists("expt");
+    exists("abs");
+    exists("floor");
+    exists("ceiling");
+    exists("round");
+    exists("truncate");
+    exists("min");
+    exists("max");
+    exists("sin");
+    exists("cos");
+    exists("tan");
+    exists("atan2");
+    exists("log");
+    exists("exp");
+    exists("pi");
+    exists("e");
+    #[cfg(feature = "random")]
+    {
+        exists("random");
+        exists("random-int");
+        exists("random-choice");
+        exists("shuffle");
+        exists("uuid");
+    }
+    exists("bit-and");
+    exists("bit-or");
+    exists("bit-xor");
+    exists("bit-not");
+    exists("shift-left");
+    exists("shift-right");
+    exists("gcd");
+    exists("lcm");
+    exists("string-append");
+    exists("substring");
+    exists("string-split");
+    exists("string-join");
+    exists("string-contains?");
+    exists("string-starts-with?");
+    exists("string-ends-with?");
+    exists("string-index-of");
+    exists("string-replace");
+    exists("string-upcase");
+    exists("string-downcase");
+    exists("string-trim");
+    exists("string-trim-left");
+    exists("string-trim-right");
+    exists("string->symbol");
+    exists("symbol->string");
+    exists("string->list");
+    exists("list->string");
+    exists("string-ref");
+}
+
+#[test]
+fn system_builtins_exist() {
+    fn exists(x: &str) {
+        run_code(x);
+    }
+
+    exists("read-line");
+    exists("read-char");
+    exists("slurp");
+    exists("spit");
+    exists("file-exists?");
+    exists("delete-file");
+    exists("list-directory");
+    exists("make-directory");
+    exists("current-time");
+    exists("monotonic-time");
+    exists("sleep");
+    exists("getenv");
+    exists("setenv");
+    exists("exit");
+    exists("shell");
+    exists("process-run");
+    #[cfg(feature = "encoding")]
+    {
+        exists("base64-encode");
+        exists("base64-decode");
+        exists("hex-encode");
+        exists("hex-decode");
+    }
+    exists("memoize");
+    exists("profile-start");
+    exists("profile-stop");
+    exists("profile-report");
 }
 
 // //// //// //// // BUILTIN TESTS // //// //// //// //
@@ -246,6 +356,192 @@ fn addition() {
     helper("(+ -4 -2)", "-6");
 }
 
+#[test]
+fn variadic_arithmetic() {
+    helper("(+)", "0");
+    // -0 and 0 compare equal, but print differently
+    assert_eq!(run_code("(+)").to_string(), "0");
+    helper("(+ 5)", "5");
+    helper("(+ 1 2 3 4)", "10");
+    helper("(*)", "1");
+    helper("(* 5)", "5");
+    helper("(* 1 2 3 4)", "24");
+    helper("(- 5)", "-5");
+    helper("(- 10 1 2 3)", "4");
+    helper("(/ 4)", "0.25");
+    helper("(/ 60 2 3)", "10");
+
+    run_has_error("(-)");
+    run_has_error("(/)");
+    run_has_error("(+ 1 2 'a)");
+}
+
+#[test]
+fn variadic_comparison() {
+    helper("(< 1)", "t");
+    helper("(< 1 2 3)", "t");
+    helper("(< 1 3 2)", "nil");
+    helper("(<= 1 1 2)", "t");
+    helper("(<= 1 2 1)", "nil");
+    helper("(> 3 2 1)", "t");
+    helper("(> 3 3 1)", "nil");
+    helper("(>= 3 3 1)", "t");
+    helper("(>= 3 1 2)", "nil");
+
+    run_has_error("(<)");
+}
+
+#[test]
+fn square_root() {
+    helper("(sqrt 16)", "4");
+    helper("(sqrt 0)", "0");
+    helper("(sqrt 2.25)", "1.5");
+    run_has_error("(sqrt -1)");
+    run_has_error("(sqrt 'a)");
+}
+
+#[test]
+fn exponentiation() {
+    helper("(expt 2 10)", "1024");
+    helper("(expt 2 -1)", "0.5");
+    helper("(expt 9 0.5)", "3");
+    helper("(expt 5 0)", "1");
+    run_has_error("(expt 2)");
+}
+
+#[test]
+fn absolute_value() {
+    helper("(abs 5)", "5");
+    helper("(abs -5)", "5");
+    helper("(abs -2.5)", "2.5");
+    helper("(abs 0)", "0");
+}
+
+#[test]
+fn rounding() {
+    helper("(floor 2.7)", "2");
+    helper("(floor -2.7)", "-3");
+    helper("(ceiling 2.2)", "3");
+    helper("(ceiling -2.2)", "-2");
+    helper("(truncate 2.7)", "2");
+    helper("(truncate -2.7)", "-2");
+    helper("(round 2.4)", "2");
+    helper("(round 2.6)", "3");
+    // halves are rounded away from zero
+    helper("(round 2.5)", "3");
+    helper("(round -2.5)", "-3");
+    helper("(round 0.5)", "1");
+}
+
+#[test]
+fn minimum_and_maximum() {
+    helper("(min 3)", "3");
+    helper("(min 3 1 2)", "1");
+    helper("(min -3 1 2)", "-3");
+    helper("(max 3)", "3");
+    helper("(max 3 1 2)", "3");
+    helper("(max -3 -1 -2)", "-1");
+    run_has_error("(min)");
+    run_has_error("(max)");
+}
+
+#[test]
+fn transcendental_functions() {
+    helper("(sin 0)", "0");
+    helper("(cos 0)", "1");
+    helper("(tan 0)", "0");
+    helper("(< (abs (sin pi)) 1e-10)", "t");
+    helper("(< (abs (+ (cos pi) 1)) 1e-10)", "t");
+    helper("(atan2 0 1)", "0");
+    helper("(= (atan2 1 0) (/ pi 2))", "t");
+    helper("(exp 0)", "1");
+    helper("(log 1)", "0");
+    helper("(log e)", "1");
+    helper("(< (abs (- (log (exp 2)) 2)) 1e-10)", "t");
+    run_has_error("(log 0)");
+    run_has_error("(log -1)");
+    run_has_error("(atan2 1)");
+}
+
+#[cfg(feature = "random")]
+#[test]
+fn random_numbers() {
+    for _ in 0..20 {
+        helper("(<= 0 (random))", "t");
+        helper("(< (random) 1)", "t");
+        helper("(<= 0 (random-int 10) 9)", "t");
+        helper("(<= -5 (random-int -5 5) 4)", "t");
+        helper("(< 0 (random-choice '(1 2 3)) 4)", "t");
+        helper("(apply + (shuffle '(1 2 3 4)))", "10");
+        helper("(apply * (shuffle '(1 2 3 4)))", "24");
+    }
+    helper("(random-int 1)", "0");
+    helper("(random-int 3 4)", "3");
+    helper("(random-choice '(5))", "5");
+    helper("(shuffle nil)", "nil");
+
+    run_has_error("(random 1)");
+    run_has_error("(random-int 0)");
+    run_has_error("(random-int 5 2)");
+    run_has_error("(random-int 2.5)");
+    run_has_error("(random-choice nil)");
+    run_has_error("(shuffle '(1 2 . 3))");
+}
+
+#[test]
+fn bitwise_operations() {
+    helper("(bit-and 12 10)", "8");
+    helper("(bit-and 15 14 7)", "6");
+    helper("(bit-and)", "-1");
+    helper("(bit-or 12 10)", "14");
+    helper("(bit-or 1 2 4)", "7");
+    helper("(bit-or)", "0");
+    helper("(bit-xor 12 10)", "6");
+    helper("(bit-xor 1 3 7)", "5");
+    helper("(bit-xor)", "0");
+    helper("(bit-not 0)", "-1");
+    helper("(bit-not 5)", "-6");
+    helper("(shift-left 1 10)", "1024");
+    helper("(shift-left 3 0)", "3");
+    helper("(shift-right 1024 3)", "128");
+    helper("(shift-right -8 1)", "-4");
+
+    run_has_error("(bit-and 1.5 1)");
+    run_has_error("(bit-not)");
+    run_has_error("(shift-left 1 64)");
+    run_has_error("(shift-right 1 -1)");
+    run_has_error("(shift-left 1)");
+}
+
+#[test]
+fn greatest_common_divisor() {
+    helper("(gcd)", "0");
+    helper("(gcd 12)", "12");
+    helper("(gcd -12)", "12");
+    helper("(gcd 12 18)", "6");
+    helper("(gcd 12 18 8)", "2");
+    helper("(gcd -12 18)", "6");
+    helper("(gcd 0 5)", "5");
+    helper("(gcd 7 13)", "1");
+    run_has_error("(gcd 1.5 3)");
+    run_has_error("(gcd 1e300)");
+    helper("(gcd -9223372036854775808)", "9223372036854775808");
+    helper("(gcd -9223372036854775808 6)", "2");
+}
+
+#[test]
+fn least_common_multiple() {
+    helper("(lcm)", "1");
+    helper("(lcm 6)", "6");
+    helper("(lcm 4 6)", "12");
+    helper("(lcm 2 3 4)", "12");
+    helper("(lcm -4 6)", "12");
+    helper("(lcm 0 5)", "0");
+    run_has_error("(lcm 1.5 3)");
+    helper("(lcm -9223372036854775808)", "9223372036854775808");
+    run_has_error("(lcm -9223372036854775808 3)");
+}
+
 #[test]
 fn cons() {
     helper("(cons 1 2)", "'(1 . 2)");
@@ -286,6 +582,131 @@ fn string_length() {
     helper("(string-length \"👍\")", "1");
 }
 
+#[test]
+fn string_append() {
+    helper("(string-append)", "\"\"");
+    helper("(string-append \"abc\")", "\"abc\"");
+    helper("(string-append \"abc\" \"def\" \"\" \"g\")", "\"abcdefg\"");
+    run_has_error("(string-append \"abc\" 1)");
+}
+
+#[test]
+fn substring() {
+    helper("(substring \"hello world\" 6)", "\"world\"");
+    helper("(substring \"hello world\" 0 5)", "\"hello\"");
+    helper("(substring \"hello\" 2 2)", "\"\"");
+    helper("(substring \"hello\" 5)", "\"\"");
+    helper("(substring \"👍abc\" 1 3)", "\"ab\"");
+    run_has_error("(substring \"hello\" 6)");
+    run_has_error("(substring \"hello\" 3 2)");
+    run_has_error("(substring \"hello\" 0 6)");
+    run_has_error("(substring \"hello\" -1)");
+    run_has_error("(substring \"hello\" 1.5)");
+    run_has_error("(substring \"hello\")");
+}
+
+#[test]
+fn string_split() {
+    helper("(string-split \"a,b,c\" \",\")", "'(\"a\" \"b\" \"c\")");
+    helper("(string-split \"a, b\" \", \")", "'(\"a\" \"b\")");
+    helper("(string-split \"abc\" \",\")", "'(\"abc\")");
+    helper("(string-split \",a,\" \",\")", "'(\"\" \"a\" \"\")");
+    run_has_error("(string-split \"abc\" \"\")");
+    run_has_error("(string-split \"abc\")");
+}
+
+#[test]
+fn string_join() {
+    helper("(string-join '(\"a\" \"b\" \"c\") \", \")", "\"a, b, c\"");
+    helper("(string-join '(\"a\" \"b\" \"c\"))", "\"abc\"");
+    helper("(string-join nil \",\")", "\"\"");
+    helper(
+        "(string-join (string-split \"a,b,c\" \",\") \";\")",
+        "\"a;b;c\"",
+    );
+    run_has_error("(string-join '(\"a\" 1) \",\")");
+}
+
+#[test]
+fn string_search() {
+    helper(r#"(string-contains? "hello world" "o w")"#, "t");
+    helper(r#"(string-contains? "hello world" "")"#, "t");
+    helper(r#"(string-contains? "hello world" "xyz")"#, "nil");
+    helper(r#"(string-starts-with? "hello world" "hello")"#, "t");
+    helper(r#"(string-starts-with? "hello world" "world")"#, "nil");
+    helper(r#"(string-ends-with? "hello world" "world")"#, "t");
+    helper(r#"(string-ends-with? "hello world" "hello")"#, "nil");
+    run_has_error(r#"(string-contains? "hello" 1)"#);
+    run_has_error(r#"(string-starts-with? "hello")"#);
+}
+
+#[test]
+fn string_index_of() {
+    helper(r#"(string-index-of "hello world" "world")"#, "6");
+    helper(r#"(string-index-of "hello world" "o")"#, "4");
+    helper(r#"(string-index-of "hello world" "")"#, "0");
+    helper(r#"(string-index-of "hello world" "xyz")"#, "nil");
+    helper(r#"(string-index-of "👍👍abc" "abc")"#, "2");
+}
+
+#[test]
+fn string_replace() {
+    helper(
+        r#"(string-replace "hello world" "o" "0")"#,
+        r#""hell0 w0rld""#,
+    );
+    helper(r#"(string-replace "aaa" "a" "bb")"#, r#""bbbbbb""#);
+    helper(r#"(string-replace "hello" "xyz" "abc")"#, r#""hello""#);
+    helper(r#"(string-replace "hello" "l" "")"#, r#""heo""#);
+    run_has_error(r#"(string-replace "hello" "" "x")"#);
+    run_has_error(r#"(string-replace "hello" "l")"#);
+}
+
+#[test]
+fn string_case() {
+    helper(r#"(string-upcase "Hello World")"#, r#""HELLO WORLD""#);
+    helper(r#"(string-downcase "Hello World")"#, r#""hello world""#);
+    helper(r#"(string-upcase "éàü")"#, r#""ÉÀÜ""#);
+    helper(r#"(string-downcase "ΑΒΓ")"#, r#""αβγ""#);
+    helper(r#"(string-upcase "straße")"#, r#""STRASSE""#);
+    helper(r#"(string-upcase "👍")"#, r#""👍""#);
+    run_has_error("(string-upcase 'abc)");
+}
+
+#[test]
+fn string_trim() {
+    helper(r#"(string-trim "  hello  ")"#, r#""hello""#);
+    helper(r#"(string-trim-left "  hello  ")"#, r#""hello  ""#);
+    helper(r#"(string-trim-right "  hello  ")"#, r#""  hello""#);
+    helper(r#"(string-trim "\n\t hello \t\n")"#, r#""hello""#);
+    // U+3000 IDEOGRAPHIC SPACE is unicode whitespace
+    helper(r#"(string-trim "　héllo　")"#, r#""héllo""#);
+    helper(r#"(string-trim "   ")"#, r#""""#);
+    run_has_error("(string-trim)");
+}
+
+#[test]
+fn string_list_conversion() {
+    helper(r#"(string->list "abc")"#, r#"'("a" "b" "c")"#);
+    helper(r#"(string->list "")"#, "nil");
+    helper(r#"(string->list "é👍")"#, r#"'("é" "👍")"#);
+    helper(r#"(list->string '("a" "b" "c"))"#, r#""abc""#);
+    helper("(list->string nil)", r#""""#);
+    helper(r#"(list->string (string->list "héllo"))"#, r#""héllo""#);
+    run_has_error("(list->string '(1 2))");
+    run_has_error("(string->list 'abc)");
+}
+
+#[test]
+fn string_ref() {
+    helper(r#"(string-ref "abc" 0)"#, r#""a""#);
+    helper(r#"(string-ref "abc" 2)"#, r#""c""#);
+    helper(r#"(string-ref "é👍" 1)"#, r#""👍""#);
+    run_has_error(r#"(string-ref "abc" 3)"#);
+    run_has_error(r#"(string-ref "abc" -1)"#);
+    run_has_error(r#"(string-ref "abc")"#);
+}
+
 #[test]
 fn is_string() {
     helper("(string? \"Hello World!\")", "t");
@@ -295,6 +716,29 @@ fn is_string() {
     helper("(string? =)", "nil");
 }
 
+#[test]
+fn symbol_string_conversion() {
+    helper(r#"(string->symbol "abc")"#, "'abc");
+    helper(r#"(string->symbol "nil")"#, "nil");
+    helper(r#"(symbol? (string->symbol "abc"))"#, "t");
+    helper("(symbol->string 'abc)", r#""abc""#);
+    helper("(symbol->string nil)", r#""nil""#);
+    helper(
+        r#"(string->symbol (string-append "get-" (symbol->string 'name)))"#,
+        "'get-name",
+    );
+    helper(
+        r#"(defmacro (getter-name name)
+             (cons 'quote
+                   (cons (string->symbol (string-append "get-" (symbol->string name))) nil)))
+           (getter-name foo)"#,
+        "'get-foo",
+    );
+    run_has_error(r#"(string->symbol "")"#);
+    run_has_error("(string->symbol 'abc)");
+    run_has_error(r#"(symbol->string "abc")"#);
+}
+
 #[test]
 fn is_symbol() {
     helper("(symbol? t)", "t");
@@ -305,6 +749,141 @@ fn is_symbol() {
     helper("(symbol? =)", "nil");
 }
 
+#[test]
+fn keywords() {
+    helper(":foo", ":foo");
+    helper("(keyword? :foo)", "t");
+    helper("(keyword? 'foo)", "nil");
+    helper("(symbol? :foo)", "nil");
+    helper("(= :foo :foo)", "t");
+    helper("(= :foo :bar)", "nil");
+    helper("(cons :a 1)", "'(:a . 1)");
+}
+
+#[test]
+fn maps() {
+    helper("{:b 2 :a 1}", "{:a 1 :b 2}");
+    helper("(map-get {:a 1 \"b\" (2 3)} \"b\")", "'(2 3)");
+    helper("(map-get {:a 1} :b)", "nil");
+    helper("(map-get {:a 1} :b 0)", "0");
+    helper("(map-set (map-set {} :a 1) :a 2)", "{:a 2}");
+    helper("(map? {})", "t");
+    helper("(map? '(:a 1))", "nil");
+    // the keys and values are not evaluated
+    helper("(map-get {x (+ 1 2)} 'x)", "'(+ 1 2)");
+
+    // maps are printed the way they are written, with their entries sorted by key
+    let map = run_code("{:c \"z\" :a {1 2} :b (1 . 2)}");
+    assert_eq!(map.to_string(), "{:a {1 2} :b (1 . 2) :c \"z\"}");
+    assert_eq!(parse_one(&map.to_string()), *map);
+
+    // equal maps hash the same, whatever order their entries were added in
+    let hash = |src: &str| {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        run_code(src).hash(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(hash("{:a 1 :b 2 :c 3}"), hash("{:c 3 :b 2 :a 1}"));
+    assert_ne!(hash("{:a 1 :b 2}"), hash("{:a 2 :b 1}"));
+
+    parse_has_error("{:a 1 :b}");
+    parse_has_error("{:a 1");
+    parse_has_error(":a 1}");
+}
+
+#[test]
+fn type_names() {
+    for (src, expected) in [
+        ("1", "number"),
+        ("\"a\"", "string"),
+        ("'a", "symbol"),
+        (":a", "keyword"),
+        ("{:a 1}", "map"),
+        ("'(1 2)", "list"),
+        ("'(1 . 2)", "pair"),
+        ("car", "builtin"),
+        ("(lambda (x) x)", "function"),
+    ] {
+        assert_eq!(run_code(src).type_name(), expected);
+    }
+}
+
+#[test]
+fn pretty_print_layout() {
+    let width = |width| Layout {
+        width,
+        ..Layout::default()
+    };
+    let atom = run_code("'(define (f x) (+ x 1))");
+    assert_eq!(atom.to_string(), "(define (f x) (+ x 1))");
+    assert_eq!(
+        atom.pretty_print_with(&width(20)),
+        "(define (f x)\n   (+ x 1))"
+    );
+    // the closing parentheses count too
+    assert!(atom
+        .pretty_print_with(&width(11))
+        .ends_with("\n   (+ x 1))"));
+    assert!(atom.pretty_print_with(&width(10)).contains("\n   (+\n"));
+    let long = run_code("'(a-long-symbol another-long-symbol)");
+    // atoms wider than the lines are still printed whole
+    assert_eq!(
+        long.pretty_print_with(&width(10)),
+        "(a-long-symbol\n   another-long-symbol)"
+    );
+    let layout = Layout {
+        width: 20,
+        indent: 2,
+        ..Layout::default()
+    };
+    assert_eq!(atom.pretty_print_with(&layout), "(define (f x)\n  (+ x 1))");
+}
+
+#[test]
+fn pretty_print_body_forms() {
+    let atom = run_code("'(with-file \"name\" :read (read-line) (close))");
+    let mut layout = Layout {
+        width: 20,
+        ..Layout::default()
+    };
+    assert_eq!(
+        atom.pretty_print_with(&layout),
+        "(with-file\n   \"name\"\n   :read\n   (read-line)\n   (close))"
+    );
+    layout.body_forms.insert("with-file".to_string(), 2);
+    assert_eq!(
+        atom.pretty_print_with(&layout),
+        "(with-file \"name\" :read\n   (read-line)\n   (close))"
+    );
+    layout.body_forms.clear();
+    layout.align_arguments = true;
+    assert_eq!(
+        atom.pretty_print_with(&layout),
+        "(with-file \"name\"\n           :read\n           (read-line)\n           (close))"
+    );
+}
+
+#[cfg(feature = "diagnostics")]
+#[test]
+fn colored_printing() {
+    let atom = run_code("'(1 \"s\" :k (x . 2))");
+    let colored = atom.colored();
+    assert!(colored.contains("\u{1b}["));
+
+    // without the escape sequences, it is printed the same as without colors
+    let mut plain = String::new();
+    let mut chars = colored.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    assert_eq!(plain, atom.to_string());
+}
+
 #[test]
 fn is_pair() {
     helper("(pair? (cons 1 2))", "t");
@@ -326,194 +905,1706 @@ fn into_string() {
     helper("(into-string nil)", r#""nil""#);
     helper("(into-string 'arbitrary-symbol)", r#""arbitrary-symbol""#);
     helper("(into-string =)", r##""#<BUILTIN>""##);
-    helper("(into-string '(1 2 3))", r##""(1 2 3)""##);
-    helper("(into-string '(1 (2 3)))", r##""(1 (2 3))""##);
+    helper("(into-string '(1 2 3))", r#""(1 2 3)""#);
+    helper("(into-string '(1 (2 3)))", r#""(1 (2 3))""#);
 }
 
 // into-pretty-string is not tested, because it's behaviour may change more often, and is less likely to influence program behaviour
 // print and println are not tested, because the side effects are difficult to test
-
-// //// //// //// // MAKE-A-LISP TESTS // //// //// //// //
-
-fn run(src: &str) -> Atom {
-    run_code(src).as_ref().clone()
-}
+// the same goes for read-line and read-char, so we only test the argument checking
 
 #[test]
-fn read_numbers() {
-    assert_eq!(run("1"), Atom::integer(1));
-    assert_eq!(run("7"), Atom::integer(7));
-    assert_eq!(run("   7"), Atom::integer(7));
-    assert_eq!(run("-123"), Atom::integer(-123));
+fn read_from_stdin_arguments() {
+    run_has_error("(read-line 1)");
+    run_has_error("(read-char 1)");
 }
 
 #[test]
-fn read_symbol() {
-    assert_eq!(parse_one("+"), Atom::symbol("+"));
-    assert_eq!(parse_one("abc"), Atom::symbol("abc"));
-    assert_eq!(parse_one("   abc"), Atom::symbol("abc"));
-    assert_eq!(parse_one("abc5"), Atom::symbol("abc5"));
-    assert_eq!(parse_one("abc-def"), Atom::symbol("abc-def"));
-}
+fn slurp_and_spit() {
+    let path = temp_path("slurp-and-spit.txt");
+    let path = path.to_str().unwrap();
 
-#[test]
-fn read_symbol_starting_with_dash() {
-    assert_eq!(parse_one("-"), Atom::symbol("-"));
-    assert_eq!(parse_one("-abc"), Atom::symbol("-abc"));
-    assert_eq!(parse_one("->>"), Atom::symbol("->>"));
+    helper(
+        &format!(r#"(spit "{path}" "hello") (slurp "{path}")"#),
+        r#""hello""#,
+    );
+    helper(
+        &format!(r#"(spit "{path}" "hello") (spit "{path}" " world" t) (slurp "{path}")"#),
+        r#""hello world""#,
+    );
+    helper(
+        &format!(r#"(spit "{path}" "hello") (spit "{path}" "bye" nil) (slurp "{path}")"#),
+        r#""bye""#,
+    );
+    std::fs::remove_file(path).unwrap();
+
+    run_has_error(&format!(r#"(slurp "{path}")"#));
+    run_has_error(r#"(spit "/this/directory/does/not/exist" "hello")"#);
+    run_has_error(r#"(spit "file.txt")"#);
+    run_has_error(r#"(spit "file.txt" 1)"#);
 }
 
 #[test]
-fn read_list() {
-    assert_eq!(
-        parse_one("(+ 1 2)"),
-        Atom::Pair(
-            Rc::new(Atom::symbol("+")),
-            Rc::new(Atom::Pair(
-                Rc::new(Atom::integer(1)),
-                Rc::new(Atom::Pair(Rc::new(Atom::integer(2)), Rc::new(Atom::nil())))
-            ))
-        )
+fn filesystem() {
+    let dir = temp_path("filesystem");
+    let dir = dir.to_str().unwrap();
+    drop(std::fs::remove_dir_all(dir));
+
+    helper(&format!(r#"(file-exists? "{dir}")"#), "nil");
+    helper(&format!(r#"(make-directory "{dir}/sub")"#), "t");
+    helper(&format!(r#"(file-exists? "{dir}/sub")"#), "t");
+    helper(
+        &format!(r#"(spit "{dir}/b.txt" "b") (spit "{dir}/a.txt" "a") (list-directory "{dir}")"#),
+        r#"'("a.txt" "b.txt" "sub")"#,
     );
-
-    assert_eq!(
-        parse_one("(+ 1 2)"),
-        create_list(&[Atom::symbol("+"), Atom::integer(1), Atom::integer(2)])
+    helper(&format!(r#"(delete-file "{dir}/a.txt")"#), "t");
+    helper(&format!(r#"(file-exists? "{dir}/a.txt")"#), "nil");
+    helper(
+        &format!(r#"(list-directory "{dir}")"#),
+        r#"'("b.txt" "sub")"#,
     );
+    run_has_error(&format!(r#"(delete-file "{dir}/a.txt")"#));
+    run_has_error(&format!(r#"(list-directory "{dir}/does-not-exist")"#));
 
-    assert_eq!(parse_one("(nil)"), create_list(&[Atom::nil()]));
-
-    assert_eq!(
-        parse_one("((3 4))"),
-        create_list(&[create_list(&[Atom::integer(3), Atom::integer(4)])])
-    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
 
-    assert_eq!(
-        parse_one("(+ 1 (+ 2 3))"),
-        create_list(&[
-            Atom::symbol("+"),
-            Atom::integer(1),
-            create_list(&[Atom::symbol("+"), Atom::integer(2), Atom::integer(3)])
-        ])
+#[test]
+fn time() {
+    // 2022-01-01T00:00:00Z
+    helper("(> (current-time) 1640995200)", "t");
+    helper("(<= 0 (monotonic-time))", "t");
+    helper(
+        "(define start (monotonic-time)) (sleep 0.05) (>= (- (monotonic-time) start) 0.05)",
+        "t",
     );
+    helper("(sleep 0)", "nil");
+    run_has_error("(sleep -1)");
+    run_has_error("(sleep 'a)");
+    run_has_error("(current-time 1)");
+    run_has_error("(monotonic-time 1)");
+}
 
-    assert_eq!(
-        parse_one("  ( +   1   (+   2 3   )   )  "),
-        create_list(&[
-            Atom::symbol("+"),
-            Atom::integer(1),
-            create_list(&[Atom::symbol("+"), Atom::integer(2), Atom::integer(3)])
-        ])
-    );
+// setenv changes the environment of the whole process, which other tests running at the same time read, so the test
+// runs again by itself in a child process
+#[test]
+fn environment_variables() {
+    const CHILD: &str = "LWHLISP_TEST_ENVIRONMENT_VARIABLES_CHILD";
+    if std::env::var_os(CHILD).is_none() {
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "tests::environment_variables",
+                "--test-threads=1",
+            ])
+            .env(CHILD, "1")
+            .stdout(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        return;
+    }
 
-    assert_eq!(
-        parse_one("(* 1 2)"),
-        create_list(&[Atom::symbol("*"), Atom::integer(1), Atom::integer(2)])
+    helper(r#"(getenv "LWHLISP_TEST_UNSET_VARIABLE")"#, "nil");
+    helper(
+        r#"(setenv "LWHLISP_TEST_VARIABLE" "value") (getenv "LWHLISP_TEST_VARIABLE")"#,
+        r#""value""#,
     );
-
-    assert_eq!(
-        parse_one("(** 1 2)"),
-        create_list(&[Atom::symbol("**"), Atom::integer(1), Atom::integer(2)])
+    helper(
+        r#"(setenv "LWHLISP_TEST_VARIABLE" nil) (getenv "LWHLISP_TEST_VARIABLE")"#,
+        "nil",
     );
+    run_has_error(r#"(setenv "" "value")"#);
+    run_has_error(r#"(setenv "A=B" "value")"#);
+    run_has_error(r#"(setenv "LWHLISP_TEST_VARIABLE" 1)"#);
+    run_has_error("(getenv 'HOME)");
+}
 
-    assert_eq!(
-        parse_one("(* -3 6)"),
-        create_list(&[Atom::symbol("*"), Atom::integer(-3), Atom::integer(6)])
-    );
+// stdin is only read once, and only what the test writes to it should be, so the test runs again in a child process
+// reading it
+#[cfg(feature = "eyre")]
+#[test]
+fn read_files_and_stdin() {
+    use std::io::Write as _;
 
-    assert_eq!(
-        parse_one("(() ())"),
-        create_list(&[Atom::nil(), Atom::nil()])
-    );
+    const CHILD: &str = "LWHLISP_TEST_READ_STDIN_CHILD";
+    if std::env::var_os(CHILD).is_some() {
+        assert_eq!(crate::read_file_to_string("-").unwrap(), "(+ 1 2)\n");
+        return;
+    }
+    let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "tests::read_files_and_stdin", "--test-threads=1"])
+        .env(CHILD, "1")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"(+ 1 2)\n").unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let path = temp_path("read.lisp");
+    std::fs::write(&path, "(car x)").unwrap();
+    let path = path.display().to_string();
+    assert_eq!(crate::read_file_to_string(&path).unwrap(), "(car x)");
+    std::fs::remove_file(&path).unwrap();
+    assert!(crate::read_file_to_string(&path).is_err());
 }
 
 #[test]
-fn read_nil_true_false() {
-    assert_eq!(parse_one("nil"), Atom::symbol("nil"));
-    assert_eq!(parse_one("true"), Atom::symbol("true"));
-    assert_eq!(parse_one("false"), Atom::symbol("false"));
+fn exit_arguments() {
+    // the process is not exited, evaluation stops with an error instead
+    let e = Interpreter::new()
+        .eval_str("(exit 3) (car 1 2)")
+        .unwrap_err();
+    assert_eq!(e.exit_status(), Some(3));
+    assert_eq!(e.kind(), crate::error::ErrorKind::Exit(3));
+    let e = Interpreter::new().eval_str("(exit)").unwrap_err();
+    assert_eq!(e.exit_status(), Some(0));
+    let e = Interpreter::new().eval_str("(car 1 2)").unwrap_err();
+    assert_eq!(e.exit_status(), None);
+
+    run_has_error("(exit 1.5)");
+    run_has_error("(exit 1 2)");
+    run_has_error(r#"(exit "1")"#);
+    run_has_error("(exit 9999999999)");
 }
 
+#[cfg(unix)]
 #[test]
-fn read_string() {
-    assert_eq!(parse_one("\"abc\""), Atom::string("abc"));
-    assert_eq!(parse_one("   \"abc\""), Atom::string("abc"));
-    assert_eq!(
-        parse_one("\"abc (with parens)\""),
-        Atom::string("abc (with parens)")
+fn subprocesses() {
+    helper(r#"(shell "printf hello")"#, r#"(quote (0 "hello" ""))"#);
+    helper(
+        r#"(shell "printf oops >&2; exit 3")"#,
+        r#"(quote (3 "" "oops"))"#,
     );
-    assert_eq!(parse_one(r#""abc\"def""#), Atom::string("abc\"def"));
-    assert_eq!(parse_one("\"\""), Atom::string(""));
-    assert_eq!(parse_one(r#""\\""#), Atom::string(r#"\"#));
-    assert_eq!(
-        parse_one(r#""\\\\\\\\\\\\\\\\\\""#),
-        Atom::string(r#"\\\\\\\\\"#)
+    helper(r#"(shell "true" t)"#, "(quote (0 nil nil))");
+    helper(
+        r#"(process-run "printf" (quote ("%s-%s" "a" "b c")))"#,
+        r#"(quote (0 "a-b c" ""))"#,
     );
+    helper(r#"(process-run "true")"#, r#"(quote (0 "" ""))"#);
+    run_has_error(r#"(process-run "lwhlisp-nonexistent-program")"#);
+    run_has_error(r#"(process-run "printf" (quote (1 2)))"#);
+    run_has_error("(shell 'true)");
+    run_has_error("(shell)");
 }
 
+#[cfg(feature = "encoding")]
 #[test]
-fn read_single_char_string() {
-    fn single_char_string(s: &str) {
-        assert_eq!(parse_one(&format!("\"{}\"", s)), Atom::string(s));
-    }
+fn base64_encoding() {
+    helper(r#"(base64-encode "hello, world")"#, r#""aGVsbG8sIHdvcmxk""#);
+    helper(r#"(base64-encode "")"#, r#""""#);
+    helper(r#"(base64-decode "aGVsbG8sIHdvcmxk")"#, r#""hello, world""#);
+    helper(r#"(base64-decode (base64-encode "héllo"))"#, r#""héllo""#);
+    run_has_error(r#"(base64-decode "not base64!")"#);
+    run_has_error(r#"(base64-decode "/w==")"#);
+    run_has_error("(base64-encode 1)");
+}
 
-    for c in "&-()*+,-/:;<=>?@[]^_`{}~!".chars() {
-        single_char_string(&c.to_string());
-    }
+#[cfg(feature = "encoding")]
+#[test]
+fn hex_encoding() {
+    helper(r#"(hex-encode "hi!")"#, r#""686921""#);
+    helper(r#"(hex-decode "686921")"#, r#""hi!""#);
+    helper(r#"(hex-decode "68692A")"#, r#""hi*""#);
+    helper(r#"(hex-decode (hex-encode "héllo"))"#, r#""héllo""#);
+    run_has_error(r#"(hex-decode "6869a")"#);
+    run_has_error(r#"(hex-decode "zz")"#);
+    run_has_error(r#"(hex-decode "ff")"#);
 }
 
+#[cfg(feature = "random")]
 #[test]
-fn read_erronous_input() {
-    parse_has_error("(1 2");
-    parse_has_error("[1 2");
-    parse_has_error("\"abc");
-    parse_has_error("\\");
-    parse_has_error(r#"\\\\\\\\\\\\\\\\\\\"#);
-    parse_has_error(r#"(1 \"abc"#);
-    parse_has_error(r#"(1 \"abc\""#);
+fn uuid_generation() {
+    let uuid = run_code("(uuid)").get_string().unwrap();
+    assert_eq!(uuid.len(), 36);
+    assert_eq!(uuid.chars().nth(14), Some('4'));
+    assert!(matches!(uuid.chars().nth(19), Some('8' | '9' | 'a' | 'b')));
+    assert_ne!(uuid, run_code("(uuid)").get_string().unwrap());
+    run_has_error("(uuid 1)");
 }
 
 #[test]
-fn read_quote() {
-    assert_eq!(
-        parse_one("'1"),
-        create_list(&[Atom::symbol("quote"), Atom::integer(1)])
+fn closure_scoping() {
+    // bindings from where the closure was created take precedence over those of the caller
+    helper(
+        "(define x 1)
+         (define make-adder (lambda (x) (lambda (y) (+ x y))))
+         (define add-ten (make-adder 10))
+         ((lambda (x) (add-ten x)) 5)",
+        "15",
     );
-    assert_eq!(
-        parse_one("'(1 2 3)"),
-        create_list(&[
-            Atom::symbol("quote"),
-            create_list(&[Atom::integer(1), Atom::integer(2), Atom::integer(3)])
-        ])
+    // names not bound where the closure was created are looked up in the caller
+    helper(
+        "(define f (lambda () y))
+         ((lambda (y) (f)) 3)",
+        "3",
+    );
+    helper(
+        "(define (count-down n) (if (= n 0) 0 (count-down (- n 1))))
+         (count-down 200)",
+        "0",
     );
 }
 
 #[test]
-fn read_quasiquote() {
-    assert_eq!(
-        parse_one("`1"),
-        create_list(&[Atom::symbol("quasiquote"), Atom::integer(1)])
-    );
+fn long_lists() {
+    const LENGTH: usize = 100_000;
+    let elements = "1 ".repeat(LENGTH);
+    let mut env = Env::default();
+
+    let list = Rc::new(parse_one(&format!("({elements})")));
+    // too long for a single line, so each element is on its own line
     assert_eq!(
-        parse_one("`(1 2 3)"),
-        create_list(&[
-            Atom::symbol("quasiquote"),
-            create_list(&[Atom::integer(1), Atom::integer(2), Atom::integer(3)])
-        ])
+        list.to_string(),
+        format!("(1{})", "\n   1".repeat(LENGTH - 1))
     );
+    assert_eq!(Atom::into_vec(list).len(), LENGTH + 1);
+
+    #[allow(clippy::cast_precision_loss)]
+    let expected = Atom::number(LENGTH as f64);
+    let sum = Atom::eval(Rc::new(parse_one(&format!("(+ {elements})"))), &mut env).unwrap();
+    assert_eq!(sum, expected);
+    let sum = Atom::eval(
+        Rc::new(parse_one(&format!("(apply + (quote ({elements})))"))),
+        &mut env,
+    )
+    .unwrap();
+    assert_eq!(sum, expected);
+    // the arguments of a variadic function are evaluated into a list without recursing per argument
+    let sum = Atom::eval(
+        Rc::new(parse_one(&format!(
+            "((lambda args (apply + args)) {elements})"
+        ))),
+        &mut env,
+    )
+    .unwrap();
+    assert_eq!(sum, expected);
 }
 
+#[cfg(feature = "cli")]
 #[test]
-fn read_unquote() {
+fn parse_cache() {
+    let src = r#"(define (f x) (cons x "string")) '(1 2 . 3) (quote ()) {:a {1 2}}"#;
+    let atoms = parse(src);
+    let hash = cache::hash_source(src);
+    let bytes = cache::encode(&atoms, hash).unwrap();
+    assert_eq!(cache::decode(&bytes, hash), Some(atoms));
+    assert_eq!(
+        cache::decode(&bytes, cache::hash_source("(other source)")),
+        None
+    );
+    assert_eq!(cache::decode(&bytes[..bytes.len() - 1], hash), None);
+    assert_eq!(cache::decode(b"not a cache", hash), None);
+    assert!(cache::encode(&[Atom::NativeFunc(Rc::new(Ok))], hash).is_err());
+}
+
+#[test]
+fn default_library_loads() {
+    let mut env = Env::default();
+    for atom in parse(crate::DEFAULT_LIBRARY) {
+        Atom::eval(Rc::new(atom), &mut env).unwrap();
+    }
+    let result = Atom::eval(
+        Rc::new(parse_one("(map (lambda (x) (* x 2)) (list 1 2 3))")),
+        &mut env,
+    )
+    .unwrap();
+    assert_eq!(result, Rc::new(parse_one("(2 4 6)")));
+}
+
+fn optimized(src: &str, env: &Env) -> Rc<Atom> {
+    crate::optimize::optimize(&Rc::new(parse_one(src)), env)
+}
+
+#[test]
+fn optimize_folds_constants() {
+    let env = Env::default();
+    assert_eq!(optimized("(+ 1 (* 2 3))", &env), Atom::integer(7));
+    assert_eq!(optimized("(quote 5)", &env), Atom::integer(5));
+    assert_eq!(
+        optimized(r#"(quote "text")"#, &env),
+        Rc::new(Atom::string("text"))
+    );
+    assert_eq!(
+        optimized("(if t (- 10 4) (sqrt 16))", &env),
+        Rc::new(parse_one("(if t 6 4)"))
+    );
+    // not constant, or not pure
+    assert_eq!(
+        optimized("(quote (+ 1 2))", &env),
+        Rc::new(parse_one("(quote (+ 1 2))"))
+    );
+    assert!(Atom::is_list(&optimized("(random-int 10)", &env)));
+    assert!(Atom::is_list(&optimized("(sqrt -1)", &env)));
+}
+
+#[test]
+fn optimize_keeps_names() {
+    let env = Env::default();
+    // builtins are called by name, so that the code can still be printed and saved
+    assert_eq!(
+        optimized("(lambda (x) (+ x (* 2 3)))", &env),
+        Rc::new(parse_one("(lambda (x) (+ x 6))"))
+    );
+    // the head of the form could be a macro which is not defined yet
+    assert_eq!(
+        optimized("(later (+ 1 2))", &env),
+        Rc::new(parse_one("(later (+ 1 2))"))
+    );
+    // parameters and local definitions shadow builtins
+    assert_eq!(
+        optimized("(lambda (+) (+ 1 2))", &env),
+        Rc::new(parse_one("(lambda (+) (+ 1 2))"))
+    );
+    assert_eq!(
+        optimized("(define (f x) (define car cdr) (car x))", &env),
+        Rc::new(parse_one("(define (f x) (define car cdr) (car x))"))
+    );
+}
+
+#[test]
+fn optimize_preserves_results() {
+    let src = "(define (f x . rest) (if (< x (* 2 3)) (+ x (abs -4)) (apply * rest)))
+               (f 1 0)
+               (f 10 2 3)";
+    let mut env = Env::default();
+    let mut results = Vec::new();
+    for atom in parse(src) {
+        let atom = crate::optimize::optimize(&Rc::new(atom), &env);
+        results.push(Atom::eval(atom, &mut env).unwrap());
+    }
+    assert_eq!(results[1], Atom::integer(5));
+    assert_eq!(results[2], Atom::integer(6));
+}
+
+#[test]
+fn memoize() {
+    helper(
+        "(define fib (memoize (lambda (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))))
+         (fib 70)",
+        "190392490709135",
+    );
+    helper(
+        "(define first (memoize (lambda (x) (car x))))
+         (first (quote (1 2)))
+         (first (quote (3 4)))",
+        "3",
+    );
+    helper("((memoize +) 1 2 3)", "6");
+
+    // NaN is not equal to itself, but finds its cached result
+    let cache = crate::atom::MemoCache::default();
+    let args = Atom::list_from_vec(vec![Atom::number(f64::NAN)]);
+    cache.insert(args, Atom::t());
+    let args = Atom::list_from_vec(vec![Atom::number(f64::NAN)]);
+    assert_eq!(cache.get(&args), Some(Atom::t()));
+    assert_eq!(
+        cache.get(&Atom::list_from_vec(vec![Atom::number(0.0)])),
+        None
+    );
+    run_has_error("(memoize 1)");
+    run_has_error("(memoize car cdr)");
+}
+
+#[test]
+fn env_symbol_names() {
+    let mut env = Env::default();
+    env.set("zzz-test", Atom::nil());
+    let mut child = Env::new(Some(Rc::new(env)));
+    child.set("car", Atom::nil());
+    let names = child.symbol_names();
+    assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(names.iter().any(|name| &**name == "define"));
+    assert_eq!(names.last().map(AsRef::as_ref), Some("zzz-test"));
+}
+
+#[test]
+fn apropos() {
+    helper(
+        "(define string-thing 1) (apropos \"ring-th\")",
+        "'(string-thing)",
+    );
+    let names = Atom::list_to_vec(&run_code("(apropos \"string\")")).unwrap();
+    assert!(names.contains(&Rc::new(Atom::symbol("string-append"))));
+    assert!(names.iter().all(|name| name.to_string().contains("string")));
+    helper("(apropos \"no such name\")", "nil");
+    run_has_error("(apropos 'string)");
+}
+
+#[test]
+fn documentation() {
+    let mut env = Env::default();
+    for atom in parse(
+        "(define (f x . rest) \"Do something with x.\" x)
+         (define (g) \"Only a string\")
+         (defmacro (m a) \"A macro.\" a)
+         (define mf (memoize f))
+         (define n 1)",
+    ) {
+        Atom::eval(Rc::new(atom), &mut env).unwrap();
+    }
+    let doc = |name: &str| env.documentation(name).unwrap();
+    assert_eq!(doc("f"), "(f x . rest)\nDo something with x.");
+    assert_eq!(doc("g"), "(g)\nNot documented.");
+    assert_eq!(doc("m"), "(m a)\nA macro.");
+    assert_eq!(doc("mf"), "(mf x . rest)\nDo something with x.");
+    assert_eq!(
+        doc("car"),
+        "(car pair)\nThe first element of pair, or pair itself if it is not a pair."
+    );
+    assert_eq!(doc("define"), "define is a special form.");
+    assert_eq!(doc("n"), "n is bound to a number: 1");
+    assert!(env.documentation("unbound-name").is_err());
+
+    // every builtin is documented, starting with how it is called
+    for name in env.symbol_names() {
+        if matches!(env.lookup(&name).as_deref(), Some(Atom::NativeFunc(_))) {
+            assert!(doc(&name).starts_with(&format!("({name}")), "{name}");
+        }
+    }
+
+    helper("(doc car)", "nil");
+    run_has_error("(doc unbound-name)");
+}
+
+#[test]
+fn session_round_trip() {
+    let mut env = Env::default();
+    let baseline = env.clone();
+    for atom in parse(
+        "(define (square x) \"Multiply x by itself.\" (* x x))
+         (defmacro (unless c . body) (cons 'if (cons c (cons nil body))))
+         (define first car)
+         (define data '(1 \"two\" :three (four . 5)))
+         (define fast-square (memoize square))
+         (define functions (cons square nil))",
+    ) {
+        Atom::eval(Rc::new(atom), &mut env).unwrap();
+    }
+    let src = session::save(&env, &baseline);
+    assert!(src.contains("(define first car)"));
+    assert!(src.contains("#| functions is bound to a list, which can not be saved |#"));
+
+    let mut restored = Env::default();
+    for atom in parse(&src) {
+        Atom::eval(Rc::new(atom), &mut restored).unwrap();
+    }
+    for (src, expected) in [
+        ("(square 3)", "9"),
+        ("(unless nil 1)", "1"),
+        ("(first '(1 2))", "1"),
+        ("data", "'(1 \"two\" :three (four . 5))"),
+        ("(fast-square 4)", "16"),
+    ] {
+        let result = Atom::eval(Rc::new(parse_one(src)), &mut restored).unwrap();
+        assert_eq!(result, run_code(expected), "{src}");
+    }
+    assert!(restored.lookup("functions").is_none());
+}
+
+#[test]
+fn gc() {
+    // the closures returned by m refer back to m and fib through the environment they were called from
+    let src = "(define m (memoize (lambda (x) (lambda () x))))
+               (define fib (memoize (lambda (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))))
+               (m 1)
+               (m 2)
+               (fib 10)
+               (gc)";
+    assert_eq!(run_code(src), Atom::integer(0));
+    let mut env = Env::default();
+    for atom in parse(src) {
+        Atom::eval(Rc::new(atom), &mut env).unwrap();
+    }
+    // the caches of another interpreter are left alone
+    assert_eq!(gc::collect(&Env::default()), 0);
+    // too few caches were filled for it to be worth collecting
+    gc::collect_if_due(&env);
+    for atom in parse("(define m nil) (define fib nil)") {
+        Atom::eval(Rc::new(atom), &mut env).unwrap();
+    }
+    assert_eq!(gc::collect(&env), 2);
+    assert_eq!(gc::collect(&env), 0);
+    helper(
+        "(define m (memoize (lambda (x) (lambda () x)))) ((m 1)) (gc) ((m 1))",
+        "1",
+    );
+}
+
+#[test]
+fn profiler() {
+    run_code(
+        "(define (double x) (* x 2))
+         (define (quadruple x) (double (double x)))
+         (profile-start)
+         (quadruple 1)
+         (quadruple 2)
+         (profile-stop)
+         (quadruple 3)",
+    );
+    let report = crate::profile::report();
+    let calls = |name: &str| {
+        report
+            .lines()
+            .find(|line| line.split_whitespace().next() == Some(name))
+            .and_then(|line| line.split_whitespace().nth(1))
+            .map(|calls| calls.parse::<usize>().unwrap())
+    };
+    assert_eq!(calls("quadruple"), Some(2));
+    assert_eq!(calls("double"), Some(4));
+    assert_eq!(calls("*"), Some(4));
+    assert_eq!(calls("profile-start"), None);
+    run_has_error("(profile-start 1)");
+
+    // restarting the profiler keeps the running calls, and memoized functions are profiled by name
+    run_code(
+        "(define square (memoize (lambda (x) (* x x))))
+         (define (restart) (profile-start) (square 2))
+         (profile-start)
+         (restart)
+         (profile-stop)",
+    );
+    let report = crate::profile::report();
+    let calls = |name: &str| {
+        report
+            .lines()
+            .find(|line| line.split_whitespace().next() == Some(name))
+            .and_then(|line| line.split_whitespace().nth(1))
+            .map(|calls| calls.parse::<usize>().unwrap())
+    };
+    assert_eq!(calls("restart"), Some(1));
+    assert_eq!(calls("square"), Some(1));
+    assert_eq!(calls("<anonymous>"), None);
+}
+
+#[test]
+fn cached_atoms() {
+    assert!(Rc::ptr_eq(&Atom::nil(), &Atom::nil()));
+    assert!(Rc::ptr_eq(&Atom::t(), &Atom::bool(true)));
+    assert!(Rc::ptr_eq(&Atom::integer(42), &Atom::number(42.0)));
+    assert!(Rc::ptr_eq(&run_code("(+ 40 2)"), &Atom::integer(42)));
+    assert!(!Rc::ptr_eq(
+        &Atom::integer(1 << 40),
+        &Atom::integer(1 << 40)
+    ));
+    assert_eq!(Atom::number(-0.0).to_string(), "-0");
+    assert_eq!(Atom::number(0.5).to_string(), "0.5");
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn find_library() {
+    use std::ffi::OsString;
+
+    let dir = temp_path("find-library");
+    drop(std::fs::remove_dir_all(&dir));
+    let config_dir = dir.join("config");
+    let other_dir = dir.join("other");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::create_dir_all(&other_dir).unwrap();
+    let file = dir.join("file.lisp");
+    std::fs::write(&file, "").unwrap();
+
+    assert_eq!(config::find_library(None, &[], Some(&config_dir)), None);
+    std::fs::write(config_dir.join("lib.lisp"), "").unwrap();
+    assert_eq!(
+        config::find_library(None, &[], Some(&config_dir)),
+        Some(config_dir.join("lib.lisp"))
+    );
+    // a directory without a library is skipped
+    let library_path = std::env::join_paths([&other_dir, &file]).unwrap();
+    assert_eq!(
+        config::find_library(Some(&library_path), &[], Some(&config_dir)),
+        Some(file.clone())
+    );
+    assert_eq!(
+        config::find_library(Some(&OsString::from(&other_dir)), &[], Some(&config_dir)),
+        Some(config_dir.join("lib.lisp"))
+    );
+
+    // configured paths come after the library path
+    assert_eq!(
+        config::find_library(None, &[other_dir, file.clone()], Some(&config_dir)),
+        Some(file.clone())
+    );
+    assert_eq!(
+        config::find_library(Some(&library_path), &[config_dir], None),
+        Some(file)
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn config_file() {
+    let dir = std::path::Path::new("project");
+    let src = "[format]\nwidth = 100\n[library]\npath = [\"lib.lisp\", \"/lisp\"]\n[repl]\nprompt = \"{n}> \"";
+    let config = config::Config::parse(src, dir).unwrap();
+    assert_eq!(
+        config,
+        config::Config {
+            width: Some(100),
+            library_path: vec![dir.join("lib.lisp"), "/lisp".into()],
+            prompt: Some("{n}> ".to_string()),
+            ..config::Config::default()
+        }
+    );
+
+    let user = config::Config::parse(
+        "format.width = 60\nformat.indent = 2\nformat.body-forms = { cond = 0 }\nrepl.color = false",
+        dir,
+    )
+    .unwrap();
+    let config = config.or(user);
+    assert_eq!(config.width, Some(100));
+    assert_eq!(config.indent, Some(2));
+    assert_eq!(config.color, Some(false));
+    assert_eq!(config.body_forms.get("cond"), Some(&0));
+
+    assert!(config::Config::parse("[format]\nwidth = -1", dir).is_err());
+    assert!(config::Config::parse("[repl]\ncolor = \"no\"", dir).is_err());
+    assert!(config::Config::parse("format.body-forms = { let = true }", dir).is_err());
+    assert!(config::Config::parse("[format", dir).is_err());
+
+    // only the configuration file of the user can choose the library
+    let dir = temp_path("config");
+    let (project, config_dir) = (dir.join("project"), dir.join("config"));
+    std::fs::create_dir_all(project.join("sub")).unwrap();
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        project.join(config::PROJECT_CONFIG_FILE_NAME),
+        "format.width = 100\nlibrary.path = [\"evil.lisp\"]",
+    )
+    .unwrap();
+    let loaded = config::Config::load_from(Some(&project.join("sub")), Some(&config_dir)).unwrap();
+    assert_eq!(loaded.width, Some(100));
+    assert!(loaded.library_path.is_empty());
+    std::fs::write(
+        config_dir.join(config::USER_CONFIG_FILE_NAME),
+        "library.path = [\"lib.lisp\"]",
+    )
+    .unwrap();
+    let loaded = config::Config::load_from(Some(&project), Some(&config_dir)).unwrap();
+    assert_eq!(loaded.library_path, [config_dir.join("lib.lisp")]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn lint_unbound_symbols() {
+    let src = "(define (f x) (+ x y (g x)))
+               (define (g a . rest) (define local 1) (list a rest local))
+               (defmacro (m a) a)
+               (m whatever)
+               (print 'quoted (lambda (q) (* q q)) (nope 1))";
+    let atoms = parse(src).into_iter().map(Rc::new).collect::<Vec<_>>();
+    let env = Env::default();
+    let unbound = lint::unbound_symbols(&atoms, &env)
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(unbound, ["y", "list", "nope"]);
+}
+
+#[test]
+fn json_syntax_tree() {
+    assert_eq!(json::string("a\"\\\n\u{1}"), r#""a\"\\\n\u0001""#);
+    assert_eq!(
+        json::object(&[("a", "1".to_string()), ("b", json::string("c"))]),
+        r#"{"a":1,"b":"c"}"#
+    );
+
+    let src = "(a :b . \"c\")";
+    let (atom, spans) = parser_with_spans().parse(src).unwrap().remove(0);
+    assert_eq!(
+        json::syntax_tree(&atom, &spans),
+        r#"{"type":"list","elements":[{"type":"symbol","value":"a","span":[1,2]},{"type":"keyword","value":"b","span":[3,5]}],"tail":{"type":"string","value":"c","span":[8,11]},"span":[0,12]}"#
+    );
+}
+
+#[test]
+fn interpreter() {
+    let mut interpreter = Interpreter::new();
+    assert!(interpreter.eval_str("(map car '((1)))").is_err());
+    interpreter.load_library().unwrap();
+    let results = interpreter
+        .eval_str("(define x 20) (map (lambda (y) (+ x y)) '(1 2))")
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[1], run_code("'(21 22)"));
+    assert_eq!(interpreter.env().lookup("x"), Some(Atom::number(20.0)));
+
+    interpreter.env_mut().set("y", Atom::number(1.0));
+    assert!(interpreter
+        .eval_str("(define z 2) (not-bound y) (define w 3)")
+        .is_err());
+    assert!(interpreter.env().lookup("z").is_some());
+    assert!(interpreter.env().lookup("w").is_none());
+    // nothing is evaluated if the source can not be parsed
+    let e = interpreter.eval_str("(define v 1) (").unwrap_err();
+    assert!(
+        e.to_string().contains("1:15: Unexpected end of input"),
+        "{e}"
+    );
+    assert!(interpreter.env().lookup("v").is_none());
+
+    let file = temp_path("interpreter.lisp");
+    std::fs::write(&file, "(+ y 1)").unwrap();
+    let results = interpreter.eval_file(&file).unwrap();
+    assert_eq!(results, [Atom::number(2.0)]);
+    std::fs::remove_file(&file).unwrap();
+    assert!(interpreter.eval_file(&file).is_err());
+}
+
+#[test]
+fn interpreter_fuel() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str("(define (loop n) (loop (+ n 1)))")
+        .unwrap();
+    interpreter.set_fuel(100);
+    let e = interpreter.eval_str("(loop 0)").unwrap_err();
+    assert!(crate::fuel::is_exhausted(&e), "{e:#}");
+    assert_eq!(interpreter.fuel(), Some(0));
+    assert!(interpreter.eval_str("(+ 1 2)").is_err());
+
+    // the fuel is shared between calls
+    interpreter.set_fuel(3);
+    assert_eq!(
+        interpreter.eval_str("(+ 1 2)").unwrap(),
+        [Atom::number(3.0)]
+    );
+    assert_eq!(interpreter.fuel(), Some(2));
+    interpreter.clear_fuel();
+    assert_eq!(
+        interpreter.eval_str("(+ 1 2)").unwrap(),
+        [Atom::number(3.0)]
+    );
+    assert_eq!(interpreter.fuel(), None);
+}
+
+#[test]
+fn interpreter_allocation_limit() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(
+            "(define (grow l) (grow (cons l l)))
+             (define (double s n) (if (= n 0) s (double (string-append s s) (- n 1))))",
+        )
+        .unwrap();
+    interpreter.set_allocation_limit(10_000);
+    let e = interpreter.eval_str("(grow nil)").unwrap_err();
+    assert!(crate::allocation::is_exceeded(&e), "{e:#}");
+    assert!(format!("{e:#}").contains("Allocation limit of 10000 bytes exceeded"));
+    // the count starts again at each call
+    assert!(interpreter.eval_str("(cons 1 2)").is_ok());
+    let e = interpreter.eval_str(r#"(double "a" 15)"#).unwrap_err();
+    assert!(crate::allocation::is_exceeded(&e), "{e:#}");
+    interpreter.clear_allocation_limit();
+    assert!(interpreter.eval_str(r#"(double "a" 15)"#).is_ok());
+}
+
+#[test]
+fn interpreter_interrupt() {
+    use std::time::Duration;
+
+    let mut interpreter = Interpreter::new();
+    let handle = interpreter.interrupt_handle();
+    // a clone has a flag of its own
+    interpreter.clone().interrupt_handle().interrupt();
+    assert!(!handle.is_interrupted());
+    let interrupter = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        handle.interrupt();
+    });
+    let e = interpreter
+        .eval_str(
+            "(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+             (define before 1) (fib 100) (define after 2)",
+        )
+        .unwrap_err();
+    interrupter.join().unwrap();
+    assert!(crate::interrupt::is_interrupted(&e), "{e:#}");
+    assert!(interpreter.env().lookup("before").is_some());
+    assert!(interpreter.env().lookup("after").is_none());
+    // the flag is cleared once the evaluation it stopped returns
+    assert!(!interpreter.interrupt_handle().is_interrupted());
+    assert!(interpreter.eval_str("(+ 1 2)").is_ok());
+}
+
+#[test]
+fn interpreter_timeout() {
+    use std::time::{Duration, Instant};
+
+    let mut interpreter = Interpreter::new();
+    let start = Instant::now();
+    let e = interpreter
+        .eval_with_timeout(
+            "(define before 1)
+             (define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+             (fib 100)
+             (define after 2)",
+            Duration::from_millis(50),
+        )
+        .unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(crate::interrupt::is_timed_out(&e), "{e:#}");
+    assert!(interpreter.env().lookup("before").is_some());
+    assert!(interpreter.env().lookup("after").is_none());
+    // the deadline only applies to that call
+    assert_eq!(
+        interpreter
+            .eval_with_timeout("(+ 1 2)", Duration::from_secs(10))
+            .unwrap(),
+        [Atom::number(3.0)]
+    );
+    assert_eq!(
+        interpreter
+            .eval_with_timeout("(fib 10)", Duration::MAX)
+            .unwrap(),
+        [Atom::number(55.0)]
+    );
+    assert!(interpreter.eval_str("(+ 1 2)").is_ok());
+}
+
+#[test]
+fn sandbox_capabilities() {
+    use crate::env::Capability;
+
+    let env = Env::with_capabilities(&Capability::SANDBOX);
+    for name in [
+        "slurp",
+        "spit",
+        "delete-file",
+        "shell",
+        "process-run",
+        "exit",
+        "setenv",
+    ] {
+        assert!(env.lookup(name).is_none(), "{name}");
+        assert!(env.documentation(name).is_err(), "{name}");
+    }
+    for name in ["println", "read-line", "car", "string-append", "define"] {
+        assert!(env.lookup(name).is_some(), "{name}");
+    }
+    // waiting only takes time, like computing
+    assert_eq!(Capability::of("sleep"), Some(Capability::Pure));
+    let env = Env::with_capabilities(&Capability::ALL);
+    assert_eq!(
+        env.symbol_names().len(),
+        Env::default().symbol_names().len()
+    );
+    // a builtin which is not classified would be left out of a sandbox even if it is harmless
+    let env = Env::default();
+    for name in env.symbol_names() {
+        let builtin = match env.lookup(&name).unwrap().as_ref() {
+            Atom::NativeFunc(_) => true,
+            Atom::Symbol(form) => *form == name,
+            _ => false,
+        };
+        assert!(!builtin || Capability::of(&name).is_some(), "{name}");
+    }
+    assert_eq!(Capability::of("doc"), Some(Capability::Io));
+    assert_eq!(Capability::of("bench"), Some(Capability::Io));
+    assert_eq!(Capability::of("not-a-builtin"), None);
+
+    let mut interpreter = Interpreter::with_capabilities(&[Capability::Pure]);
+    interpreter.load_library().unwrap();
+    assert!(interpreter.eval_str("(println 1)").is_err());
+    assert_eq!(
+        interpreter.eval_str("(map car '((1)))").unwrap(),
+        [run_code("'(1)")]
+    );
+}
+
+#[test]
+fn interpreter_output() {
+    use crate::output::{Buffer, Output};
+
+    let buffer = Buffer::default();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Output::new(buffer.clone()));
+    interpreter
+        .eval_str(r#"(print "a") (println 1) (print '(b "c"))"#)
+        .unwrap();
+    assert_eq!(buffer.contents(), "a1\n(b \"c\")");
+    assert_eq!(buffer.take(), "a1\n(b \"c\")");
+    interpreter.eval_str("(doc car)").unwrap();
+    assert!(buffer.take().starts_with("(car "));
+    // the output is only used while the interpreter evaluates
+    let mut env = Env::default();
+    Atom::eval(Rc::new(parse("(print 1)").remove(0)), &mut env).unwrap();
+    assert_eq!(buffer.contents(), "");
+}
+
+#[test]
+fn eval_observer() {
+    use crate::observer::EvalObserver;
+
+    #[derive(Default)]
+    struct Tracer {
+        depth: usize,
+        lines: Vec<String>,
+    }
+
+    impl EvalObserver for Tracer {
+        fn on_enter(&mut self, expr: &Atom) {
+            self.lines
+                .push(format!("{}> {expr}", " ".repeat(self.depth)));
+            self.depth += 1;
+        }
+
+        fn on_exit(&mut self, expr: &Atom, result: &crate::error::Result<Rc<Atom>>) {
+            self.depth -= 1;
+            let result = result
+                .as_ref()
+                .map_or_else(|_| "error".to_string(), ToString::to_string);
+            self.lines
+                .push(format!("{}< {expr} = {result}", " ".repeat(self.depth)));
+        }
+    }
+
+    let mut interpreter = Interpreter::new();
+    let (results, tracer) = interpreter.eval_observed("(+ 1 (* 2 3))", Tracer::default());
+    assert_eq!(results.unwrap(), [Atom::number(7.0)]);
+    assert_eq!(
+        tracer.lines,
+        [
+            "> (+ 1 (* 2 3))",
+            " > (* 2 3)",
+            " < (* 2 3) = 6",
+            "< (+ 1 (* 2 3)) = 7"
+        ]
+    );
+    let (results, tracer) = interpreter.eval_observed("(car (not-bound))", Tracer::default());
+    assert!(results.is_err());
+    assert_eq!(tracer.lines.last().unwrap(), "< (car (not-bound)) = error");
+    assert_eq!(tracer.depth, 0);
+}
+
+#[test]
+fn stateful_builtin() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let calls = Rc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let mut env = Env::default();
+    env.add_builtin("count", "(count)\nCount the calls.", move |_| {
+        let calls = counted.fetch_add(1, Ordering::Relaxed) + 1;
+        Ok(Atom::integer(i64::try_from(calls)?))
+    });
+    let src = "(count) (count) (count)";
+    let results = parse(src)
+        .into_iter()
+        .map(|atom| Atom::eval(Rc::new(atom), &mut env).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(results.last(), Some(&Atom::integer(3)));
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+    assert!(env.documentation("count").unwrap().starts_with("(count)"));
+}
+
+#[test]
+fn rust_conversions() {
+    use std::collections::HashMap;
+
+    assert_eq!(Atom::from(vec![1_i64, 2]), *run_code("'(1 2)"));
+    assert_eq!(Atom::from(Some("a")), Atom::string("a"));
+    assert_eq!(Atom::from(None::<bool>), *Atom::nil());
+    assert_eq!(Atom::from(vec![true, false]), *run_code("'(t nil)"));
+    let map = HashMap::from([("x".to_string(), 1.5)]);
+    assert_eq!(Atom::from(map.clone()), *run_code("'((\"x\" . 1.5))"));
+
+    assert_eq!(i64::try_from(&*run_code("(+ 1 2)")).unwrap(), 3);
+    assert!(i64::try_from(&*run_code("1.5")).is_err());
+    assert_eq!(<&str>::try_from(&*run_code("\"a\"")).unwrap(), "a");
+    assert!(String::try_from(&*run_code("'a")).is_err());
+    assert!(bool::try_from(&*run_code("0")).unwrap());
+    let numbers = Vec::<Option<f64>>::try_from(&*run_code("'(1 nil 3)")).unwrap();
+    assert_eq!(numbers, [Some(1.0), None, Some(3.0)]);
+    let e = Vec::<i64>::try_from(&*run_code("'(1 \"2\")")).unwrap_err();
+    assert_eq!(
+        format!("{e:#}"),
+        "In element 1: Expected a number, got \"2\""
+    );
+    assert!(Vec::<i64>::try_from(&*run_code("'(1 . 2)")).is_err());
+    assert_eq!(
+        HashMap::<String, f64>::try_from(&Atom::from(map.clone())).unwrap(),
+        map
+    );
+    assert!(HashMap::<String, f64>::try_from(&*run_code("nil"))
+        .unwrap()
+        .is_empty());
+    assert!(HashMap::<String, f64>::try_from(&*run_code("'((a . 1))")).is_err());
+}
+
+#[test]
+fn typed_builtin() {
+    #[allow(clippy::needless_pass_by_value)]
+    fn split(s: String) -> crate::error::Result<Vec<String>> {
+        if s.is_empty() {
+            Err(crate::lisp_error!(Value, "Nothing to split"))
+        } else {
+            Ok(s.split(',').map(String::from).collect())
+        }
+    }
+
+    let mut env = Env::default();
+    env.register_fn("hypot", "(hypot x y)", f64::hypot);
+    env.register_fn("split", "(split s)", split);
+    env.register_fn("answer", "(answer)", || 42_i64);
+    env.register_fn("maybe", "(maybe x)", |x: Option<i64>| x.map(|x| x + 1));
+    let mut eval = |src: &str| {
+        let atom = Rc::new(parse(src).remove(0));
+        Atom::eval(atom, &mut env).map_err(|e| format!("{e:#}"))
+    };
+
+    assert_eq!(eval("(hypot 3 4)"), Ok(Atom::number(5.0)));
+    assert_eq!(eval(r#"(split "a,b")"#), Ok(run_code(r#"'("a" "b")"#)));
+    assert_eq!(eval("(answer)"), Ok(Atom::number(42.0)));
+    assert_eq!(eval("(maybe nil)"), Ok(Atom::nil()));
+    assert_eq!(eval("(maybe 1)"), Ok(Atom::number(2.0)));
+    assert_eq!(
+        eval("(hypot 3)"),
+        Err("While evaluating builtin function (hypot 3): Builtin hypot expected exactly two arguments, got (3)".to_string())
+    );
+    assert_eq!(
+        eval("(answer 1)"),
+        Err("While evaluating builtin function (answer 1): Builtin answer expected no arguments, got (1)".to_string())
+    );
+    assert_eq!(
+        eval(r#"(hypot 3 "4")"#),
+        Err(r#"While evaluating builtin function (hypot 3 "4"): As second argument of hypot: Expected a number, got "4""#.to_string())
+    );
+    assert_eq!(
+        eval(r#"(split "")"#),
+        Err(r#"While evaluating builtin function (split ""): Nothing to split"#.to_string())
+    );
+}
+
+#[test]
+fn bench() {
+    // 20 timed runs, after 2 warmup runs
+    let src = "(define counter 0)
+               (define timings (bench (define counter (+ counter 1)) :iterations 20))
+               counter";
+    assert_eq!(run_code(src), Atom::number(22.0));
+    let timings = run_code("(bench (+ 1 2) :iterations 5)");
+    let timings = Atom::list_to_vec(&timings).unwrap();
+    assert_eq!(timings.len(), 3);
+    let [min, mean, max] = [0, 1, 2].map(|i| timings[i].get_number().unwrap());
+    assert!(min <= mean && mean <= max);
+    run_code("(bench (+ 1 2))");
+    run_has_error("(bench)");
+    run_has_error("(bench (+ 1 2) :iterations 0)");
+    run_has_error("(bench (+ 1 2) :iterations 1.5)");
+    run_has_error("(bench (+ 1 2) :repeat 5)");
+}
+
+#[cfg(feature = "arc")]
+#[test]
+fn thread_safe_atoms() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Atom>();
+    assert_send_sync::<Env>();
+
+    let mut env = Env::default();
+    for atom in parse("(define (square x) (* x x)) (define squares (memoize square))") {
+        Atom::eval(Rc::new(atom), &mut env).unwrap();
+    }
+    let expr = Rc::new(parse_one("(squares 12)"));
+    let handles = (0..4)
+        .map(|_| {
+            let mut env = env.clone();
+            let expr = expr.clone();
+            std::thread::spawn(move || Atom::eval(expr, &mut env).unwrap())
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), Atom::integer(144));
+    }
+}
+
+#[cfg(feature = "arc")]
+#[test]
+fn pmap() {
+    // helper is defined after square, so it is only found through the environment pmap was called from
+    let src = "(define (square x) (helper x))
+               (define (helper x) (* x x))
+               (pmap square (quote (1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17)))";
+    helper(
+        src,
+        "(quote (1 4 9 16 25 36 49 64 81 100 121 144 169 196 225 256 289))",
+    );
+    helper("(pmap (lambda (x) (* x 2)) nil)", "nil");
+    helper(
+        "(define y 0) (pmap (lambda (x) (define y x) y) (quote (1 2 3))) y",
+        "0",
+    );
+    run_has_error("(pmap (lambda (x) (undefined-function x)) (quote (1 2 3)))");
+    run_has_error("(pmap (lambda (x) x) 5)");
+    run_has_error("(pmap (lambda (x) x))");
+}
+
+#[cfg(feature = "datetime")]
+#[test]
+fn time_format_and_parse() {
+    helper(
+        r#"(time-format 0 "%Y-%m-%d %H:%M:%S")"#,
+        r#""1970-01-01 00:00:00""#,
+    );
+    helper(
+        r#"(time-format 1658000000.5 "%Y-%m-%dT%H:%M:%S%.3f")"#,
+        r#""2022-07-16T19:33:20.500""#,
+    );
+    helper(
+        r#"(time-parse "2022-07-16 19:33:20" "%Y-%m-%d %H:%M:%S")"#,
+        "1658000000",
+    );
+    helper(
+        r#"(time-parse "2022-07-16 21:33:20 +0200" "%Y-%m-%d %H:%M:%S %z")"#,
+        "1658000000",
+    );
+    helper(r#"(time-parse "2022-07-16" "%Y-%m-%d")"#, "1657929600");
+    helper(
+        r#"(time-parse (time-format 1658000000 "%d/%m/%Y %H:%M:%S") "%d/%m/%Y %H:%M:%S")"#,
+        "1658000000",
+    );
+    run_has_error(r#"(time-format 0 "%Q")"#);
+    run_has_error(r#"(time-parse "yesterday" "%Y-%m-%d")"#);
+    run_has_error(r#"(time-format "0" "%Y")"#);
+}
+
+// //// //// //// // MAKE-A-LISP TESTS // //// //// //// //
+
+fn run(src: &str) -> Atom {
+    run_code(src).as_ref().clone()
+}
+
+#[test]
+fn read_numbers() {
+    assert_eq!(run("1"), *Atom::integer(1));
+    assert_eq!(run("7"), *Atom::integer(7));
+    assert_eq!(run("   7"), *Atom::integer(7));
+    assert_eq!(run("-123"), *Atom::integer(-123));
+}
+
+#[test]
+fn read_numbers_with_underscores() {
+    assert_eq!(parse_one("1_000_000"), Atom::Number(1_000_000.0));
+    assert_eq!(parse_one("-1_000"), Atom::Number(-1000.0));
+    assert_eq!(parse_one("1.234_5"), Atom::Number(1.2345));
+    assert_eq!(parse_one("1_0e1_0"), Atom::Number(1e11));
+    assert_eq!(parse_one("_1"), Atom::symbol("_1"));
+    assert_eq!(parse_one("1_"), Atom::symbol("1_"));
+}
+
+#[test]
+fn read_symbol() {
+    assert_eq!(parse_one("+"), Atom::symbol("+"));
+    assert_eq!(parse_one("abc"), Atom::symbol("abc"));
+    assert_eq!(parse_one("   abc"), Atom::symbol("abc"));
+    assert_eq!(parse_one("abc5"), Atom::symbol("abc5"));
+    assert_eq!(parse_one("abc-def"), Atom::symbol("abc-def"));
+}
+
+#[test]
+fn read_symbol_starting_with_dash() {
+    assert_eq!(parse_one("-"), Atom::symbol("-"));
+    assert_eq!(parse_one("-abc"), Atom::symbol("-abc"));
+    assert_eq!(parse_one("->>"), Atom::symbol("->>"));
+}
+
+#[test]
+fn read_symbol_with_extended_characters() {
+    for name in [
+        "set!",
+        "vec->list",
+        "string-ref",
+        "foo.bar",
+        "1+",
+        "&rest",
+        "a#b",
+        "λ",
+        "café",
+        "1-2",
+    ] {
+        assert_eq!(parse_one(name), Atom::symbol(name));
+    }
+    assert_eq!(parse_one("1.5"), Atom::Number(1.5));
+    assert_eq!(parse_one("-1e3"), Atom::Number(-1000.0));
+    assert_eq!(
+        parse_one("(1 . 2)"),
+        Atom::cons(Atom::Number(1.0), Atom::Number(2.0))
+    );
+    assert_eq!(
+        parse_one("(foo.bar . baz)"),
+        Atom::cons(Atom::symbol("foo.bar"), Atom::symbol("baz"))
+    );
+    assert_eq!(
+        parse_one("(a#|comment|#b)"),
+        create_list(&[Atom::symbol("a"), Atom::symbol("b")])
+    );
+    assert_eq!(parse_one("a#;b"), Atom::symbol("a"));
+    parse_has_error(".a");
+    parse_has_error("#a");
+}
+
+#[test]
+fn read_keywords() {
+    assert_eq!(parse_one(":foo"), Atom::keyword("foo"));
+    assert_eq!(parse_one(":a.b"), Atom::keyword("a.b"));
+    assert_eq!(parse_one("a:b"), Atom::symbol("a:b"));
+    assert_eq!(format!("{}", parse_one("(f :x 1)")), "(f :x 1)");
+    parse_has_error(":");
+    parse_has_error("(f : 1)");
+}
+
+#[test]
+fn read_list() {
+    assert_eq!(
+        parse_one("(+ 1 2)"),
+        Atom::Pair(
+            Rc::new(Atom::symbol("+")),
+            Rc::new(Atom::Pair(
+                Atom::integer(1),
+                Rc::new(Atom::Pair(Atom::integer(2), Atom::nil()))
+            ))
+        )
+    );
+
+    assert_eq!(
+        parse_one("(+ 1 2)"),
+        create_list(&[Atom::symbol("+"), Atom::Number(1.0), Atom::Number(2.0)])
+    );
+
+    assert_eq!(parse_one("(nil)"), create_list(&[Atom::symbol("nil")]));
+
+    assert_eq!(
+        parse_one("((3 4))"),
+        create_list(&[create_list(&[Atom::Number(3.0), Atom::Number(4.0)])])
+    );
+
+    assert_eq!(
+        parse_one("(+ 1 (+ 2 3))"),
+        create_list(&[
+            Atom::symbol("+"),
+            Atom::Number(1.0),
+            create_list(&[Atom::symbol("+"), Atom::Number(2.0), Atom::Number(3.0)])
+        ])
+    );
+
+    assert_eq!(
+        parse_one("  ( +   1   (+   2 3   )   )  "),
+        create_list(&[
+            Atom::symbol("+"),
+            Atom::Number(1.0),
+            create_list(&[Atom::symbol("+"), Atom::Number(2.0), Atom::Number(3.0)])
+        ])
+    );
+
+    assert_eq!(
+        parse_one("(* 1 2)"),
+        create_list(&[Atom::symbol("*"), Atom::Number(1.0), Atom::Number(2.0)])
+    );
+
+    assert_eq!(
+        parse_one("(** 1 2)"),
+        create_list(&[Atom::symbol("**"), Atom::Number(1.0), Atom::Number(2.0)])
+    );
+
+    assert_eq!(
+        parse_one("(* -3 6)"),
+        create_list(&[Atom::symbol("*"), Atom::Number(-3.0), Atom::Number(6.0)])
+    );
+
+    assert_eq!(
+        parse_one("(() ())"),
+        create_list(&[Atom::symbol("nil"), Atom::symbol("nil")])
+    );
+}
+
+#[test]
+fn read_nil_true_false() {
+    assert_eq!(parse_one("nil"), Atom::symbol("nil"));
+    assert_eq!(parse_one("true"), Atom::symbol("true"));
+    assert_eq!(parse_one("false"), Atom::symbol("false"));
+}
+
+#[test]
+fn read_string() {
+    assert_eq!(parse_one("\"abc\""), Atom::string("abc"));
+    assert_eq!(parse_one("   \"abc\""), Atom::string("abc"));
+    assert_eq!(
+        parse_one("\"abc (with parens)\""),
+        Atom::string("abc (with parens)")
+    );
+    assert_eq!(parse_one(r#""abc\"def""#), Atom::string("abc\"def"));
+    assert_eq!(parse_one("\"\""), Atom::string(""));
+    assert_eq!(parse_one(r#""\\""#), Atom::string(r"\"));
+    assert_eq!(
+        parse_one(r#""\\\\\\\\\\\\\\\\\\""#),
+        Atom::string(r"\\\\\\\\\")
+    );
+}
+
+#[test]
+fn read_string_escapes() {
+    assert_eq!(parse_one(r#""\x41\x62""#), Atom::string("Ab"));
+    assert_eq!(parse_one(r#""a\0b""#), Atom::string("a\0b"));
+    assert_eq!(parse_one(r#""\u00e9""#), Atom::string("é"));
+    assert_eq!(parse_one(r#""\u{e9}""#), Atom::string("é"));
+    assert_eq!(parse_one(r#""\u{1F600}""#), Atom::string("\u{1F600}"));
+    parse_has_error(r#""\x4""#);
+    parse_has_error(r#""\u{}""#);
+    parse_has_error(r#""\u{1234567}""#);
+    parse_has_error(r#""\u{110000}""#);
+}
+
+#[test]
+fn print_string_escapes() {
+    for s in [
+        "tab\there",
+        "quote \" backslash \\",
+        "nul \0 bell \x07 \u{85}",
+        "\u{1F600} é'",
+    ] {
+        let printed = format!("{:?}", Atom::string(s));
+        assert_eq!(parse_one(&printed), Atom::string(s), "{printed}");
+    }
+    assert_eq!(format!("{:?}", Atom::string("a\x07'b")), r#""a\x07'b""#);
+    helper(r#"(into-string "\x1b[0m")"#, r#""\"\\x1b[0m\"""#);
+}
+
+#[test]
+fn read_single_char_string() {
+    fn single_char_string(s: &str) {
+        assert_eq!(parse_one(&format!("\"{s}\"")), Atom::string(s));
+    }
+
+    for c in "&-()*+,-/:;<=>?@[]^_`{}~!".chars() {
+        single_char_string(&c.to_string());
+    }
+}
+
+#[test]
+fn read_erronous_input() {
+    parse_has_error("(1 2");
+    parse_has_error("[1 2");
+    parse_has_error("\"abc");
+    parse_has_error("\\");
+    parse_has_error(r"\\\\\\\\\\\\\\\\\\\");
+    parse_has_error(r#"(1 \"abc"#);
+    parse_has_error(r#"(1 \"abc\""#);
+}
+
+#[test]
+fn read_block_comment() {
+    assert_eq!(parse_one("#| comment |# 1"), Atom::Number(1.0));
+    assert_eq!(parse_one("1 #| comment |#"), Atom::Number(1.0));
+    assert_eq!(
+        parse_one("(1 #| (2 \n 3) |# 4)"),
+        create_list(&[Atom::Number(1.0), Atom::Number(4.0)])
+    );
+    assert_eq!(
+        parse_one("(1#|2|#3)"),
+        create_list(&[Atom::Number(1.0), Atom::Number(3.0)])
+    );
+    assert_eq!(
+        parse_one("#| outer #| inner |# still outer |# 1"),
+        Atom::Number(1.0)
+    );
+    assert_eq!(parse("#| only a comment |#"), vec![]);
+    assert_eq!(parse_one("#| # | || ## |# 1"), Atom::Number(1.0));
+    assert_eq!(
+        parse_one("\"#| not a comment |#\""),
+        Atom::string("#| not a comment |#")
+    );
+    parse_has_error("#| unterminated");
+    parse_has_error("#| outer #| inner |# 1");
+    parse_has_error("1 |#");
+}
+
+#[test]
+fn read_datum_comment() {
+    assert_eq!(parse_one("#;1 2"), Atom::Number(2.0));
+    assert_eq!(
+        parse_one("(1 #;(2 3) 4)"),
+        create_list(&[Atom::Number(1.0), Atom::Number(4.0)])
+    );
+    assert_eq!(
+        parse_one("(1 #; \"two\")"),
+        create_list(&[Atom::Number(1.0)])
+    );
+    assert_eq!(parse_one("(#;1)"), Atom::symbol("nil"));
+    assert_eq!(parse_one("'#;1 2"), parse_one("'2"));
+    assert_eq!(parse_one("#; #; 1 2 3"), Atom::Number(3.0));
+    assert_eq!(parse_one("#; #| comment |# 1 2"), Atom::Number(2.0));
+    assert_eq!(parse("#;(1 2)"), vec![]);
+    parse_has_error("#;");
+    parse_has_error("(1 #;)");
+    helper("(+ 1 #;2 3)", "4");
+}
+
+#[test]
+fn read_spans() {
+    fn leaf(start: usize, end: usize) -> SpanTree {
+        SpanTree {
+            span: start..end,
+            elements: vec![],
+            comments: vec![],
+        }
+    }
+
+    let src = "(+ 1 'x)\n  (a . b) #| comment |# \"s\"";
+    let atoms = parser_with_spans().parse(src).unwrap();
+    let spans = atoms.iter().map(|(_, spans)| spans).collect::<Vec<_>>();
+    assert_eq!(
+        spans,
+        [
+            &SpanTree {
+                span: 0..8,
+                elements: vec![
+                    leaf(1, 2),
+                    leaf(3, 4),
+                    SpanTree {
+                        span: 5..7,
+                        elements: vec![leaf(5, 6), leaf(6, 7)],
+                        comments: vec![],
+                    }
+                ],
+                comments: vec![],
+            },
+            &SpanTree {
+                span: 11..18,
+                elements: vec![leaf(12, 13), leaf(16, 17)],
+                comments: vec![],
+            },
+            &leaf(33, 36),
+        ]
+    );
+
+    let mut source_map = SourceMap::default();
+    let (atom, spans) = &atoms[0];
+    let atom = Rc::new(atom.clone());
+    source_map.insert(&atom, spans);
+    assert_eq!(source_map.get(&atom), Some(0..8));
+    let quoted = atom.cdr().cdr().car();
+    assert_eq!(source_map.get(&quoted), Some(5..7));
+    assert_eq!(source_map.get(&quoted.cdr().car()), Some(6..7));
+    assert_eq!(source_map.get(&Atom::nil()), None);
+
+    let (atom, spans) = &atoms[1];
+    let atom = Rc::new(atom.clone());
+    source_map.insert(&atom, spans);
+    assert_eq!(source_map.get(&atom.cdr()), Some(16..17));
+
+    // the elements after the `.` are elements of the list
+    let (atom, spans) = &parser_with_spans().parse("(a . (b c))").unwrap()[0];
+    assert_eq!(spans.elements, [leaf(1, 2), leaf(6, 7), leaf(8, 9)]);
+    let atom = Rc::new(atom.clone());
+    source_map.insert(&atom, spans);
+    assert_eq!(source_map.get(&atom.cdr().cdr().car()), Some(8..9));
+
+    assert_eq!(line_column(src, 0), (1, 1));
+    assert_eq!(line_column(src, 11), (2, 3));
+
+    // spans count characters, which are sliced by their bytes
+    let src = "(é \"ü\" 1)";
+    let (_, spans) = &parser_with_spans().parse(src).unwrap()[0];
+    let text = SourceText::new(src);
+    assert_eq!(text.get(&spans.elements[1].span), "\"ü\"");
+    assert_eq!(text.get(&spans.span), src);
+}
+
+#[test]
+fn read_comments() {
+    let src = "#| a |# (f #;x 1 #| b |#)\n'#| c |# y #| d |#";
+    let commented = parser_with_comments().parse(src).unwrap();
+    let source = SourceText::new(src);
+    let text = |spans: &[std::ops::Range<usize>]| {
+        spans
+            .iter()
+            .map(|span| source.get(span))
+            .collect::<Vec<_>>()
+    };
+
+    let (before, atom, spans) = &commented.atoms[0];
+    assert_eq!(text(before), ["#| a |#"]);
+    assert_eq!(atom, &parser().parse("(f 1)").unwrap()[0]);
+    assert_eq!(text(&spans.comments), ["#;x", "#| b |#"]);
+    assert!(spans.has_comments());
+
+    let (before, _, spans) = &commented.atoms[1];
+    assert!(before.is_empty());
+    assert_eq!(text(&spans.comments), ["#| c |#"]);
+    assert_eq!(text(&commented.trailing_comments), ["#| d |#"]);
+
+    let (_, _, spans) = &parser_with_comments().parse("(f (g))").unwrap().atoms[0];
+    assert!(!spans.has_comments());
+}
+
+#[test]
+fn read_shebang() {
+    assert_eq!(
+        parse_one("#!/usr/bin/env lwhlisp\n(+ 1 2)"),
+        create_list(&[Atom::symbol("+"), Atom::Number(1.0), Atom::Number(2.0)])
+    );
+    assert_eq!(parse("#!/usr/bin/env lwhlisp"), vec![]);
+    let (_, spans) = &parser_with_spans().parse("#!lwhlisp\n1").unwrap()[0];
+    assert_eq!(spans.span, 10..11);
+    parse_has_error("1\n#!/usr/bin/env lwhlisp");
+}
+
+#[test]
+fn read_macros() {
+    let mut macros = ReaderMacros::default();
+    macros.insert("t", |_| Ok(Atom::symbol("t")));
+    macros.insert("rx", |pattern| match pattern {
+        Atom::String(_) => Ok(Atom::cons(
+            Atom::symbol("regex"),
+            Atom::cons(pattern, Atom::symbol("nil")),
+        )),
+        _ => Err(String::from("Expected a string after #rx")),
+    });
+    let parse = |src: &str| {
+        parser_with_reader_macros(macros.clone())
+            .parse(src)
+            .map(|atoms| atoms.into_iter().map(|(atom, _)| atom).collect::<Vec<_>>())
+    };
+
+    assert_eq!(parse("#t").unwrap(), [Atom::symbol("t")]);
+    assert_eq!(
+        parse("(#t #rx\"a+\")").unwrap(),
+        [create_list(&[
+            Atom::symbol("t"),
+            create_list(&[Atom::symbol("regex"), Atom::string("a+")])
+        ])]
+    );
+    assert!(parse("#rx(1)").is_err());
+    assert!(parse("#unknown").is_err());
+    assert!(parser().parse("#t").is_err());
+
+    // defined from lisp code
+    let mut env = Env::default();
+    let definition = parse_one(
+        "(define *reader-macros* (cons (cons \"double\" (lambda (x) (cons '* (cons 2 x)))) nil))",
+    );
+    Atom::eval(Rc::new(definition), &mut env).unwrap();
+    let atoms = parser_with_reader_macros(reader_macros(&env))
+        .parse("#double(21)")
+        .unwrap();
+    assert_eq!(
+        Atom::eval(Rc::new(atoms[0].0.clone()), &mut env).unwrap(),
+        Atom::integer(42)
+    );
+}
+
+#[test]
+fn read_incomplete() {
+    assert_eq!(
+        parse_or_incomplete("(+ 1 2) 3"),
+        Parsed::Complete(vec![
+            create_list(&[Atom::symbol("+"), Atom::Number(1.0), Atom::Number(2.0)]),
+            Atom::Number(3.0)
+        ])
+    );
+    assert_eq!(parse_or_incomplete(""), Parsed::Complete(vec![]));
+    for src in [
+        "(+ 1",
+        "(define (f x)\n  (if x",
+        "\"abc",
+        "(print \"abc",
+        "'",
+        "#| comment",
+        "(1 #;",
+        "(1 .",
+        "\"\\u{12",
+        // a list with errors can only be skipped once it is closed
+        "(1 . 2 3",
+    ] {
+        assert_eq!(parse_or_incomplete(src), Parsed::Incomplete, "{src}");
+    }
+    for src in [
+        "(+ 1))",
+        ")",
+        "(1 . 2 3)",
+        "(1 . )",
+        "\"\\q\"",
+        "#unknown",
+        "(#unknown",
+    ] {
+        assert!(
+            matches!(parse_or_incomplete(src), Parsed::Error(_)),
+            "{src}"
+        );
+    }
+}
+
+#[test]
+fn read_with_errors() {
+    fn parse_with_errors(src: &str) -> (Vec<Atom>, usize) {
+        let (atoms, errs) = parser().parse_recovery(src);
+        (atoms.unwrap(), errs.len())
+    }
+
+    assert_eq!(
+        parse_with_errors("(+ 1 2) (1 . 2 3) (+ 3 4)"),
+        (
+            vec![
+                create_list(&[Atom::symbol("+"), Atom::Number(1.0), Atom::Number(2.0)]),
+                create_list(&[Atom::symbol("+"), Atom::Number(3.0), Atom::Number(4.0)])
+            ],
+            1
+        )
+    );
+    assert_eq!(
+        parse_with_errors("(a (b . ) c) ) d (e (f .))"),
+        (vec![Atom::symbol("d")], 3)
+    );
+}
+
+#[test]
+fn read_quote() {
+    assert_eq!(
+        parse_one("'1"),
+        create_list(&[Atom::symbol("quote"), Atom::Number(1.0)])
+    );
+    assert_eq!(
+        parse_one("'(1 2 3)"),
+        create_list(&[
+            Atom::symbol("quote"),
+            create_list(&[Atom::Number(1.0), Atom::Number(2.0), Atom::Number(3.0)])
+        ])
+    );
+}
+
+#[test]
+fn read_quasiquote() {
+    assert_eq!(
+        parse_one("`1"),
+        create_list(&[Atom::symbol("quasiquote"), Atom::Number(1.0)])
+    );
+    assert_eq!(
+        parse_one("`(1 2 3)"),
+        create_list(&[
+            Atom::symbol("quasiquote"),
+            create_list(&[Atom::Number(1.0), Atom::Number(2.0), Atom::Number(3.0)])
+        ])
+    );
+}
+
+#[test]
+fn read_unquote() {
     assert_eq!(
         parse_one(",1"),
-        create_list(&[Atom::symbol("unquote"), Atom::integer(1)])
+        create_list(&[Atom::symbol("unquote"), Atom::Number(1.0)])
     );
     assert_eq!(
         parse_one(",(1 2 3)"),
         create_list(&[
             Atom::symbol("unquote"),
-            create_list(&[Atom::integer(1), Atom::integer(2), Atom::integer(3)])
+            create_list(&[Atom::Number(1.0), Atom::Number(2.0), Atom::Number(3.0)])
         ])
     );
 }
@@ -525,9 +2616,9 @@ fn read_unquote_quasiquote() {
         create_list(&[
             Atom::symbol("quasiquote"),
             create_list(&[
-                Atom::integer(1),
+                Atom::Number(1.0),
                 create_list(&[Atom::symbol("unquote"), Atom::symbol("a")]),
-                Atom::integer(3)
+                Atom::Number(3.0)
             ])
         ])
     );
@@ -539,7 +2630,7 @@ fn read_unquote_splicing() {
         parse_one(",@(1 2 3)"),
         create_list(&[
             Atom::symbol("unquote-splicing"),
-            create_list(&[Atom::integer(1), Atom::integer(2), Atom::integer(3)])
+            create_list(&[Atom::Number(1.0), Atom::Number(2.0), Atom::Number(3.0)])
         ])
     );
 }
@@ -627,3 +2718,346 @@ fn can_load_standard_library() {
     let src = include_str!("../../lib/lib.lisp");
     run_code(src);
 }
+
+#[cfg(feature = "toml")]
+#[test]
+fn toml_parse_and_serialize() {
+    helper(
+        r#"(toml-parse "name = \"lwhlisp\"
+version = 12
+ratio = 0.5
+enabled = true
+tags = [\"lisp\", \"rust\"]
+
+[owner]
+name = \"someone\"")"#,
+        r#"(quote (("enabled" . t)
+                  ("name" . "lwhlisp")
+                  ("owner" ("name" . "someone"))
+                  ("ratio" . 0.5)
+                  ("tags" "lisp" "rust")
+                  ("version" . 12)))"#,
+    );
+    helper(
+        r#"(toml-parse (toml-serialize (quote (("a" . 1) ("b" "x" "y") ("c" ("d" . t))))))"#,
+        r#"(quote (("a" . 1) ("b" "x" "y") ("c" ("d" . t))))"#,
+    );
+    run_has_error(r#"(toml-parse "a = ")"#);
+    run_has_error("(toml-serialize 1)");
+    run_has_error("(toml-serialize (quote ((1 . 2))))");
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn yaml_parse_and_serialize() {
+    helper(
+        r#"(yaml-parse "name: lwhlisp
+version: 12
+enabled: false
+nothing: null
+tags:
+  - lisp
+  - rust
+owner:
+  name: someone")"#,
+        r#"(quote (("name" . "lwhlisp")
+                  ("version" . 12)
+                  ("enabled")
+                  ("nothing")
+                  ("tags" "lisp" "rust")
+                  ("owner" ("name" . "someone"))))"#,
+    );
+    helper(r#"(yaml-parse "- 1\n- 2.5")"#, "(quote (1 2.5))");
+    helper(
+        r#"(yaml-parse (yaml-serialize (quote (("a" . 1) ("b" "x" "y") ("c" ("d" . t))))))"#,
+        r#"(quote (("a" . 1) ("b" "x" "y") ("c" ("d" . t))))"#,
+    );
+    helper("(yaml-serialize 1)", r#""1\n""#);
+    run_has_error(r#"(yaml-parse "a: [")"#);
+}
+
+#[cfg(all(feature = "serde", feature = "yaml"))]
+#[test]
+fn serde_round_trip() {
+    let atom = run_code(r#"'(1 2.5 "s" sym :key t nil (a . b) (("x" . 1) ("y" 2 3)))"#);
+    let yaml = serde_yaml::to_string(atom.as_ref()).unwrap();
+    let back: Atom = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(back, *atom, "{yaml}");
+
+    let atom: Atom =
+        serde_yaml::from_str("{name: x, tags: [a, {symbol: b}], on: true, off: false}").unwrap();
+    assert_eq!(
+        atom,
+        *run_code(r#"'(("name" . "x") ("tags" "a" b) ("on" . t) ("off"))"#)
+    );
+    // a map which is not a valid symbol stays a map
+    let atom: Atom = serde_yaml::from_str("{symbol: 1}").unwrap();
+    assert_eq!(atom, *run_code(r#"'(("symbol" . 1))"#));
+    assert!(serde_yaml::to_string(run_code("(lambda (x) x)").as_ref()).is_err());
+}
+
+#[test]
+fn lisp_error_kinds() {
+    use crate::error::ErrorKind;
+
+    let mut interpreter = Interpreter::new();
+    let kind =
+        |interpreter: &mut Interpreter, src: &str| interpreter.eval_str(src).unwrap_err().kind();
+    assert_eq!(kind(&mut interpreter, "(car 1 2)"), ErrorKind::Arity);
+    assert_eq!(kind(&mut interpreter, "((lambda (x) x))"), ErrorKind::Arity);
+    assert_eq!(kind(&mut interpreter, r#"(+ 1 "a")"#), ErrorKind::Type);
+    assert_eq!(kind(&mut interpreter, "(sqrt -1)"), ErrorKind::Value);
+    assert_eq!(kind(&mut interpreter, "(define)"), ErrorKind::Syntax);
+
+    // the kind and atom of the cause are kept through the context added by evaluation
+    let e = interpreter
+        .eval_str("(define (f) (+ 1 undefined-thing)) (f)")
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::UnboundSymbol);
+    assert_eq!(e.atom(), Some("undefined-thing"));
+    assert!(e.chain().count() > 1);
+    assert_eq!(
+        e.root_cause().message(),
+        "Symbol undefined-thing is not bound to any value."
+    );
+    assert!(
+        format!("{e:#}").ends_with(": Symbol undefined-thing is not bound to any value."),
+        "{e:#}"
+    );
+
+    let e = interpreter.eval_str("(+ 1 2) (+ 1").unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Parse);
+    assert_eq!(e.span().unwrap().start, 12);
+    // leading blank lines count, so that the line and column are those of the source
+    let e = interpreter.eval_str("\n\n  (+ 1 2)\n  (+ 1").unwrap_err();
+    assert_eq!(e.span().unwrap().start, 18);
+    assert!(
+        e.message().starts_with("Could not parse the source: 4:7: "),
+        "{e}"
+    );
+
+    // the chain survives the conversion to a report
+    #[cfg(feature = "eyre")]
+    {
+        let report = color_eyre::Report::from(e.clone());
+        assert_eq!(format!("{report:#}"), format!("{e:#}"));
+        let e = report.downcast_ref::<crate::error::LispError>().unwrap();
+        assert_eq!(e.kind(), ErrorKind::Parse);
+    }
+    // errors of other crates keep their kind and messages
+    let e = crate::error::LispError::from(std::fs::read("/nonexistent/file").unwrap_err());
+    assert_eq!(e.kind(), ErrorKind::Io);
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn wasm_playground() {
+    use crate::wasm::Playground;
+
+    let mut playground = Playground::with_library().unwrap();
+    assert_eq!(
+        playground
+            .eval("(define (square x) (* x x)) (println (square 3)) (square 4)")
+            .unwrap(),
+        "16"
+    );
+    assert_eq!(playground.take_output(), "9\n");
+    assert_eq!(playground.take_output(), "");
+    assert_eq!(playground.eval("").unwrap(), "");
+    // the playground has no files
+    assert!(playground.eval(r#"(slurp "Cargo.toml")"#).is_err());
+}
+
+#[test]
+fn env_snapshot() {
+    let mut interpreter = Interpreter::new();
+    interpreter.load_library().unwrap();
+    interpreter
+        .eval_str(
+            "(define (square x) (* x x))
+             (defmacro (unless-nil x body) (list 'if x body nil))
+             (define numbers '(1 2 3))
+             (define head car)
+             (define (make-adder n) (lambda (x) (+ x n)))
+             (define add5 (make-adder 5))
+             (define n 100)
+             (define (make-count-down) (define (down n) (if (= n 0) n (down (- n 1)))) down)
+             (define count-down (make-count-down))",
+        )
+        .unwrap();
+    // a local function calling itself is defined again inside the function returning it
+    assert!(interpreter
+        .env()
+        .snapshot()
+        .contains("((lambda nil (define (down n) (if (= n 0) n (down (- n 1)))) down))"));
+
+    let path = temp_path("env-snapshot.lisp");
+    interpreter.env().save(&path).unwrap();
+    let mut env = Env::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut eval = |src: &str| Atom::eval(parse(src).remove(0).into(), &mut env).unwrap();
+    assert_eq!(*eval("(map square numbers)"), *run_code("'(1 4 9)"));
+    assert_eq!(
+        *eval("(unless-nil (head numbers) 'yes)"),
+        Atom::symbol("yes")
+    );
+    // the library was saved along with the definitions
+    assert_eq!(*eval("(reverse numbers)"), *run_code("'(3 2 1)"));
+    // the names a function captured are saved with it
+    assert_eq!(eval("(add5 1)"), Atom::integer(6));
+    assert_eq!(eval("(count-down 3)"), Atom::integer(0));
+
+    let mut env = Env::default();
+    assert!(env.restore("(define x").is_err());
+    assert!(env.restore("(define x (undefined-function))").is_err());
+    assert!(Env::load(std::path::Path::new("/nonexistent/snapshot.lisp")).is_err());
+}
+
+#[test]
+fn namespaces() {
+    let mut interpreter = Interpreter::new();
+    interpreter.load_library().unwrap();
+    let mut eval = |src: &str| interpreter.eval_str(src).unwrap().pop().unwrap();
+    eval(
+        "(in-namespace util)
+         (define (reverse x) 'mine)
+         (define (flip x) (reverse x))
+         (define flipped (flip '(1 2)))
+         (in-namespace nil)",
+    );
+    assert_eq!(*eval("util:flipped"), Atom::symbol("mine"));
+    // outside of the namespace, its definitions are only found qualified
+    assert_eq!(*eval("(reverse '(1 2))"), *run_code("'(2 1)"));
+    assert_eq!(*eval("(util:reverse '(1 2))"), Atom::symbol("mine"));
+    assert_eq!(*eval("(util:flip '(1 2))"), Atom::symbol("mine"));
+    assert!(interpreter.eval_str("flip").is_err());
+    assert!(interpreter.env().lookup("util:flip").is_some());
+    assert_eq!(interpreter.env().namespace(), None);
+
+    // a snapshot defines the functions of the namespace in it again
+    let mut env = Env::default();
+    env.restore(&interpreter.env().snapshot()).unwrap();
+    let result = Atom::eval(parse_one("(util:flip '(1 2))").into(), &mut env).unwrap();
+    assert_eq!(*result, Atom::symbol("mine"));
+    assert_eq!(env.namespace(), None);
+    assert!(env.lookup("flip").is_none());
+
+    // names which the namespace does not define are looked up outside of it
+    assert_eq!(
+        interpreter
+            .eval_str("(in-namespace other) (length (reverse '(1 2)))")
+            .unwrap(),
+        [Rc::new(Atom::symbol("other")), Atom::number(2.0)]
+    );
+    assert_eq!(interpreter.env().namespace().as_deref(), Some("other"));
+    assert_eq!(interpreter.env().qualify(":key").as_ref(), ":key");
+    assert!(interpreter.eval_str("(in-namespace a:b)").is_err());
+    assert!(interpreter.eval_str("(in-namespace)").is_err());
+}
+
+#[test]
+fn env_introspection() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(interpreter.env().depth(), 1);
+    assert_eq!(Env::new(None).depth(), 0);
+    assert!(interpreter
+        .env()
+        .symbol_names()
+        .iter()
+        .any(|name| &**name == "car"));
+
+    interpreter.eval_str("(define y 2) (define x 1)").unwrap();
+    let mut bindings = |src: &str| {
+        let result = interpreter.eval_str(src).unwrap().pop().unwrap();
+        Atom::list_to_vec(&result)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+    };
+    let top_level = bindings("(env)");
+    assert!(top_level.iter().any(|binding| binding == "(x . 1)"));
+    // the builtins are bound around the definitions
+    assert!(top_level
+        .iter()
+        .any(|binding| binding.starts_with("(car . ")));
+    // in a function, the bindings of the call shadow the definitions around it
+    let call = bindings("((lambda (x b) (env)) 3 4)");
+    for binding in ["(b . 4)", "(x . 3)", "(y . 2)"] {
+        assert!(call.iter().any(|bound| bound == binding), "{binding}");
+    }
+    assert!(!call.iter().any(|bound| bound == "(x . 1)"));
+    assert!(call.is_sorted());
+    assert_eq!(interpreter.env().iter().count(), 2);
+    assert!(interpreter.eval_str("(env 1)").is_err());
+}
+
+#[test]
+fn require_modules() {
+    use crate::output::{Buffer, Output};
+
+    let dir = temp_path("require");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(
+        dir.join("lib").join("shapes.lisp"),
+        "(require 'squares) (println \"loading shapes\")
+         (define (area side) (square side))
+         (provide 'area)",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib").join("squares.lisp"),
+        "(define (square x) (* x x))",
+    )
+    .unwrap();
+    std::fs::write(dir.join("lib").join("loop.lisp"), "(require 'loop)").unwrap();
+    std::fs::write(dir.join("sides.lisp"), "(define side 3)").unwrap();
+    std::fs::write(
+        dir.join("main.lisp"),
+        "(require 'shapes) (require 'shapes) (require 'sides)",
+    )
+    .unwrap();
+
+    let output = Buffer::default();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Output::new(output.clone()));
+    interpreter.set_module_path(vec![dir.join("lib")]);
+    let results = interpreter.eval_file(dir.join("main.lisp")).unwrap();
+    assert_eq!(results[1].to_string(), "(area)");
+    // the module is only evaluated once
+    assert_eq!(output.take(), "loading shapes\n");
+    // sides was found next to main, and shapes on the search path
+    assert_eq!(
+        interpreter.eval_str("(area side)").unwrap(),
+        [Atom::number(9.0)]
+    );
+    // names which are not provided stay in the module
+    assert!(interpreter.env().lookup("square").is_none());
+    assert!(interpreter.eval_str("(require 'squares)").is_ok());
+    assert!(interpreter.env().lookup("square").is_some());
+
+    assert!(interpreter.eval_str("(require 'loop)").is_err());
+    // modules only see the builtins, not what the environment requiring them first defined
+    std::fs::write(
+        dir.join("lib").join("uses-side.lisp"),
+        "(define twice-side (* 2 side))",
+    )
+    .unwrap();
+    assert!(interpreter.eval_str("(require 'uses-side)").is_err());
+    // another interpreter has modules and a search path of its own
+    let mut other = Interpreter::new();
+    other.set_output(Output::new(output.clone()));
+    assert!(other.eval_str("(require 'shapes)").is_err());
+    other.set_module_path(vec![dir.join("lib")]);
+    other.eval_str("(require 'shapes)").unwrap();
+    assert_eq!(output.take(), "loading shapes\n");
+    interpreter.set_module_path(Vec::new());
+    assert!(interpreter.eval_str("(require 'no-such-module)").is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+
+    let sandbox = Interpreter::with_capabilities(&crate::env::Capability::SANDBOX);
+    assert!(sandbox.env().lookup("require").is_none());
+    assert!(sandbox.env().lookup("provide").is_some());
+}
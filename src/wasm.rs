@@ -0,0 +1,94 @@
+//! A JavaScript API for lwhlisp compiled to `wasm32-unknown-unknown`, so that it can run in a browser, like in a
+//! playground:
+//!
+//! ```js
+//! import init, { Playground } from "./pkg/lwhlisp.js";
+//!
+//! await init();
+//! const playground = new Playground();
+//! playground.evalStr('(println "hello") (+ 1 2)'); // "3"
+//! playground.takeOutput(); // "hello\n"
+//! ```
+//!
+//! A browser has no files, processes or stdin, so the playground only has the builtins of
+//! [`Capability::SANDBOX`], and what scripts print is kept for the page to show instead of going to stdout. There
+//! is no clock either, so the builtins reading it, like `current-time` and `bench`, are left out of builds for
+//! `wasm32-unknown-unknown`, and so is `sleep`, since there are no threads to put to sleep.
+
+use wasm_bindgen::prelude::*;
+
+use crate::env::Capability;
+use crate::error::Result;
+use crate::output::{Buffer, Output};
+use crate::Interpreter;
+
+/// An interpreter with the default library loaded, whose scripts print to memory.
+#[wasm_bindgen]
+pub struct Playground {
+    interpreter: Interpreter,
+    output: Buffer,
+}
+
+// wasm-bindgen can not export const functions
+#[allow(clippy::missing_const_for_fn)]
+#[wasm_bindgen]
+impl Playground {
+    /// Make a playground, loading the default library.
+    ///
+    /// # Errors
+    /// If the default library can not be evaluated.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<Playground, JsError> {
+        Self::with_library().map_err(|e| JsError::new(&format!("{e:#}")))
+    }
+
+    /// Evaluate each top-level s-expression of `src`, returning the result of the last one, printed like in the
+    /// REPL, or an empty string if there is none. Definitions are kept for later calls.
+    ///
+    /// # Errors
+    /// If `src` can not be parsed or evaluated, with the whole chain of the error as its message. What was printed
+    /// before the error is kept.
+    #[wasm_bindgen(js_name = evalStr)]
+    pub fn eval_str(&mut self, src: &str) -> Result<String, JsError> {
+        self.eval(src).map_err(|e| JsError::new(&format!("{e:#}")))
+    }
+
+    /// What scripts printed since the last call, which is forgotten.
+    #[wasm_bindgen(js_name = takeOutput)]
+    #[must_use]
+    pub fn take_output(&self) -> String {
+        self.output.take()
+    }
+
+    /// Limit evaluation to `fuel` more reductions, so that a script looping forever does not hang the page.
+    #[wasm_bindgen(js_name = setFuel)]
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.interpreter.set_fuel(fuel);
+    }
+
+    /// Let evaluation run without limit again.
+    #[wasm_bindgen(js_name = clearFuel)]
+    pub fn clear_fuel(&mut self) {
+        self.interpreter.clear_fuel();
+    }
+}
+
+impl Playground {
+    /// Make a playground, loading the default library.
+    pub(crate) fn with_library() -> Result<Self> {
+        let output = Buffer::default();
+        let mut interpreter = Interpreter::with_capabilities(&Capability::SANDBOX);
+        interpreter.set_output(Output::new(output.clone()));
+        interpreter.load_library()?;
+        Ok(Self {
+            interpreter,
+            output,
+        })
+    }
+
+    /// Evaluate `src`, returning the printed result of its last s-expression.
+    pub(crate) fn eval(&mut self, src: &str) -> Result<String> {
+        let results = self.interpreter.eval_str(src)?;
+        Ok(results.last().map(ToString::to_string).unwrap_or_default())
+    }
+}